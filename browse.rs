@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+
+use crate::library::SongId;
+use crate::{format_duration, Song};
+
+// Индекс библиотеки "исполнитель → альбом → треки". Песни в нём хранятся по id,
+// поэтому порядок library на индекс не влияет.
+#[derive(Debug, Default)]
+pub struct LibraryIndex {
+    artists: BTreeMap<String, ArtistEntry>, // ключ - имя в нижнем регистре
+}
+
+#[derive(Debug, Clone)]
+pub struct ArtistEntry {
+    pub name: String,
+    albums: BTreeMap<(u16, String), AlbumEntry>, // по году, затем по названию
+}
+
+#[derive(Debug, Clone)]
+pub struct AlbumEntry {
+    pub title: String,
+    pub year: u16,
+    pub tracks: Vec<TrackRef>, // отсортированы по диску и номеру трека
+    pub duration: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TrackRef {
+    pub disc: u32,
+    pub track: u32,
+    pub id: SongId,
+}
+
+fn album_title(song: &Song) -> &str {
+    if song.album.is_empty() { "Без альбома" } else { &song.album }
+}
+
+impl LibraryIndex {
+    pub fn build(library: &[Song]) -> Self {
+        let mut index = LibraryIndex::default();
+        for song in library {
+            index.add(song);
+        }
+        index
+    }
+
+    pub fn add(&mut self, song: &Song) {
+        let artist_name = song.album_artist_name();
+        let artist = self.artists.entry(artist_name.to_lowercase()).or_insert_with(|| ArtistEntry {
+            name: artist_name.to_string(),
+            albums: BTreeMap::new(),
+        });
+
+        let title = album_title(song);
+        let album = artist.albums.entry((song.year, title.to_lowercase())).or_insert_with(|| AlbumEntry {
+            title: title.to_string(),
+            year: song.year,
+            tracks: Vec::new(),
+            duration: 0,
+        });
+
+        let track = TrackRef { disc: song.disc, track: song.track, id: song.id };
+        if let Err(position) = album.tracks.binary_search(&track) {
+            album.tracks.insert(position, track);
+            album.duration += song.duration;
+        }
+    }
+
+    // Пустые альбомы и исполнители удаляются вместе с последней песней
+    pub fn remove(&mut self, song: &Song) {
+        let artist_key = song.album_artist_name().to_lowercase();
+        let Some(artist) = self.artists.get_mut(&artist_key) else {
+            return;
+        };
+
+        let album_key = (song.year, album_title(song).to_lowercase());
+        if let Some(album) = artist.albums.get_mut(&album_key) {
+            let track = TrackRef { disc: song.disc, track: song.track, id: song.id };
+            if let Ok(position) = album.tracks.binary_search(&track) {
+                album.tracks.remove(position);
+                album.duration = album.duration.saturating_sub(song.duration);
+            }
+            if album.tracks.is_empty() {
+                artist.albums.remove(&album_key);
+            }
+        }
+
+        if artist.albums.is_empty() {
+            self.artists.remove(&artist_key);
+        }
+    }
+
+    pub fn artists(&self) -> Vec<&ArtistEntry> {
+        self.artists.values().collect()
+    }
+}
+
+impl ArtistEntry {
+    pub fn albums(&self) -> Vec<&AlbumEntry> {
+        self.albums.values().collect()
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.albums.values().map(|album| album.tracks.len()).sum()
+    }
+
+    pub fn duration(&self) -> u32 {
+        self.albums.values().map(|album| album.duration).sum()
+    }
+
+    pub fn ids(&self) -> Vec<SongId> {
+        self.albums.values().flat_map(|album| album.ids()).collect()
+    }
+
+    pub fn display_info(&self) -> String {
+        format!("🎤 {} ({} альб., {} треков, {})",
+                self.name, self.albums.len(), self.track_count(), format_duration(self.duration()))
+    }
+}
+
+impl AlbumEntry {
+    pub fn ids(&self) -> Vec<SongId> {
+        self.tracks.iter().map(|track| track.id).collect()
+    }
+
+    pub fn display_info(&self) -> String {
+        if self.year > 0 {
+            format!("💿 {} ({}, {} треков, {})", self.title, self.year, self.tracks.len(), format_duration(self.duration))
+        } else {
+            format!("💿 {} ({} треков, {})", self.title, self.tracks.len(), format_duration(self.duration))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: SongId, artist: &str, album_artist: &str, album: &str, year: u16, disc: u32, track: u32) -> Song {
+        let mut song = Song::new(format!("Трек {}", id), artist.to_string(), 100, String::new(), year, format!("{}.wav", id))
+            .with_album(album, track);
+        song.id = id;
+        song.album_artist = album_artist.to_string();
+        song.disc = disc;
+        song
+    }
+
+    fn names(index: &LibraryIndex) -> Vec<&str> {
+        index.artists().iter().map(|artist| artist.name.as_str()).collect()
+    }
+
+    fn albums(artist: &ArtistEntry) -> Vec<(u16, &str)> {
+        artist.albums().iter().map(|album| (album.year, album.title.as_str())).collect()
+    }
+
+    #[test]
+    fn groups_by_album_artist_then_year_and_title() {
+        let library = [
+            song(1, "Queen", "", "Innuendo", 1991, 0, 1),
+            song(2, "Freddie Mercury", "Queen", "A Night at the Opera", 1975, 0, 11),
+            song(3, "queen", "", "a night at the opera", 1975, 0, 1),
+            song(4, "Brian May", "Queen", "Innuendo", 1991, 0, 2),
+            song(5, "Abba", "", "Arrival", 1976, 0, 1),
+            song(6, "Queen", "", "Greatest Hits", 1975, 0, 3),
+        ];
+        let index = LibraryIndex::build(&library);
+
+        // Регистр имени и названия не делит группу; имя берётся у первой песни
+        assert_eq!(names(&index), ["Abba", "Queen"]);
+        let queen = index.artists()[1];
+        assert_eq!(albums(queen), [(1975, "A Night at the Opera"), (1975, "Greatest Hits"), (1991, "Innuendo")]);
+        assert_eq!(queen.albums()[0].ids(), [3, 2]);
+        assert_eq!(queen.albums()[2].ids(), [1, 4]);
+        assert_eq!((queen.track_count(), queen.duration()), (5, 500));
+        assert_eq!(queen.ids(), [3, 2, 6, 1, 4]);
+    }
+
+    #[test]
+    fn empty_tags_get_their_own_groups() {
+        let library = [
+            song(1, "", "", "", 0, 0, 0),
+            song(2, "", "", "", 0, 0, 0),
+            song(3, "Artist", "", "", 0, 0, 0),
+            song(4, "Artist", "", "Album", 0, 0, 0),
+        ];
+        let index = LibraryIndex::build(&library);
+
+        assert_eq!(names(&index), ["", "Artist"]);
+        let unknown = index.artists()[0];
+        assert_eq!(albums(unknown), [(0, "Без альбома")]);
+        // Без номеров треки идут в порядке id
+        assert_eq!(unknown.ids(), [1, 2]);
+        assert_eq!(albums(index.artists()[1]), [(0, "Album"), (0, "Без альбома")]);
+        assert_eq!(index.artists()[1].albums()[1].display_info(), "💿 Без альбома (1 треков, 01:40)");
+    }
+
+    #[test]
+    fn tracks_sort_by_disc_then_number() {
+        let library = [
+            song(1, "A", "", "Box", 2000, 2, 1),
+            song(2, "A", "", "Box", 2000, 1, 2),
+            song(3, "A", "", "Box", 2000, 1, 1),
+            song(4, "A", "", "Box", 2000, 0, 5),
+        ];
+        let index = LibraryIndex::build(&library);
+        assert_eq!(index.artists()[0].ids(), [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn remove_drops_empty_albums_and_artists() {
+        let library = [
+            song(1, "A", "", "One", 2000, 0, 1),
+            song(2, "A", "", "Two", 2001, 0, 1),
+            song(3, "B", "", "Three", 2002, 0, 1),
+        ];
+        let mut index = LibraryIndex::build(&library);
+        // Повторное добавление не дублирует трек
+        index.add(&library[0]);
+        assert_eq!(index.artists()[0].track_count(), 2);
+
+        index.remove(&library[0]);
+        assert_eq!(albums(index.artists()[0]), [(2001, "Two")]);
+        assert_eq!(index.artists()[0].duration(), 100);
+
+        index.remove(&library[2]);
+        assert_eq!(names(&index), ["A"]);
+        // Песни, которой нет в индексе, удаление не касается
+        index.remove(&library[2]);
+        index.remove(&song(9, "C", "", "", 0, 0, 0));
+        assert_eq!(names(&index), ["A"]);
+    }
+}
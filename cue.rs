@@ -0,0 +1,281 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Segment, Song};
+
+// В CUE позиция задаётся как мм:сс:кк, где кк - кадры CD (75 в секунду)
+const FRAMES_PER_SECOND: u64 = 75;
+
+#[derive(Debug, Default)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u16>,
+    pub files: Vec<CueFile>,
+}
+
+#[derive(Debug)]
+pub struct CueFile {
+    pub path: PathBuf, // уже разрешён относительно папки с CUE
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub start_ms: Option<u64>, // None - в треке нет INDEX 01
+}
+
+// Многие CUE созданы под Windows в кодировке CP1251; если текст не UTF-8,
+// считаем, что он в ней
+pub fn decode(data: &[u8]) -> String {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => data.iter().map(|&b| cp1251_char(b)).collect(),
+    }
+}
+
+fn cp1251_char(byte: u8) -> char {
+    const HIGH: [u16; 64] = [
+        0x0402, 0x0403, 0x201A, 0x0453, 0x201E, 0x2026, 0x2020, 0x2021, 0x20AC, 0x2030, 0x0409, 0x2039, 0x040A, 0x040C, 0x040B, 0x040F,
+        0x0452, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0xFFFD, 0x2122, 0x0459, 0x203A, 0x045A, 0x045C, 0x045B, 0x045F,
+        0x00A0, 0x040E, 0x045E, 0x0408, 0x00A4, 0x0490, 0x00A6, 0x00A7, 0x0401, 0x00A9, 0x0404, 0x00AB, 0x00AC, 0x00AD, 0x00AE, 0x0407,
+        0x00B0, 0x00B1, 0x0406, 0x0456, 0x0491, 0x00B5, 0x00B6, 0x00B7, 0x0451, 0x2116, 0x0454, 0x00BB, 0x0458, 0x0405, 0x0455, 0x0457,
+    ];
+    match byte {
+        0x00..=0x7F => byte as char,
+        0x80..=0xBF => char::from_u32(HIGH[(byte - 0x80) as usize] as u32).unwrap_or('\u{FFFD}'),
+        // 0xC0..0xFF - подряд А..я
+        _ => char::from_u32(0x0410 + (byte - 0xC0) as u32).unwrap_or('\u{FFFD}'),
+    }
+}
+
+pub fn parse(text: &str, cue_dir: &Path) -> Result<CueSheet, String> {
+    let mut sheet = CueSheet::default();
+
+    for (number, line) in text.lines().enumerate() {
+        let line_error = |reason: &str| format!("строка {}: {}", number + 1, reason);
+        let (command, rest) = split_word(line.trim());
+
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                let (name, _) = split_value(rest);
+                if name.is_empty() {
+                    return Err(line_error("FILE без имени файла"));
+                }
+                sheet.files.push(CueFile { path: cue_dir.join(name), tracks: Vec::new() });
+            }
+            "TRACK" => {
+                let file = sheet.files.last_mut().ok_or_else(|| line_error("TRACK до первого FILE"))?;
+                let (track_number, kind) = split_word(rest);
+                let track_number = track_number.parse().map_err(|_| line_error("некорректный номер трека"))?;
+                // Дорожки с данными на CD-Extra не являются музыкой
+                if !kind.eq_ignore_ascii_case("AUDIO") {
+                    continue;
+                }
+                file.tracks.push(CueTrack { number: track_number, title: None, performer: None, start_ms: None });
+            }
+            "INDEX" => {
+                let (index, position) = split_word(rest);
+                if index.parse::<u32>() != Ok(1) {
+                    continue;
+                }
+                let start = parse_position(position).ok_or_else(|| line_error("некорректная позиция INDEX"))?;
+                if let Some(track) = sheet.files.last_mut().and_then(|file| file.tracks.last_mut()) {
+                    track.start_ms = Some(start);
+                }
+            }
+            "TITLE" | "PERFORMER" => {
+                let (value, _) = split_value(rest);
+                let value = Some(value.to_string()).filter(|value| !value.is_empty());
+                let is_title = command.eq_ignore_ascii_case("TITLE");
+                // До первого TRACK поля относятся ко всему альбому
+                match sheet.files.last_mut().and_then(|file| file.tracks.last_mut()) {
+                    Some(track) if is_title => track.title = value,
+                    Some(track) => track.performer = value,
+                    None if is_title => sheet.title = value,
+                    None => sheet.performer = value,
+                }
+            }
+            "REM" => {
+                let (key, value) = split_word(rest);
+                let (value, _) = split_value(value);
+                match key.to_ascii_uppercase().as_str() {
+                    "GENRE" if !value.is_empty() => sheet.genre = Some(value.to_string()),
+                    "DATE" => sheet.year = value.get(0..4).and_then(|year| year.parse().ok()),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if sheet.files.iter().all(|file| file.tracks.is_empty()) {
+        return Err("в CUE нет ни одного аудиотрека".to_string());
+    }
+    Ok(sheet)
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (text, ""),
+    }
+}
+
+// Значение в кавычках или до первого пробела
+fn split_value(text: &str) -> (&str, &str) {
+    if let Some(quoted) = text.strip_prefix('"') {
+        match quoted.split_once('"') {
+            Some((value, rest)) => (value, rest.trim_start()),
+            None => (quoted, ""),
+        }
+    } else {
+        split_word(text)
+    }
+}
+
+fn parse_position(text: &str) -> Option<u64> {
+    let mut parts = text.trim().split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return None;
+    }
+    Some((minutes * 60 + seconds) * 1000 + frames * 1000 / FRAMES_PER_SECOND)
+}
+
+// Разбивает песню, прочитанную из целого файла, на виртуальные треки.
+// Трек длится до начала следующего, последний - до конца файла.
+pub fn split(sheet: &CueSheet, file: &CueFile, whole: &Song) -> Vec<Song> {
+    let tracks: Vec<(&CueTrack, u64)> = file.tracks.iter()
+        .filter_map(|track| track.start_ms.map(|start| (track, start)))
+        .collect();
+    let file_end = whole.duration as u64 * 1000;
+
+    tracks.iter().enumerate().map(|(i, &(track, start_ms))| {
+        let end_ms = tracks.get(i + 1).map(|&(_, next)| next);
+        let length = end_ms.unwrap_or(file_end).saturating_sub(start_ms);
+
+        let mut song = whole.clone();
+        song.title = track.title.clone().unwrap_or_else(|| format!("Трек {:02}", track.number));
+        if let Some(performer) = track.performer.as_ref().or(sheet.performer.as_ref()) {
+            song.artist = performer.clone();
+        }
+        if let Some(album_artist) = &sheet.performer {
+            song.album_artist = album_artist.clone();
+        }
+        if let Some(album) = &sheet.title {
+            song.album = album.clone();
+        }
+        if let Some(genre) = &sheet.genre {
+            song.genre = genre.clone();
+        }
+        if let Some(year) = sheet.year {
+            song.year = year;
+        }
+        song.track = track.number;
+        song.duration = ((length + 500) / 1000) as u32;
+        // ID записи относится ко всему файлу, а не к отдельному треку
+        song.mbid = None;
+        song.segment = Some(Segment { start_ms, end_ms });
+        song
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "\u{feff}REM GENRE \"Post-Punk\"
+REM DATE 1988
+PERFORMER \"Кино\"
+TITLE \"Группа крови\"
+FILE \"Kino - Gruppa krovi.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Группа крови\"
+    INDEX 00 00:00:00
+    INDEX 01 00:00:33
+  TRACK 02 AUDIO
+    TITLE \"Закрой за мной дверь\"
+    PERFORMER \"Виктор Цой\"
+    INDEX 01 04:45:00
+  TRACK 03 AUDIO
+    INDEX 01 08:59:74
+";
+
+    #[test]
+    fn parses_album_and_track_fields() {
+        let sheet = parse(&decode(SHEET.as_bytes()), Path::new("/music")).unwrap();
+        assert_eq!(sheet.title.as_deref(), Some("Группа крови"));
+        assert_eq!(sheet.performer.as_deref(), Some("Кино"));
+        assert_eq!((sheet.genre.as_deref(), sheet.year), (Some("Post-Punk"), Some(1988)));
+
+        let [file] = &sheet.files[..] else { panic!("{:?}", sheet.files) };
+        assert_eq!(file.path, Path::new("/music/Kino - Gruppa krovi.flac"));
+        let starts: Vec<Option<u64>> = file.tracks.iter().map(|track| track.start_ms).collect();
+        assert_eq!(starts, [Some(440), Some(285_000), Some(539_986)]);
+        assert_eq!(file.tracks[1].performer.as_deref(), Some("Виктор Цой"));
+        assert_eq!(file.tracks[2].title, None);
+    }
+
+    #[test]
+    fn split_makes_virtual_tracks() {
+        let sheet = parse(SHEET, Path::new("/music")).unwrap();
+        let mut whole = Song::new("whole".to_string(), "?".to_string(), 600, String::new(), 0, "/music/Kino - Gruppa krovi.flac".to_string());
+        whole.mbid = Some("mbid".to_string());
+
+        let songs = split(&sheet, &sheet.files[0], &whole);
+        let summary: Vec<(&str, &str, u32, u32)> = songs.iter()
+            .map(|song| (song.title.as_str(), song.artist.as_str(), song.track, song.duration))
+            .collect();
+        assert_eq!(summary, [
+            ("Группа крови", "Кино", 1, 285),
+            ("Закрой за мной дверь", "Виктор Цой", 2, 255),
+            ("Трек 03", "Кино", 3, 60),
+        ]);
+        assert!(songs.iter().all(|song| song.album == "Группа крови" && song.album_artist == "Кино" && song.mbid.is_none()));
+        assert_eq!(songs[0].segment, Some(Segment { start_ms: 440, end_ms: Some(285_000) }));
+        assert_eq!(songs[2].segment, Some(Segment { start_ms: 539_986, end_ms: None }));
+    }
+
+    #[test]
+    fn data_tracks_and_tracks_without_index_are_skipped() {
+        let text = "FILE a.wav WAVE\nTRACK 1 MODE1/2352\nINDEX 01 00:00:00\nTRACK 2 AUDIO\nTRACK 3 AUDIO\nINDEX 01 01:00:00\n";
+        let sheet = parse(text, Path::new("")).unwrap();
+        let numbers: Vec<u32> = sheet.files[0].tracks.iter().map(|track| track.number).collect();
+        assert_eq!(numbers, [2, 3]);
+
+        let whole = Song::new(String::new(), String::new(), 120, String::new(), 0, "a.wav".to_string());
+        let songs = split(&sheet, &sheet.files[0], &whole);
+        assert_eq!(songs.len(), 1);
+        assert_eq!((songs[0].track, songs[0].duration), (3, 60));
+    }
+
+    #[test]
+    fn cp1251_is_decoded() {
+        // "TITLE "Кино"" в CP1251
+        let bytes = [b"TITLE \"".as_slice(), &[0xCA, 0xE8, 0xED, 0xEE], b"\" \xB8"].concat();
+        assert_eq!(decode(&bytes), "TITLE \"Кино\" ё");
+        assert_eq!(decode("ёлка".as_bytes()), "ёлка");
+    }
+
+    #[test]
+    fn malformed_sheets_are_errors() {
+        let error = |text: &str| parse(text, Path::new("")).unwrap_err();
+        assert_eq!(error("TRACK 01 AUDIO\n"), "строка 1: TRACK до первого FILE");
+        assert_eq!(error("FILE\n"), "строка 1: FILE без имени файла");
+        assert_eq!(error("FILE a.wav WAVE\nTRACK x AUDIO\n"), "строка 2: некорректный номер трека");
+        assert_eq!(error("FILE a.wav WAVE\nTRACK 1 AUDIO\nINDEX 01 00:61:00\n"), "строка 3: некорректная позиция INDEX");
+        assert_eq!(error("FILE a.wav WAVE\nTRACK 1 AUDIO\nINDEX 01 00:00:75\n"), "строка 3: некорректная позиция INDEX");
+        assert_eq!(error("FILE a.wav WAVE\nTRACK 1 AUDIO\nINDEX 01 1:2:3:4\n"), "строка 3: некорректная позиция INDEX");
+        assert_eq!(error(""), "в CUE нет ни одного аудиотрека");
+        assert_eq!(error("FILE a.bin BINARY\nTRACK 1 MODE1/2352\n"), "в CUE нет ни одного аудиотрека");
+        // Незакрытая кавычка - значение до конца строки
+        let sheet = parse("FILE \"a b.wav\nTRACK 1 AUDIO\nTITLE \"Open\n", Path::new("")).unwrap();
+        assert_eq!(sheet.files[0].path, Path::new("a b.wav"));
+        assert_eq!(sheet.files[0].tracks[0].title.as_deref(), Some("Open"));
+    }
+}
@@ -0,0 +1,215 @@
+mod wav;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::scanner::AudioFormat;
+use crate::Song;
+
+pub use wav::WavDecoder;
+
+// Параметры потока PCM на выходе декодера
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub total_frames: Option<u64>, // None - длина заранее неизвестна
+}
+
+impl StreamInfo {
+    pub fn frames_to_ms(&self, frames: u64) -> u64 {
+        frames * 1000 / self.sample_rate as u64
+    }
+
+    pub fn ms_to_frames(&self, ms: u64) -> u64 {
+        ms * self.sample_rate as u64 / 1000
+    }
+}
+
+// Декодер выдаёт чередующиеся по каналам сэмплы f32 в диапазоне [-1.0, 1.0].
+// Кадр - по одному сэмплу на каждый канал.
+pub trait Decoder: Send {
+    fn info(&self) -> StreamInfo;
+
+    // Заполняет начало buf целыми кадрами и возвращает число записанных сэмплов; 0 - конец потока
+    fn read(&mut self, buf: &mut [f32]) -> io::Result<usize>;
+
+    // Переходит к кадру с указанным номером от начала потока
+    fn seek(&mut self, frame: u64) -> io::Result<()>;
+}
+
+// Слоты декодеров: чтобы подключить новый формат, достаточно добавить строку сюда
+type Opener = fn(File) -> io::Result<Box<dyn Decoder>>;
+const BACKENDS: &[(AudioFormat, Opener)] = &[
+    (AudioFormat::Wav, |file| Ok(Box::new(WavDecoder::new(file)?))),
+];
+
+pub fn open(path: &Path) -> io::Result<Box<dyn Decoder>> {
+    let mut header = Vec::with_capacity(12);
+    File::open(path)?.take(12).read_to_end(&mut header)?;
+    let format = AudioFormat::sniff(&header)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "не похоже на аудиофайл"))?;
+
+    match BACKENDS.iter().find(|(backend, _)| *backend == format) {
+        Some((_, opener)) => opener(File::open(path)?),
+        None => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("декодирование {:?} пока не поддерживается", format),
+        )),
+    }
+}
+
+// Открывает песню; для трека из CUE поток ограничен его границами и начинается с нуля
+pub fn open_song(song: &Song) -> io::Result<Box<dyn Decoder>> {
+    let decoder = open(Path::new(&song.path))?;
+    match song.segment {
+        Some(segment) => Ok(Box::new(Trimmed::new(decoder, segment.start_ms, segment.end_ms)?)),
+        None => Ok(decoder),
+    }
+}
+
+struct Trimmed {
+    inner: Box<dyn Decoder>,
+    start: u64,         // первый кадр отрезка во внутреннем потоке
+    end: Option<u64>,   // кадр, на котором отрезок заканчивается
+    position: u64,      // текущий кадр во внутреннем потоке
+}
+
+impl Trimmed {
+    fn new(mut inner: Box<dyn Decoder>, start_ms: u64, end_ms: Option<u64>) -> io::Result<Self> {
+        let info = inner.info();
+        let start = info.ms_to_frames(start_ms);
+        let end = end_ms.map(|end| info.ms_to_frames(end)).or(info.total_frames);
+        inner.seek(start)?;
+        Ok(Trimmed { inner, start, end, position: start })
+    }
+}
+
+impl Decoder for Trimmed {
+    fn info(&self) -> StreamInfo {
+        let total_frames = self.end.map(|end| end.saturating_sub(self.start));
+        StreamInfo { total_frames, ..self.inner.info() }
+    }
+
+    fn read(&mut self, buf: &mut [f32]) -> io::Result<usize> {
+        let channels = self.inner.info().channels as usize;
+        let mut len = buf.len() / channels * channels;
+        if let Some(end) = self.end {
+            let left = end.saturating_sub(self.position) as usize;
+            len = len.min(left * channels);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += (read / channels) as u64;
+        Ok(read)
+    }
+
+    fn seek(&mut self, frame: u64) -> io::Result<()> {
+        let mut target = self.start + frame;
+        if let Some(end) = self.end {
+            target = target.min(end);
+        }
+        self.inner.seek(target)?;
+        self.position = target;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Поток, в котором сэмпл равен номеру кадра: по нему видно, откуда читали
+    struct Ramp {
+        frames: u64,
+        position: u64,
+    }
+
+    impl Decoder for Ramp {
+        fn info(&self) -> StreamInfo {
+            StreamInfo { sample_rate: 1000, channels: 2, total_frames: Some(self.frames) }
+        }
+
+        fn read(&mut self, buf: &mut [f32]) -> io::Result<usize> {
+            let frames = (buf.len() as u64 / 2).min(self.frames - self.position);
+            for frame in 0..frames as usize {
+                buf[frame * 2] = (self.position + frame as u64) as f32;
+                buf[frame * 2 + 1] = 0.0;
+            }
+            self.position += frames;
+            Ok(frames as usize * 2)
+        }
+
+        fn seek(&mut self, frame: u64) -> io::Result<()> {
+            self.position = frame.min(self.frames);
+            Ok(())
+        }
+    }
+
+    fn trimmed(start_ms: u64, end_ms: Option<u64>) -> Trimmed {
+        Trimmed::new(Box::new(Ramp { frames: 100, position: 0 }), start_ms, end_ms).unwrap()
+    }
+
+    fn frames(decoder: &mut dyn Decoder) -> Vec<f32> {
+        let mut frames = Vec::new();
+        let mut buf = [0.0; 7]; // не кратно числу каналов
+        loop {
+            match decoder.read(&mut buf).unwrap() {
+                0 => return frames,
+                read => frames.extend(buf[..read].iter().step_by(2)),
+            }
+        }
+    }
+
+    #[test]
+    fn trimmed_plays_only_its_segment() {
+        let mut decoder = trimmed(20, Some(30));
+        assert_eq!(decoder.info().total_frames, Some(10));
+        assert_eq!(frames(&mut decoder), (20..30).map(|frame| frame as f32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn last_segment_runs_to_the_end_of_the_file() {
+        let mut decoder = trimmed(95, None);
+        assert_eq!(decoder.info().total_frames, Some(5));
+        assert_eq!(frames(&mut decoder), [95.0, 96.0, 97.0, 98.0, 99.0]);
+    }
+
+    #[test]
+    fn trimmed_seek_is_relative_to_the_segment() {
+        let mut decoder = trimmed(20, Some(30));
+        decoder.seek(8).unwrap();
+        assert_eq!(frames(&mut decoder), [28.0, 29.0]);
+        decoder.seek(50).unwrap();
+        assert!(frames(&mut decoder).is_empty());
+    }
+
+    #[test]
+    fn segment_past_the_end_is_empty() {
+        let mut decoder = trimmed(150, Some(200));
+        assert!(frames(&mut decoder).is_empty());
+        let mut decoder = trimmed(150, None);
+        assert_eq!(decoder.info().total_frames, Some(0));
+        assert!(frames(&mut decoder).is_empty());
+    }
+
+    #[test]
+    fn open_rejects_unknown_and_undecodable_files() {
+        let dir = std::env::temp_dir();
+        let text = dir.join(format!("decode_test_{}.txt", std::process::id()));
+        std::fs::write(&text, "просто текст").unwrap();
+        assert_eq!(open(&text).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+
+        let flac = dir.join(format!("decode_test_{}.flac", std::process::id()));
+        std::fs::write(&flac, b"fLaC\0\0\0\x22").unwrap();
+        assert_eq!(open(&flac).err().map(|e| e.kind()), Some(io::ErrorKind::Unsupported));
+
+        std::fs::remove_file(&text).unwrap();
+        std::fs::remove_file(&flac).unwrap();
+        assert_eq!(open(&dir.join("decode_test_missing.wav")).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+    }
+}
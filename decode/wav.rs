@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use super::{Decoder, StreamInfo};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleFormat {
+    Int,
+    Float,
+}
+
+pub struct WavDecoder {
+    reader: BufReader<File>,
+    info: StreamInfo,
+    format: SampleFormat,
+    bytes_per_sample: usize,
+    data_start: u64,
+    data_frames: u64,
+    position: u64, // текущий кадр
+    bytes: Vec<u8>,
+}
+
+fn invalid(reason: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.into())
+}
+
+fn le_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl WavDecoder {
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut reader = BufReader::new(file);
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(invalid("нет заголовка RIFF/WAVE"));
+        }
+
+        let mut fmt: Option<(SampleFormat, u16, u32, usize)> = None;
+        loop {
+            let mut chunk = [0u8; 8];
+            if reader.read_exact(&mut chunk).is_err() {
+                return Err(invalid("не найден блок data"));
+            }
+            let len = le_u32(&chunk[4..8]) as u64;
+
+            match &chunk[0..4] {
+                b"fmt " => {
+                    if !(16..=1024).contains(&len) {
+                        return Err(invalid("некорректный размер блока fmt"));
+                    }
+                    let mut body = vec![0u8; len as usize];
+                    reader.read_exact(&mut body)?;
+                    fmt = Some(parse_fmt(&body)?);
+                    // Блоки выравниваются по чётной границе
+                    if len % 2 == 1 {
+                        reader.seek(SeekFrom::Current(1))?;
+                    }
+                }
+                b"data" => {
+                    let (format, channels, sample_rate, bytes_per_sample) =
+                        fmt.ok_or_else(|| invalid("блок data идёт раньше fmt"))?;
+                    let data_start = reader.stream_position()?;
+                    // Размер может быть больше реального, если запись оборвалась
+                    let file_len = reader.get_ref().metadata()?.len();
+                    let len = len.min(file_len.saturating_sub(data_start));
+                    let data_frames = len / (channels as u64 * bytes_per_sample as u64);
+
+                    return Ok(WavDecoder {
+                        reader,
+                        info: StreamInfo { sample_rate, channels, total_frames: Some(data_frames) },
+                        format,
+                        bytes_per_sample,
+                        data_start,
+                        data_frames,
+                        position: 0,
+                        bytes: Vec::new(),
+                    });
+                }
+                _ => {
+                    reader.seek(SeekFrom::Current((len + len % 2) as i64))?;
+                }
+            }
+        }
+    }
+}
+
+fn parse_fmt(body: &[u8]) -> io::Result<(SampleFormat, u16, u32, usize)> {
+    let mut tag = le_u16(&body[0..2]);
+    let channels = le_u16(&body[2..4]);
+    let sample_rate = le_u32(&body[4..8]);
+    let bits = le_u16(&body[14..16]);
+
+    // WAVE_FORMAT_EXTENSIBLE: настоящий формат - первые два байта GUID подформата
+    if tag == FORMAT_EXTENSIBLE {
+        if body.len() < 40 {
+            return Err(invalid("обрезанный блок fmt у WAVE_FORMAT_EXTENSIBLE"));
+        }
+        tag = le_u16(&body[24..26]);
+    }
+
+    if channels == 0 || sample_rate == 0 {
+        return Err(invalid("нулевое число каналов или частота"));
+    }
+
+    let format = match (tag, bits) {
+        (FORMAT_PCM, 8 | 16 | 24 | 32) => SampleFormat::Int,
+        (FORMAT_FLOAT, 32 | 64) => SampleFormat::Float,
+        _ => return Err(invalid(format!("неподдерживаемый формат WAV: код {:#06x}, {} бит", tag, bits))),
+    };
+    Ok((format, channels, sample_rate, bits as usize / 8))
+}
+
+impl WavDecoder {
+    fn sample(&self, bytes: &[u8]) -> f32 {
+        match (self.format, self.bytes_per_sample) {
+            // 8-битный PCM беззнаковый, остальные - знаковые
+            (SampleFormat::Int, 1) => (bytes[0] as f32 - 128.0) / 128.0,
+            (SampleFormat::Int, 2) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            (SampleFormat::Int, 3) => {
+                (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32 / 8_388_608.0
+            }
+            (SampleFormat::Int, _) => le_u32(bytes) as i32 as f32 / 2_147_483_648.0,
+            (SampleFormat::Float, 4) => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            (SampleFormat::Float, _) => f64::from_le_bytes(bytes[0..8].try_into().unwrap()) as f32,
+        }
+    }
+}
+
+impl Decoder for WavDecoder {
+    fn info(&self) -> StreamInfo {
+        self.info
+    }
+
+    fn read(&mut self, buf: &mut [f32]) -> io::Result<usize> {
+        let channels = self.info.channels as usize;
+        let frames = (buf.len() / channels).min((self.data_frames - self.position) as usize);
+        if frames == 0 {
+            return Ok(0);
+        }
+
+        let samples = frames * channels;
+        self.bytes.resize(samples * self.bytes_per_sample, 0);
+        let mut bytes = std::mem::take(&mut self.bytes);
+        self.reader.read_exact(&mut bytes)?;
+        for (sample, chunk) in buf.iter_mut().zip(bytes.chunks_exact(self.bytes_per_sample)) {
+            *sample = self.sample(chunk);
+        }
+        self.bytes = bytes;
+
+        self.position += frames as u64;
+        Ok(samples)
+    }
+
+    fn seek(&mut self, frame: u64) -> io::Result<()> {
+        let frame = frame.min(self.data_frames);
+        let block_align = self.info.channels as u64 * self.bytes_per_sample as u64;
+        self.reader.seek(SeekFrom::Start(self.data_start + frame * block_align))?;
+        self.position = frame;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn fmt_chunk(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut body = Vec::new();
+        body.extend_from_slice(&tag.to_le_bytes());
+        body.extend_from_slice(&channels.to_le_bytes());
+        body.extend_from_slice(&sample_rate.to_le_bytes());
+        body.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        body.extend_from_slice(&block_align.to_le_bytes());
+        body.extend_from_slice(&bits.to_le_bytes());
+        chunk(b"fmt ", &body)
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        file.extend_from_slice(b"WAVE");
+        file.extend(body);
+        file
+    }
+
+    fn open_bytes(name: &str, bytes: &[u8]) -> io::Result<WavDecoder> {
+        let path = std::env::temp_dir().join(format!("wav_test_{}_{}.wav", std::process::id(), name));
+        File::create(&path).unwrap().write_all(bytes).unwrap();
+        let decoder = WavDecoder::new(File::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        decoder
+    }
+
+    fn read_all(decoder: &mut WavDecoder) -> Vec<f32> {
+        let mut samples = Vec::new();
+        let mut buf = [0.0; 6];
+        loop {
+            match decoder.read(&mut buf).unwrap() {
+                0 => return samples,
+                read => samples.extend_from_slice(&buf[..read]),
+            }
+        }
+    }
+
+    fn error(name: &str, bytes: &[u8]) -> String {
+        open_bytes(name, bytes).err().expect("ожидалась ошибка").to_string()
+    }
+
+    #[test]
+    fn decodes_16_bit_stereo_after_odd_chunk() {
+        let samples: Vec<u8> = [0i16, 16384, -32768, 32767, 8192, -8192].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let file = riff(&[chunk(b"LIST", b"odd"), fmt_chunk(1, 2, 44_100, 16), chunk(b"data", &samples)]);
+        let mut decoder = open_bytes("pcm16", &file).unwrap();
+        assert_eq!(decoder.info(), StreamInfo { sample_rate: 44_100, channels: 2, total_frames: Some(3) });
+        assert_eq!(read_all(&mut decoder), [0.0, 0.5, -1.0, 32767.0 / 32768.0, 0.25, -0.25]);
+    }
+
+    #[test]
+    fn decodes_every_sample_width() {
+        let cases: [(u16, u16, Vec<u8>, f32); 5] = [
+            (1, 8, vec![192], 0.5),
+            (1, 24, vec![0, 0, 0xC0], -0.5),
+            (1, 32, (i32::MIN / 4).to_le_bytes().to_vec(), -0.25),
+            (3, 32, 0.75f32.to_le_bytes().to_vec(), 0.75),
+            (3, 64, (-0.125f64).to_le_bytes().to_vec(), -0.125),
+        ];
+        for (tag, bits, data, expected) in cases {
+            let mut decoder = open_bytes("width", &riff(&[fmt_chunk(tag, 1, 8000, bits), chunk(b"data", &data)])).unwrap();
+            assert_eq!(read_all(&mut decoder), [expected], "{} бит", bits);
+        }
+    }
+
+    #[test]
+    fn extensible_format_uses_the_subformat() {
+        let mut fmt = fmt_chunk(FORMAT_EXTENSIBLE, 1, 8000, 16)[8..].to_vec();
+        fmt.extend_from_slice(&22u16.to_le_bytes());
+        fmt.extend_from_slice(&16u16.to_le_bytes());
+        fmt.extend_from_slice(&4u32.to_le_bytes());
+        fmt.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        fmt.extend_from_slice(&[0; 14]);
+        let mut decoder = open_bytes("extensible", &riff(&[chunk(b"fmt ", &fmt), chunk(b"data", &8192i16.to_le_bytes())])).unwrap();
+        assert_eq!(read_all(&mut decoder), [0.25]);
+
+        let short = chunk(b"fmt ", &fmt[..30]);
+        assert_eq!(error("short_extensible", &riff(&[short, chunk(b"data", &[0, 0])])), "обрезанный блок fmt у WAVE_FORMAT_EXTENSIBLE");
+    }
+
+    #[test]
+    fn seek_moves_to_the_frame() {
+        let samples: Vec<u8> = (0..10i16).flat_map(|s| (s * 1000).to_le_bytes()).collect();
+        let mut decoder = open_bytes("seek", &riff(&[fmt_chunk(1, 1, 8000, 16), chunk(b"data", &samples)])).unwrap();
+        decoder.seek(7).unwrap();
+        assert_eq!(read_all(&mut decoder), [7000.0 / 32768.0, 8000.0 / 32768.0, 9000.0 / 32768.0]);
+        decoder.seek(100).unwrap();
+        assert!(read_all(&mut decoder).is_empty());
+    }
+
+    #[test]
+    fn oversized_data_length_is_clamped_to_the_file() {
+        let mut file = riff(&[fmt_chunk(1, 2, 8000, 16), chunk(b"data", &[0; 16])]);
+        let data_len = file.len() - 16 - 4;
+        file[data_len..data_len + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        file.truncate(file.len() - 2); // запись оборвалась посреди кадра
+        let mut decoder = open_bytes("clamped", &file).unwrap();
+        assert_eq!(decoder.info().total_frames, Some(3));
+        assert_eq!(read_all(&mut decoder).len(), 6);
+    }
+
+    #[test]
+    fn malformed_headers_are_errors() {
+        assert_eq!(error("riff", b"RIFX\0\0\0\0WAVE"), "нет заголовка RIFF/WAVE");
+        assert_eq!(error("no_data", &riff(&[fmt_chunk(1, 1, 8000, 16)])), "не найден блок data");
+        assert_eq!(error("data_first", &riff(&[chunk(b"data", &[0, 0]), fmt_chunk(1, 1, 8000, 16)])), "блок data идёт раньше fmt");
+        assert_eq!(error("fmt_len", &riff(&[chunk(b"fmt ", &[0; 8]), chunk(b"data", &[])])), "некорректный размер блока fmt");
+        assert_eq!(error("channels", &riff(&[fmt_chunk(1, 0, 8000, 16), chunk(b"data", &[])])), "нулевое число каналов или частота");
+        assert_eq!(
+            error("adpcm", &riff(&[fmt_chunk(2, 1, 8000, 4), chunk(b"data", &[])])),
+            "неподдерживаемый формат WAV: код 0x0002, 4 бит"
+        );
+        // Блок неизвестного типа длиннее файла
+        let mut file = riff(&[fmt_chunk(1, 1, 8000, 16)]);
+        file.extend_from_slice(b"junk");
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(error("junk", &file), "не найден блок data");
+        assert!(open_bytes("empty", b"").is_err());
+    }
+}
@@ -0,0 +1,406 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::decode::{self, Decoder, StreamInfo};
+use crate::output::Sink;
+use crate::Song;
+
+// Сколько кадров декодируется и отдаётся выводу за один раз
+const CHUNK_FRAMES: usize = 4096;
+// Как часто сообщать о позиции воспроизведения
+const POSITION_INTERVAL_MS: u64 = 250;
+
+// Плейлист живёт в MusicPlayer, поэтому на Next и Previous движок только обрывает трек
+// и сообщает Skipped, а соседний трек MusicPlayer выбирает сам и присылает через Play.
+// Без играющего трека Next и Previous ничего не делают.
+pub enum Command {
+    Play { id: u64, song: Box<Song> },
+    Pause,
+    Resume,
+    Stop,
+    Next,
+    Previous,
+    Seek(u64), // в миллисекундах от начала трека
+    SetVolume(u8),
+    SetSink(Box<dyn Sink>),
+    Shutdown,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    TrackStarted,
+    TrackEnded, // трек доигран до конца; после Stop не приходит
+    PositionChanged(u64),
+    Skipped(Direction), // трек оборван по Next или Previous
+    Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Next,
+    Previous,
+}
+
+// Поток воспроизведения: владеет декодером и выводом, общается с меню через каналы
+pub struct Engine {
+    commands: Sender<Command>,
+    events: Receiver<(u64, Event)>, // с id из Play, чтобы отличать события прежних треков
+    thread: Option<JoinHandle<()>>,
+    last_id: u64, // id последнего Play или Stop; события с другим id устарели
+}
+
+impl Engine {
+    pub fn spawn(sink: Box<dyn Sink>, volume: u8) -> Self {
+        let (commands, command_rx) = mpsc::channel();
+        let (event_tx, events) = mpsc::channel();
+
+        let worker = Worker {
+            sink,
+            gain: gain(volume),
+            current: None,
+            track_id: 0,
+            paused: false,
+            events: event_tx,
+        };
+        let thread = thread::Builder::new()
+            .name("playback".to_string())
+            .spawn(move || worker.run(command_rx))
+            .expect("не удалось запустить поток воспроизведения");
+
+        Engine { commands, events, thread: Some(thread), last_id: 0 }
+    }
+
+    pub fn play(&mut self, song: Song) {
+        self.last_id += 1;
+        self.send(Command::Play { id: self.last_id, song: Box::new(song) });
+    }
+
+    // Новый id отсекает события, которые трек успел отправить до остановки:
+    // иначе уже доигранный трек переключил бы плейлист после Stop
+    pub fn stop(&mut self) {
+        self.last_id += 1;
+        self.send(Command::Stop);
+    }
+
+    // Если поток завершился, команды молча теряются: ошибка уже пришла событием
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    // Забирает накопившиеся события текущего трека, не блокируясь
+    pub fn poll(&self) -> Vec<Event> {
+        self.events.try_iter().filter(|&(id, _)| id == self.last_id).map(|(_, event)| event).collect()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.send(Command::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Громкость на слух ближе к квадратичной шкале, чем к линейной
+fn gain(volume: u8) -> f32 {
+    let volume = volume.min(100) as f32 / 100.0;
+    volume * volume
+}
+
+struct Track {
+    decoder: Box<dyn Decoder>,
+    info: StreamInfo,
+    position: u64, // текущий кадр
+    reported_ms: u64,
+}
+
+struct Worker {
+    sink: Box<dyn Sink>,
+    gain: f32,
+    current: Option<Track>,
+    track_id: u64,
+    paused: bool,
+    events: Sender<(u64, Event)>,
+}
+
+impl Worker {
+    fn run(mut self, commands: Receiver<Command>) {
+        let mut buf = Vec::new();
+
+        loop {
+            // Пока играем, команды проверяются между кусками; в простое ждём их
+            let command = if self.current.is_some() && !self.paused {
+                match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            } else {
+                match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                }
+            };
+
+            match command {
+                Some(Command::Shutdown) => return,
+                Some(command) => self.handle(command),
+                None => self.play_chunk(&mut buf),
+            }
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        let _ = self.events.send((self.track_id, event));
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Play { id, song } => {
+                self.track_id = id;
+                self.start(*song);
+            }
+            Command::Pause => self.paused = self.current.is_some(),
+            Command::Resume => {
+                if self.paused {
+                    self.sink.reset();
+                }
+                self.paused = false;
+            }
+            Command::Stop => {
+                self.current = None;
+                self.paused = false;
+            }
+            Command::Next => self.skip(Direction::Next),
+            Command::Previous => self.skip(Direction::Previous),
+            Command::Seek(ms) => self.seek(ms),
+            Command::SetVolume(volume) => self.gain = gain(volume),
+            Command::SetSink(mut sink) => {
+                // Новый вывод сразу настраиваем под играющий трек; если не вышло - остаёмся на старом
+                if let Some(track) = &self.current {
+                    if let Err(e) = sink.configure(track.info) {
+                        self.emit(Event::Error(format!("не удалось переключить вывод: {}", e)));
+                        return;
+                    }
+                }
+                let _ = self.sink.flush();
+                self.sink = sink;
+            }
+            Command::Shutdown => {}
+        }
+    }
+
+    fn start(&mut self, song: Song) {
+        self.current = None;
+        self.paused = false;
+
+        let decoder = match decode::open_song(&song) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                self.emit(Event::Error(format!("{}: {}", song.path, e)));
+                return;
+            }
+        };
+
+        let info = decoder.info();
+        if let Err(e) = self.sink.configure(info) {
+            self.emit(Event::Error(format!("вывод не принимает поток {} Гц, {} кан.: {}", info.sample_rate, info.channels, e)));
+            return;
+        }
+
+        self.current = Some(Track { decoder, info, position: 0, reported_ms: 0 });
+        self.emit(Event::TrackStarted);
+    }
+
+    fn skip(&mut self, direction: Direction) {
+        if self.current.is_none() {
+            return;
+        }
+        self.current = None;
+        self.paused = false;
+        self.sink.reset();
+        self.emit(Event::Skipped(direction));
+    }
+
+    fn seek(&mut self, ms: u64) {
+        let Some(track) = self.current.as_mut() else {
+            return;
+        };
+
+        let mut frame = track.info.ms_to_frames(ms);
+        if let Some(total) = track.info.total_frames {
+            frame = frame.min(total);
+        }
+        if let Err(e) = track.decoder.seek(frame) {
+            self.emit(Event::Error(format!("не удалось перемотать: {}", e)));
+            return;
+        }
+
+        track.position = frame;
+        self.sink.reset();
+        track.reported_ms = track.info.frames_to_ms(frame);
+        let position = track.reported_ms;
+        self.emit(Event::PositionChanged(position));
+    }
+
+    fn play_chunk(&mut self, buf: &mut Vec<f32>) {
+        let Some(track) = self.current.as_mut() else {
+            return;
+        };
+
+        let channels = track.info.channels as usize;
+        buf.resize(CHUNK_FRAMES * channels, 0.0);
+
+        let read = match track.decoder.read(buf) {
+            Ok(0) => {
+                self.current = None;
+                self.emit(Event::TrackEnded);
+                return;
+            }
+            Ok(read) => read,
+            Err(e) => {
+                self.current = None;
+                self.emit(Event::Error(format!("ошибка декодирования: {}", e)));
+                return;
+            }
+        };
+
+        for sample in &mut buf[..read] {
+            *sample *= self.gain;
+        }
+        if let Err(e) = self.sink.write(&buf[..read]) {
+            self.current = None;
+            self.emit(Event::Error(format!("ошибка вывода: {}", e)));
+            return;
+        }
+
+        track.position += (read / channels) as u64;
+        let position = track.info.frames_to_ms(track.position);
+        if position >= track.reported_ms + POSITION_INTERVAL_MS {
+            track.reported_ms = position;
+            self.emit(Event::PositionChanged(position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::NullSink;
+    use std::time::{Duration, Instant};
+
+    // Секунда тишины: 8 кГц, моно, 16 бит
+    fn silent_wav(name: &str) -> Song {
+        let path = std::env::temp_dir().join(format!("engine_{}_{}.wav", name, std::process::id()));
+        let data_len: u32 = 8000 * 2;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&8000u32.to_le_bytes());
+        bytes.extend_from_slice(&16000u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(bytes.len() + data_len as usize, 0);
+        std::fs::write(&path, bytes).unwrap();
+        Song::new(name.to_string(), String::new(), 1, String::new(), 0, path.to_string_lossy().into_owned())
+    }
+
+    // События до первого подходящего под wanted включительно
+    fn events_until(engine: &Engine, wanted: impl Fn(&Event) -> bool) -> Vec<Event> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = Vec::new();
+        while Instant::now() < deadline {
+            for event in engine.poll() {
+                let found = wanted(&event);
+                events.push(event);
+                if found {
+                    return events;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("событие не пришло: {:?}", events);
+    }
+
+    fn wait_for(engine: &Engine, wanted: impl Fn(&Event) -> bool) {
+        events_until(engine, wanted);
+    }
+
+    #[test]
+    fn track_plays_to_the_end() {
+        let song = silent_wav("end");
+        let mut engine = Engine::spawn(Box::new(NullSink::new(false)), 50);
+        engine.play(song.clone());
+        let events = events_until(&engine, |event| matches!(event, Event::TrackEnded));
+        assert!(matches!(events[0], Event::TrackStarted));
+        std::fs::remove_file(&song.path).unwrap();
+    }
+
+    #[test]
+    fn next_cuts_the_track_and_reports_skip() {
+        let song = silent_wav("next");
+        let mut engine = Engine::spawn(Box::new(NullSink::new(true)), 50);
+        engine.play(song.clone());
+        wait_for(&engine, |event| matches!(event, Event::TrackStarted));
+
+        engine.send(Command::Next);
+        wait_for(&engine, |event| matches!(event, Event::Skipped(Direction::Next)));
+        // Оборванный трек не доигрывается
+        thread::sleep(Duration::from_millis(1200));
+        assert!(!engine.poll().iter().any(|event| matches!(event, Event::TrackEnded)));
+        std::fs::remove_file(&song.path).unwrap();
+    }
+
+    #[test]
+    fn skip_without_track_is_ignored() {
+        let song = silent_wav("idle");
+        let mut engine = Engine::spawn(Box::new(NullSink::new(false)), 50);
+        engine.send(Command::Previous);
+        engine.play(song.clone());
+        wait_for(&engine, |event| matches!(event, Event::TrackEnded));
+
+        // Трек доигран: переключать нечего
+        engine.send(Command::Next);
+        engine.send(Command::Previous);
+        engine.play(song.clone());
+        let events = events_until(&engine, |event| matches!(event, Event::TrackEnded));
+        assert!(!events.iter().any(|event| matches!(event, Event::Skipped(_))), "{:?}", events);
+        std::fs::remove_file(&song.path).unwrap();
+    }
+
+    #[test]
+    fn stop_discards_events_sent_before_it() {
+        let song = silent_wav("stop");
+        let mut engine = Engine::spawn(Box::new(NullSink::new(false)), 50);
+        engine.play(song.clone());
+        // Без вывода в реальном времени секунда звука доигрывается за миллисекунды,
+        // и TrackEnded уже лежит в канале, когда приходит Stop
+        thread::sleep(Duration::from_millis(300));
+        engine.stop();
+        assert!(engine.poll().is_empty());
+
+        engine.send(Command::Next);
+        thread::sleep(Duration::from_millis(100));
+        assert!(engine.poll().is_empty());
+
+        // Следующий Play снова получает свои события
+        engine.play(song.clone());
+        wait_for(&engine, |event| matches!(event, Event::TrackEnded));
+        std::fs::remove_file(&song.path).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_an_error_event() {
+        let mut engine = Engine::spawn(Box::new(NullSink::new(false)), 50);
+        let song = Song::new(String::new(), String::new(), 1, String::new(), 0, "/nonexistent/engine.wav".to_string());
+        engine.play(song);
+        wait_for(&engine, |event| matches!(event, Event::Error(_)));
+    }
+}
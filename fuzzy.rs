@@ -0,0 +1,169 @@
+// Нечёткое сравнение слов: поиск прощает опечатки ("nirvanna" найдёт Nirvana)
+
+// Сколько опечаток прощается слову такой длины; короткие слова должны совпасть точно
+pub fn typo_budget(len: usize) -> usize {
+    match len {
+        0..=4 => 0,
+        5..=8 => 1,
+        _ => 2,
+    }
+}
+
+// Слова текста: буквы и цифры, всё остальное - разделители
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty())
+}
+
+// Число опечаток между строками
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    distance_within(a, &b, usize::MAX).unwrap_or(usize::MAX)
+}
+
+// Расстояние Дамерау-Левенштейна (перестановка соседних букв - одна опечатка),
+// если оно не больше max. Слова с сильно разной длиной отсекаются без подсчёта,
+// а подсчёт прерывается, как только вся строка таблицы превысила max -
+// на большой библиотеке почти все пары отсекаются сразу.
+fn distance_within(a: &str, b: &[char], max: usize) -> Option<usize> {
+    if a.chars().count().abs_diff(b.len()) > max {
+        return None;
+    }
+    // Три строки таблицы: для a[i - 2], a[i - 1] и a[i]
+    let mut before = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut row = vec![0; b.len() + 1];
+    let mut last = None;
+    for (i, ca) in a.chars().enumerate() {
+        row[0] = i + 1;
+        let mut row_min = row[0];
+        for (j, &cb) in b.iter().enumerate() {
+            let mut distance = (previous[j] + usize::from(ca != cb)).min(row[j] + 1).min(previous[j + 1] + 1);
+            if j > 0 && last == Some(cb) && ca == b[j - 1] {
+                distance = distance.min(before[j - 1] + 1);
+            }
+            row[j + 1] = distance;
+            row_min = row_min.min(distance);
+        }
+        if row_min > max {
+            return None;
+        }
+        last = Some(ca);
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut row);
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= max)
+}
+
+// Число опечаток, если слово похоже на искомое (в пределах typo_budget)
+pub fn similar(word: &str, wanted: &[char]) -> Option<usize> {
+    distance_within(word, wanted, typo_budget(wanted.len()))
+}
+
+// Слова запроса, заранее разобранные на символы
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    words: Vec<Vec<char>>,
+}
+
+impl Pattern {
+    // needle - уже после normalize::fold
+    pub fn new(needle: &str) -> Self {
+        Pattern { words: words(needle).map(|word| word.chars().collect()).collect() }
+    }
+
+    pub fn words(&self) -> &[Vec<char>] {
+        &self.words
+    }
+
+    // Наименьшее число опечаток, с которым слова запроса совпадают с идущими подряд
+    // словами текста (text - после normalize::fold); None - не похоже
+    pub fn typos(&self, text: &str) -> Option<usize> {
+        if self.words.is_empty() {
+            return None;
+        }
+        if let [wanted] = self.words.as_slice() {
+            return words(text).filter_map(|word| similar(word, wanted)).min();
+        }
+        let text: Vec<&str> = words(text).collect();
+        text.windows(self.words.len())
+            .filter_map(|window| {
+                window.iter().zip(&self.words)
+                    .map(|(word, wanted)| similar(word, wanted))
+                    .sum::<Option<usize>>()
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(word: &str) -> Vec<char> {
+        word.chars().collect()
+    }
+
+    #[test]
+    fn edit_distance_counts_each_kind_of_typo_once() {
+        assert_eq!(edit_distance("queen", "queen"), 0);
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("nirvana", "nirvanna"), 1); // лишняя буква
+        assert_eq!(edit_distance("nirvana", "nrvana"), 1); // пропущенная
+        assert_eq!(edit_distance("nirvana", "nirvena"), 1); // замена
+        assert_eq!(edit_distance("nirvana", "nirvaan"), 1); // перестановка соседних
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        assert_eq!(edit_distance("abc", "ca"), 3); // перестановка не объединяется с правкой между буквами
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("кино", "книо"), 1);
+        assert_eq!(edit_distance("rhapsody", "rapsodi"), edit_distance("rapsodi", "rhapsody"));
+    }
+
+    #[test]
+    fn distance_within_stops_past_max() {
+        assert_eq!(distance_within("queen", &chars("queen"), 0), Some(0));
+        assert_eq!(distance_within("queen", &chars("qeen"), 0), None);
+        assert_eq!(distance_within("queen", &chars("qeen"), 1), Some(1));
+        assert_eq!(distance_within("ab", &chars("abcdef"), 3), None); // отсекается по длине
+        assert_eq!(distance_within("abcdef", &chars("uvwxyz"), 2), None);
+        assert_eq!(distance_within("abcdef", &chars("uvwxyz"), 6), Some(6));
+    }
+
+    #[test]
+    fn short_words_must_match_exactly() {
+        assert_eq!([0, 4, 5, 8, 9, 20].map(typo_budget), [0, 0, 1, 1, 2, 2]);
+        assert_eq!(similar("abba", &chars("abbа")), None); // последняя "а" - кириллица
+        assert_eq!(similar("queen", &chars("qeen")), None);
+        assert_eq!(similar("qeen", &chars("queen")), Some(1));
+        assert_eq!(similar("metalica", &chars("metallica")), Some(1));
+        assert_eq!(similar("metalika", &chars("metallica")), Some(2));
+        assert_eq!(similar("metal", &chars("metallica")), None);
+    }
+
+    #[test]
+    fn words_split_on_anything_but_letters_and_digits() {
+        assert_eq!(words("  AC/DC - Back in Black (1980) ").collect::<Vec<_>>(), ["AC", "DC", "Back", "in", "Black", "1980"]);
+        assert_eq!(words("группа-крови").collect::<Vec<_>>(), ["группа", "крови"]);
+        assert_eq!(words(" - ").count(), 0);
+    }
+
+    #[test]
+    fn pattern_matches_consecutive_words() {
+        let pattern = Pattern::new("bohemian rapsody");
+        assert_eq!(pattern.words(), [chars("bohemian"), chars("rapsody")]);
+        assert_eq!(pattern.typos("queen - bohemian rhapsody (live)"), Some(1));
+        assert_eq!(pattern.typos("bohemain rhapsody"), Some(2));
+        // Слова должны идти подряд и в том же порядке
+        assert_eq!(pattern.typos("bohemian live rhapsody"), None);
+        assert_eq!(pattern.typos("rhapsody bohemian"), None);
+        assert_eq!(pattern.typos("bohemian"), None);
+
+        // Из нескольких мест берётся лучшее
+        let pattern = Pattern::new("stairway");
+        assert_eq!(pattern.typos("stariway to stairway"), Some(0));
+        assert_eq!(pattern.typos("stairs"), None);
+
+        assert_eq!(Pattern::new(" - ").typos("anything"), None);
+    }
+}
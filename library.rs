@@ -0,0 +1,173 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::Song;
+
+// Постоянный номер песни в библиотеке; 0 - песня ещё не добавлена
+pub type SongId = u64;
+
+// Библиотека песен. Плейлисты и очередь хранят id, а не копии песен,
+// поэтому правка тегов в библиотеке сразу видна везде.
+// Для чтения библиотека ведёт себя как срез песен (поэтому pub(crate): Song приватна).
+#[derive(Debug)]
+pub(crate) struct Library {
+    songs: Vec<Song>,
+    positions: HashMap<SongId, usize>, // id → индекс в songs
+    next_id: SongId,
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Library { songs: Vec::new(), positions: HashMap::new(), next_id: 1 }
+    }
+
+    // Песни из файла состояния сохраняют свои id; повторные и нулевые получают новые
+    pub fn from_songs(songs: Vec<Song>) -> Self {
+        let mut library = Library::new();
+        library.next_id = songs.iter().map(|song| song.id).max().unwrap_or(0) + 1;
+        for song in songs {
+            library.add(song);
+        }
+        library
+    }
+
+    // Песня, уже бывшая в этой библиотеке, сохраняет id; новая (id == 0) получает следующий
+    pub fn add(&mut self, mut song: Song) -> &Song {
+        if song.id == 0 || self.positions.contains_key(&song.id) {
+            song.id = self.next_id;
+        }
+        self.next_id = self.next_id.max(song.id + 1);
+
+        self.positions.insert(song.id, self.songs.len());
+        self.songs.push(song);
+        &self.songs[self.songs.len() - 1]
+    }
+
+    // Забирает все песни для пересборки. Счётчик id не сбрасывается:
+    // id удалённых песен не достанутся новым.
+    pub fn take(&mut self) -> Vec<Song> {
+        self.positions.clear();
+        std::mem::take(&mut self.songs)
+    }
+
+    // Убирает песни, для которых remove вернул true, и возвращает их. id остальных не меняются.
+    pub fn remove_if(&mut self, mut remove: impl FnMut(&Song) -> bool) -> Vec<Song> {
+        let (removed, kept) = std::mem::take(&mut self.songs).into_iter().partition(|song| remove(song));
+        self.songs = kept;
+        self.positions = self.songs.iter().enumerate().map(|(index, song)| (song.id, index)).collect();
+        removed
+    }
+
+    pub fn song(&self, id: SongId) -> Option<&Song> {
+        self.positions.get(&id).map(|&index| &self.songs[index])
+    }
+
+    pub fn song_mut(&mut self, id: SongId) -> Option<&mut Song> {
+        self.positions.get(&id).map(|&index| &mut self.songs[index])
+    }
+
+    pub fn contains(&self, id: SongId) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    // Место песни в библиотеке, чтобы расставить найденные по индексу песни в её порядке
+    pub fn position(&self, id: SongId) -> Option<usize> {
+        self.positions.get(&id).copied()
+    }
+
+    pub fn sort_by(&mut self, compare: impl FnMut(&Song, &Song) -> Ordering) {
+        self.songs.sort_by(compare);
+        self.positions = self.songs.iter().enumerate().map(|(index, song)| (song.id, index)).collect();
+    }
+}
+
+impl Deref for Library {
+    type Target = [Song];
+
+    fn deref(&self) -> &[Song] {
+        &self.songs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: SongId, title: &str) -> Song {
+        let mut song = Song::new(title.to_string(), String::new(), 0, String::new(), 0, format!("{}.wav", title));
+        song.id = id;
+        song
+    }
+
+    fn titles(library: &Library) -> Vec<(SongId, &str)> {
+        library.iter().map(|song| (song.id, song.title.as_str())).collect()
+    }
+
+    // Карта positions должна указывать на каждую песню и ни на что больше
+    fn assert_positions(library: &Library) {
+        assert_eq!(library.positions.len(), library.len());
+        for (index, song) in library.iter().enumerate() {
+            assert_eq!(library.position(song.id), Some(index));
+            assert_eq!(library.song(song.id).unwrap().title, song.title);
+        }
+    }
+
+    #[test]
+    fn ids_are_kept_or_assigned() {
+        let mut library = Library::from_songs(vec![song(5, "a"), song(2, "b"), song(5, "c"), song(0, "d")]);
+        // Повтор и ноль получают id после наибольшего
+        assert_eq!(titles(&library), [(5, "a"), (2, "b"), (6, "c"), (7, "d")]);
+        assert_positions(&library);
+
+        assert_eq!(library.add(song(0, "e")).id, 8);
+        assert_eq!(library.add(song(3, "f")).id, 3);
+        assert_eq!(library.add(song(3, "g")).id, 9);
+        assert_positions(&library);
+    }
+
+    #[test]
+    fn remove_if_keeps_ids_and_positions() {
+        let mut library = Library::from_songs((1..=6).map(|id| song(0, &id.to_string())).collect());
+        let removed = library.remove_if(|song| song.id % 2 == 0);
+
+        assert_eq!(removed.iter().map(|song| song.id).collect::<Vec<_>>(), [2, 4, 6]);
+        assert_eq!(titles(&library), [(1, "1"), (3, "3"), (5, "5")]);
+        assert_positions(&library);
+        for id in [2, 4, 6] {
+            assert!(!library.contains(id));
+            assert!(library.song(id).is_none());
+        }
+
+        // id удалённых песен новым не достаются
+        assert_eq!(library.add(song(0, "new")).id, 7);
+        assert_positions(&library);
+    }
+
+    #[test]
+    fn take_and_re_add_keep_ids() {
+        let mut library = Library::from_songs(vec![song(0, "a"), song(0, "b"), song(0, "c")]);
+        let mut songs = library.take();
+        assert!(library.is_empty() && library.song(1).is_none());
+
+        songs.reverse();
+        songs.remove(1);
+        for song in songs {
+            library.add(song);
+        }
+        assert_eq!(titles(&library), [(3, "c"), (1, "a")]);
+        assert_positions(&library);
+        assert_eq!(library.add(song(0, "d")).id, 4);
+    }
+
+    #[test]
+    fn sort_updates_positions() {
+        let mut library = Library::from_songs(vec![song(0, "c"), song(0, "a"), song(0, "b")]);
+        library.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(titles(&library), [(2, "a"), (3, "b"), (1, "c")]);
+        assert_positions(&library);
+
+        library.song_mut(1).unwrap().title = "z".to_string();
+        assert_eq!(library[2].title, "z");
+    }
+}
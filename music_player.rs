@@ -1,47 +1,280 @@
-use std::io;
-use std::collections::HashMap;
+mod browse;
+mod cue;
+mod decode;
+mod engine;
+mod fuzzy;
+mod library;
+mod normalize;
+mod output;
+mod playlist_files;
+mod query;
+mod scanner;
+mod search_index;
+mod shuffle;
+mod smart;
+mod storage;
+mod tags;
+
+use std::io::{self, Write};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use library::{Library, SongId};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Song {
+    id: SongId,
     title: String,
     artist: String,
     duration: u32, // в секундах
     genre: String,
     year: u16,
     path: String,
+    album: String,
+    album_artist: String, // пусто - совпадает с artist
+    track: u32, // 0 - номер неизвестен
+    disc: u32,
+    composer: String,
+    comment: String,
+    mbid: Option<String>, // MusicBrainz recording ID
+    segment: Option<Segment>, // трек из CUE - часть файла path
+    play_count: u32,
+    rating: u8, // 0 - без оценки, иначе 1..=5
+    last_played: Option<u64>, // unix-время последнего запуска
+    cover: Option<tags::Cover>, // обложка внутри файла path
+}
+
+// Границы виртуального трека внутри файла, в миллисекундах
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Segment {
+    start_ms: u64,
+    end_ms: Option<u64>, // None - до конца файла
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Playlist {
     name: String,
-    songs: Vec<Song>,
+    songs: Vec<SongId>, // песни библиотеки по id
     current_index: Option<usize>,
+    #[serde(skip)]
     is_playing: bool,
     is_shuffle: bool,
+    smart_shuffle: bool, // при is_shuffle: разносить исполнителей и жанры
+    repeat: RepeatMode,
+    rules: Option<smart::SmartRules>, // Some - умный плейлист, songs вычисляются по правилам
+    #[serde(skip)]
+    shuffle: shuffle::ShuffleState, // после перезапуска начинается новый цикл
+}
+
+// По умолчанию плейлист повторяется: так он играл до появления режимов (см. миграцию v4)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum RepeatMode {
+    Off,       // доиграть плейлист до конца и остановиться
+    One,       // повторять текущий трек
+    #[default]
+    All,       // после последнего трека начать сначала
+    StopAtEnd, // остановиться, когда закончится текущий трек
+}
+
+// Поле песни, по которому сортируется плейлист
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum SortField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Year,
+    Track,
+    Composer,
+    Duration,
+    PlayCount,
+    Rating,
+    LastPlayed,
+    Path,
 }
 
 struct MusicPlayer {
-    library: Vec<Song>,
+    library: Library,
     playlists: HashMap<String, Playlist>,
     current_playlist: Option<String>,
     volume: u8,
+    library_roots: Vec<String>,
+    scan_cache: HashMap<String, scanner::FileStamp>,
+    index: browse::LibraryIndex,
+    search_index: search_index::SearchIndex, // слова → песни для search_songs
+    output: output::SinkKind,
+    engine: engine::Engine,
+    position_ms: u64, // позиция в текущем треке по данным движка
+    paused: bool,
+    queue: Vec<SongId>, // очередь воспроизведения: берётся раньше активного плейлиста
+    from_queue: Option<SongId>, // играющая сейчас песня из очереди
+    playing: bool,
+    state_path: Option<PathBuf>, // None - состояние не сохраняется на диск
+    dirty: bool,
+}
+
+fn format_duration(duration: u32) -> String {
+    let minutes = duration / 60;
+    let seconds = duration % 60;
+    format!("{:02}:{:02}", minutes, seconds)
 }
 
 impl Song {
     fn new(title: String, artist: String, duration: u32, genre: String, year: u16, path: String) -> Self {
-        Song { title, artist, duration, genre, year, path }
+        Song {
+            id: 0,
+            title,
+            artist,
+            duration,
+            genre,
+            year,
+            path,
+            album: String::new(),
+            album_artist: String::new(),
+            track: 0,
+            disc: 0,
+            composer: String::new(),
+            comment: String::new(),
+            mbid: None,
+            segment: None,
+            play_count: 0,
+            rating: 0,
+            last_played: None,
+            cover: None,
+        }
+    }
+
+    fn with_album(mut self, album: &str, track: u32) -> Self {
+        self.album = album.to_string();
+        self.track = track;
+        self
+    }
+
+    // Треки одного CUE лежат в одном файле, поэтому путь сам по себе их не различает
+    fn key(&self) -> String {
+        match self.segment {
+            Some(segment) => format!("{}#{}", self.path, segment.start_ms),
+            None => self.path.clone(),
+        }
+    }
+
+    fn album_artist_name(&self) -> &str {
+        if self.album_artist.is_empty() { &self.artist } else { &self.album_artist }
+    }
+
+    // Порядок "как на полке": исполнитель альбома → год → альбом → диск → трек
+    fn album_order(&self, other: &Song) -> Ordering {
+        self.album_artist_name().to_lowercase().cmp(&other.album_artist_name().to_lowercase())
+            .then(self.year.cmp(&other.year))
+            .then_with(|| self.album.to_lowercase().cmp(&other.album.to_lowercase()))
+            .then(self.disc.cmp(&other.disc))
+            .then(self.track.cmp(&other.track))
+            .then_with(|| self.title.to_lowercase().cmp(&other.title.to_lowercase()))
     }
 
     fn format_duration(&self) -> String {
-        let minutes = self.duration / 60;
-        let seconds = self.duration % 60;
-        format!("{:02}:{:02}", minutes, seconds)
+        format_duration(self.duration)
     }
 
     fn display(&self) -> String {
-        format!("🎵 {} - {} [{}] ({})", 
-                self.artist, self.title, self.format_duration(), self.genre)
+        let mut line = format!("🎵 {} - {} [{}] ({})", 
+                               self.artist, self.title, self.format_duration(), self.genre);
+
+        if !self.album.is_empty() {
+            line.push_str(&format!(" 💿 {}", self.album));
+            if self.year > 0 {
+                line.push_str(&format!(", {}", self.year));
+            }
+            if self.track > 0 {
+                if self.disc > 1 {
+                    line.push_str(&format!(" #{}-{}", self.disc, self.track));
+                } else {
+                    line.push_str(&format!(" #{}", self.track));
+                }
+            }
+        }
+        line
+    }
+}
+
+impl RepeatMode {
+    const ALL: [RepeatMode; 4] = [RepeatMode::Off, RepeatMode::One, RepeatMode::All, RepeatMode::StopAtEnd];
+
+    // Переходит ли ручное листание с последнего трека на первый и обратно
+    fn wraps(self) -> bool {
+        matches!(self, RepeatMode::One | RepeatMode::All)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            RepeatMode::Off => "выключен",
+            RepeatMode::One => "повтор трека",
+            RepeatMode::All => "повтор плейлиста",
+            RepeatMode::StopAtEnd => "остановка после трека",
+        }
+    }
+}
+
+impl SortField {
+    const ALL: [SortField; 13] = [
+        SortField::Title,
+        SortField::Artist,
+        SortField::Album,
+        SortField::AlbumArtist,
+        SortField::Genre,
+        SortField::Year,
+        SortField::Track,
+        SortField::Composer,
+        SortField::Duration,
+        SortField::PlayCount,
+        SortField::Rating,
+        SortField::LastPlayed,
+        SortField::Path,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SortField::Title => "название",
+            SortField::Artist => "исполнитель",
+            SortField::Album => "альбом",
+            SortField::AlbumArtist => "исполнитель альбома",
+            SortField::Genre => "жанр",
+            SortField::Year => "год",
+            SortField::Track => "номер трека",
+            SortField::Composer => "композитор",
+            SortField::Duration => "длительность",
+            SortField::PlayCount => "число прослушиваний",
+            SortField::Rating => "оценка",
+            SortField::LastPlayed => "последнее прослушивание",
+            SortField::Path => "путь к файлу",
+        }
+    }
+
+    fn compare(self, a: &Song, b: &Song) -> Ordering {
+        let text = |a: &str, b: &str| a.to_lowercase().cmp(&b.to_lowercase());
+        match self {
+            SortField::Title => text(&a.title, &b.title),
+            SortField::Artist => text(&a.artist, &b.artist),
+            SortField::Album => text(&a.album, &b.album),
+            SortField::AlbumArtist => text(a.album_artist_name(), b.album_artist_name()),
+            SortField::Genre => text(&a.genre, &b.genre),
+            SortField::Year => a.year.cmp(&b.year),
+            SortField::Track => a.disc.cmp(&b.disc).then(a.track.cmp(&b.track)),
+            SortField::Composer => text(&a.composer, &b.composer),
+            SortField::Duration => a.duration.cmp(&b.duration),
+            SortField::PlayCount => a.play_count.cmp(&b.play_count),
+            SortField::Rating => a.rating.cmp(&b.rating),
+            SortField::LastPlayed => a.last_played.cmp(&b.last_played),
+            SortField::Path => a.key().cmp(&b.key()),
+        }
     }
 }
 
@@ -53,87 +286,246 @@ impl Playlist {
             current_index: None,
             is_playing: false,
             is_shuffle: false,
+            smart_shuffle: false,
+            repeat: RepeatMode::default(),
+            rules: None,
+            shuffle: shuffle::ShuffleState::default(),
         }
     }
 
-    fn add_song(&mut self, song: Song) {
-        self.songs.push(song);
+    fn smart(name: String, rules: smart::SmartRules) -> Self {
+        Playlist { rules: Some(rules), ..Playlist::new(name) }
     }
 
-    fn remove_song(&mut self, index: usize) -> Option<Song> {
-        if index < self.songs.len() {
-            Some(self.songs.remove(index))
-        } else {
-            None
+    // Новое содержимое умного плейлиста. С pin играющий трек остаётся, даже если
+    // больше не подходит под правила, - иначе он пропал бы посреди воспроизведения.
+    fn set_songs(&mut self, mut ids: Vec<SongId>, pin: bool) -> bool {
+        let current = self.current_song_id();
+        if let (Some(current), Some(index), true) = (current, self.current_index, pin) {
+            if !ids.contains(&current) {
+                let before: HashSet<SongId> = self.songs[..index].iter().copied().collect();
+                let position = ids.iter().rposition(|id| before.contains(id)).map_or(0, |i| i + 1);
+                ids.insert(position, current);
+            }
         }
+        if ids == self.songs {
+            return false;
+        }
+
+        self.current_index = current.and_then(|current| ids.iter().position(|&id| id == current));
+        self.songs = ids;
+        self.shuffle = shuffle::ShuffleState::default();
+        true
     }
 
-    fn get_current_song(&self) -> Option<&Song> {
-        if let Some(index) = self.current_index {
-            self.songs.get(index)
-        } else {
-            None
+    fn add_song(&mut self, id: SongId) {
+        self.songs.push(id);
+        self.shuffle.inserted(self.songs.len() - 1);
+    }
+
+    fn remove_song(&mut self, index: usize) -> Option<SongId> {
+        if index >= self.songs.len() {
+            return None;
+        }
+
+        let id = self.songs.remove(index);
+        self.shuffle.removed(index);
+        self.current_index = match self.current_index {
+            Some(current) if current > index => Some(current - 1),
+            Some(_) if self.songs.is_empty() => None,
+            Some(current) => Some(current.min(self.songs.len() - 1)),
+            None => None,
+        };
+        Some(id)
+    }
+
+    // Убирает id песен, которых больше нет в библиотеке; возвращает их число
+    fn remove_dangling(&mut self, library: &Library) -> usize {
+        let dangling: Vec<usize> = self.songs.iter().enumerate()
+            .filter(|(_, id)| !library.contains(**id))
+            .map(|(i, _)| i)
+            .collect();
+        for &index in dangling.iter().rev() {
+            self.remove_song(index);
+        }
+        dangling.len()
+    }
+
+    // Переставляет песни: на место i встаёт песня order[i].
+    // Текущий индекс и порядок перемешивания следуют за песнями.
+    fn reorder(&mut self, order: Vec<usize>) {
+        let mut new_position = vec![0; order.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_position[old] = new;
+        }
+
+        self.songs = order.iter().map(|&old| self.songs[old]).collect();
+        self.current_index = self.current_index.map(|current| new_position[current]);
+        self.shuffle.reordered(&new_position);
+    }
+
+    fn move_song(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.songs.len() || to >= self.songs.len() {
+            return false;
+        }
+        let mut order: Vec<usize> = (0..self.songs.len()).collect();
+        let moved = order.remove(from);
+        order.insert(to, moved);
+        self.reorder(order);
+        true
+    }
+
+    fn swap_songs(&mut self, a: usize, b: usize) -> bool {
+        if a >= self.songs.len() || b >= self.songs.len() {
+            return false;
+        }
+        let mut order: Vec<usize> = (0..self.songs.len()).collect();
+        order.swap(a, b);
+        self.reorder(order);
+        true
+    }
+
+    // Сортировка устойчивая: при равных значениях сохраняется прежний порядок.
+    // Песни, которых нет в библиотеке, уходят в конец.
+    fn sort_by_field(&mut self, field: SortField, descending: bool, library: &Library) {
+        let mut order: Vec<usize> = (0..self.songs.len()).collect();
+        order.sort_by(|&a, &b| match (library.song(self.songs[a]), library.song(self.songs[b])) {
+            (Some(a), Some(b)) => {
+                let ordering = field.compare(a, b);
+                if descending { ordering.reverse() } else { ordering }
+            }
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        });
+        self.reorder(order);
+    }
+
+    // Убирает повторы одной и той же песни; из повторов играющей остаётся именно она.
+    // Возвращает число удалённых.
+    fn remove_duplicates(&mut self) -> usize {
+        let current_id = self.current_song_id();
+        let mut seen = HashSet::new();
+        let duplicates: Vec<usize> = self.songs.iter().enumerate()
+            .filter(|&(i, &id)| {
+                if Some(i) == self.current_index {
+                    return false;
+                }
+                // Первое вхождение уступает место играющему повтору
+                current_id == Some(id) || !seen.insert(id)
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        for &index in duplicates.iter().rev() {
+            self.remove_song(index);
+        }
+        duplicates.len()
+    }
+
+    fn set_shuffle(&mut self, shuffle: bool, smart: bool) {
+        self.is_shuffle = shuffle;
+        self.smart_shuffle = shuffle && smart;
+        self.shuffle = shuffle::ShuffleState::default();
+    }
+
+    fn shuffle_label(&self) -> &'static str {
+        match (self.is_shuffle, self.smart_shuffle) {
+            (false, _) => "выключено",
+            (true, false) => "случайное",
+            (true, true) => "умное (исполнители и жанры вразброс)",
         }
     }
 
-    fn next_song(&mut self) -> Option<&Song> {
+    fn current_song_id(&self) -> Option<SongId> {
+        self.songs.get(self.current_index?).copied()
+    }
+
+    // Переход по кнопке "следующий": на краю плейлиста продолжаем только в режимах повтора
+    fn next_song(&mut self, library: &Library) -> Option<SongId> {
         if self.songs.is_empty() {
             return None;
         }
 
         if self.is_shuffle {
-            let mut rng = rand::thread_rng();
-            self.current_index = Some(rng.gen_range(0..self.songs.len()));
+            let strategy = if self.smart_shuffle { shuffle::Strategy::Spread } else { shuffle::Strategy::Random };
+            let songs: Vec<Option<&Song>> = self.songs.iter().map(|&id| library.song(id)).collect();
+            let next = self.shuffle.next(&songs, self.current_index, self.repeat.wraps(), strategy)?;
+            self.current_index = Some(next);
         } else {
-            self.current_index = match self.current_index {
-                Some(index) => Some((index + 1) % self.songs.len()),
-                None => Some(0),
+            let next = match self.current_index {
+                Some(index) if index + 1 < self.songs.len() => index + 1,
+                Some(_) if !self.repeat.wraps() => return None,
+                Some(_) | None => 0,
             };
+            self.current_index = Some(next);
         }
 
-        self.get_current_song()
+        self.current_song_id()
     }
 
-    fn previous_song(&mut self) -> Option<&Song> {
+    fn previous_song(&mut self) -> Option<SongId> {
         if self.songs.is_empty() {
             return None;
         }
 
-        self.current_index = match self.current_index {
-            Some(index) => {
-                if index == 0 {
-                    Some(self.songs.len() - 1)
-                } else {
-                    Some(index - 1)
-                }
-            }
-            None => Some(0),
+        // В перемешанном порядке "назад" - это назад по истории
+        if self.is_shuffle {
+            self.current_index = Some(self.shuffle.previous(self.current_index)?);
+            return self.current_song_id();
+        }
+
+        let previous = match self.current_index {
+            Some(0) if !self.repeat.wraps() => return None,
+            Some(0) => self.songs.len() - 1,
+            Some(index) => index - 1,
+            None => 0,
         };
+        self.current_index = Some(previous);
 
-        self.get_current_song()
+        self.current_song_id()
     }
 
-    fn get_total_duration(&self) -> u32 {
-        self.songs.iter().map(|song| song.duration).sum()
+    // Что играть, когда трек доигран до конца; None - остановиться.
+    // На последнем треке Off и StopAtEnd ведут себя одинаково, а посреди плейлиста
+    // Off переходит к следующему треку, StopAtEnd - останавливается.
+    fn track_finished(&mut self, library: &Library) -> Option<SongId> {
+        match self.repeat {
+            RepeatMode::One => self.current_song_id(),
+            RepeatMode::StopAtEnd => None,
+            RepeatMode::Off | RepeatMode::All => self.next_song(library),
+        }
     }
 
-    fn display_info(&self) -> String {
-        let total_duration = self.get_total_duration();
-        let total_minutes = total_duration / 60;
-        let total_seconds = total_duration % 60;
-        
-        format!("📁 {} ({} треков, {:02}:{:02})", 
-                self.name, self.songs.len(), total_minutes, total_seconds)
+    fn get_total_duration(&self, library: &Library) -> u32 {
+        self.songs.iter().filter_map(|&id| library.song(id)).map(|song| song.duration).sum()
+    }
+
+    fn display_info(&self, library: &Library) -> String {
+        let icon = if self.rules.is_some() { "🧠" } else { "📁" };
+        format!("{} {} ({} треков, {})", 
+                icon, self.name, self.songs.len(), format_duration(self.get_total_duration(library)))
     }
 }
 
 impl MusicPlayer {
     fn new() -> Self {
         let mut player = MusicPlayer {
-            library: Vec::new(),
+            library: Library::new(),
             playlists: HashMap::new(),
             current_playlist: None,
             volume: 50,
+            library_roots: Vec::new(),
+            scan_cache: HashMap::new(),
+            index: browse::LibraryIndex::default(),
+            search_index: search_index::SearchIndex::default(),
+            output: output::SinkKind::Null { realtime: true },
+            engine: engine::Engine::spawn(Box::new(output::NullSink::new(true)), 50),
+            position_ms: 0,
+            paused: false,
+            queue: Vec::new(),
+            from_queue: None,
+            playing: false,
+            state_path: None,
+            dirty: false,
         };
 
         // Добавляем демо-композиции
@@ -141,208 +533,1592 @@ impl MusicPlayer {
         player
     }
 
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut player = match storage::load(path)? {
+            Some(state) => {
+                let library = Library::from_songs(state.library);
+                MusicPlayer {
+                    index: browse::LibraryIndex::build(&library),
+                    search_index: search_index::SearchIndex::build(&library),
+                    library,
+                    playlists: state.playlists,
+                    current_playlist: state.current_playlist,
+                    volume: state.volume.min(100),
+                    library_roots: state.library_roots,
+                    scan_cache: state.scan_cache,
+                    output: output::SinkKind::Null { realtime: true },
+                    engine: engine::Engine::spawn(Box::new(output::NullSink::new(true)), state.volume.min(100)),
+                    position_ms: 0,
+                    paused: false,
+                    queue: state.queue,
+                    from_queue: None,
+                    playing: false,
+                    state_path: None,
+                    dirty: false,
+                }
+            }
+            None => {
+                // Первый запуск: начинаем с демо-композиций и сразу сохраняем их
+                let mut player = MusicPlayer::new();
+                player.dirty = true;
+                player
+            }
+        };
+
+        if let Some(playlist_name) = &player.current_playlist {
+            if !player.playlists.contains_key(playlist_name) {
+                player.current_playlist = None;
+            }
+        }
+        // Файл могли править вручную: ссылки на отсутствующие песни убираем сразу
+        player.remove_dangling();
+        // Правила вроде "не звучал N дней" зависят от текущего времени
+        player.refresh_smart_playlists();
+
+        player.state_path = Some(path.to_path_buf());
+        Ok(player)
+    }
+
+    // Вызывается, только если состояние менялось (dirty)
+    fn save(&mut self) -> io::Result<()> {
+        if let Some(path) = &self.state_path {
+            let state = storage::StateRef {
+                version: storage::SCHEMA_VERSION,
+                library: &self.library,
+                playlists: &self.playlists,
+                current_playlist: &self.current_playlist,
+                volume: self.volume,
+                library_roots: &self.library_roots,
+                scan_cache: &self.scan_cache,
+                queue: &self.queue,
+            };
+            storage::save(path, &state)?;
+        }
+
+        self.dirty = false;
+        Ok(())
+    }
+
     fn add_demo_songs(&mut self) {
         let demo_songs = vec![
-            Song::new("Bohemian Rhapsody".to_string(), "Queen".to_string(), 354, "Rock".to_string(), 1975, "queen_bohemian.mp3".to_string()),
-            Song::new("Stairway to Heaven".to_string(), "Led Zeppelin".to_string(), 482, "Rock".to_string(), 1971, "lz_stairway.mp3".to_string()),
-            Song::new("Hotel California".to_string(), "Eagles".to_string(), 391, "Rock".to_string(), 1976, "eagles_hotel.mp3".to_string()),
-            Song::new("Imagine".to_string(), "John Lennon".to_string(), 183, "Pop".to_string(), 1971, "lennon_imagine.mp3".to_string()),
-            Song::new("Sweet Child O' Mine".to_string(), "Guns N' Roses".to_string(), 356, "Rock".to_string(), 1987, "gnr_sweet_child.mp3".to_string()),
-            Song::new("Billie Jean".to_string(), "Michael Jackson".to_string(), 294, "Pop".to_string(), 1982, "mj_billie_jean.mp3".to_string()),
-            Song::new("Smells Like Teen Spirit".to_string(), "Nirvana".to_string(), 301, "Grunge".to_string(), 1991, "nirvana_teen_spirit.mp3".to_string()),
-            Song::new("Yesterday".to_string(), "The Beatles".to_string(), 125, "Pop".to_string(), 1965, "beatles_yesterday.mp3".to_string()),
+            Song::new("Bohemian Rhapsody".to_string(), "Queen".to_string(), 354, "Rock".to_string(), 1975, "queen_bohemian.mp3".to_string()).with_album("A Night at the Opera", 11),
+            Song::new("Stairway to Heaven".to_string(), "Led Zeppelin".to_string(), 482, "Rock".to_string(), 1971, "lz_stairway.mp3".to_string()).with_album("Led Zeppelin IV", 4),
+            Song::new("Hotel California".to_string(), "Eagles".to_string(), 391, "Rock".to_string(), 1976, "eagles_hotel.mp3".to_string()).with_album("Hotel California", 1),
+            Song::new("Imagine".to_string(), "John Lennon".to_string(), 183, "Pop".to_string(), 1971, "lennon_imagine.mp3".to_string()).with_album("Imagine", 1),
+            Song::new("Sweet Child O' Mine".to_string(), "Guns N' Roses".to_string(), 356, "Rock".to_string(), 1987, "gnr_sweet_child.mp3".to_string()).with_album("Appetite for Destruction", 9),
+            Song::new("Billie Jean".to_string(), "Michael Jackson".to_string(), 294, "Pop".to_string(), 1982, "mj_billie_jean.mp3".to_string()).with_album("Thriller", 6),
+            Song::new("Smells Like Teen Spirit".to_string(), "Nirvana".to_string(), 301, "Grunge".to_string(), 1991, "nirvana_teen_spirit.mp3".to_string()).with_album("Nevermind", 1),
+            Song::new("Yesterday".to_string(), "The Beatles".to_string(), 125, "Pop".to_string(), 1965, "beatles_yesterday.mp3".to_string()).with_album("Help!", 13),
         ];
 
-        self.library = demo_songs;
+        for song in demo_songs {
+            let song = self.library.add(song);
+            self.index.add(song);
+            self.search_index.add(song);
+        }
+
+        // Демо-плейлисты собираются по жанру и пополняются вместе с библиотекой
+        let by_genre = |genres: &[&str]| smart::SmartRules {
+            rules: vec![smart::Rule::GenreIn(genres.iter().map(|genre| genre.to_string()).collect())],
+            combine: smart::Combine::All,
+            sort: None,
+            limit: None,
+        };
+        for playlist in [
+            Playlist::smart("🎸 Rock Classics".to_string(), by_genre(&["Rock", "Grunge"])),
+            Playlist::smart("🎤 Pop Hits".to_string(), by_genre(&["Pop"])),
+        ] {
+            self.playlists.insert(playlist.name.clone(), playlist);
+        }
+        self.refresh_smart_playlists();
+    }
+
+    fn scan_library(&mut self, roots: &[PathBuf]) -> scanner::ScanReport {
+        let roots: Vec<PathBuf> = roots.iter().map(|root| scanner::canonical(root)).collect();
+        self.canonicalize_paths();
+        let known_paths: HashSet<String> = self.library.iter().map(|song| song.path.clone()).collect();
+
+        // Кэшу можно верить только для файлов, которые всё ещё в библиотеке
+        self.scan_cache.retain(|path, _| known_paths.contains(path));
+
+        let mut report = scanner::ScanReport::default();
+        let found = scanner::scan(&roots, &self.scan_cache, &mut report);
+
+        // Файлы, удалённые из просканированных папок, уходят из библиотеки. Файл, который
+        // есть, но не прочитался (нет прав на папку и т.п.), остаётся.
+        let found_paths: HashSet<&str> = found.iter().map(|file| file.path.as_str()).collect();
+        let removed = self.library.remove_if(|song| {
+            let path = Path::new(&song.path);
+            roots.iter().any(|root| path.starts_with(root)) && !found_paths.contains(song.path.as_str()) && !path.exists()
+        });
+        for song in &removed {
+            self.index.remove(song);
+            self.search_index.remove(song);
+            self.scan_cache.remove(&song.path);
+        }
+        report.removed = removed.len();
+
+        let mut updated: HashMap<String, Vec<Song>> = HashMap::new();
+        let mut added = Vec::new();
+        for file in found {
+            if let Some(songs) = file.songs {
+                if known_paths.contains(&file.path) {
+                    updated.insert(file.path.clone(), songs);
+                } else {
+                    added.extend(songs);
+                }
+            }
+            self.scan_cache.insert(file.path, file.stamp);
+        }
+
+        // Из одного файла может получиться несколько песен (CUE), поэтому песни
+        // изменившегося файла заменяются целиком, на месте первой из них.
+        // Трек с прежним ключом сохраняет свой id, поэтому плейлисты на него не теряют.
+        // Старые треки убираем из индексов заранее: у нового трека может быть тот же id.
+        let mut old_ids: HashMap<String, SongId> = HashMap::new();
+        for song in self.library.iter().filter(|song| updated.contains_key(&song.path)) {
+            self.index.remove(song);
+            self.search_index.remove(song);
+            old_ids.insert(song.key(), song.id);
+        }
+
+        let mut replaced = HashSet::new();
+        for song in self.library.take() {
+            if let Some(songs) = updated.remove(&song.path) {
+                replaced.insert(song.path);
+                for mut song in songs {
+                    song.id = old_ids.get(&song.key()).copied().unwrap_or(0);
+                    let song = self.library.add(song);
+                    self.index.add(song);
+                    self.search_index.add(song);
+                    report.updated += 1;
+                }
+            } else if !replaced.contains(&song.path) {
+                self.library.add(song);
+            }
+        }
+
+        for mut song in added {
+            song.id = 0;
+            let song = self.library.add(song);
+            self.index.add(song);
+            self.search_index.add(song);
+            report.added += 1;
+        }
+
+        // Треки, пропавшие из CUE или вместе с файлом, пропадают и из плейлистов
+        self.remove_dangling();
+        self.refresh_smart_playlists();
+
+        for root in &roots {
+            let root = root.to_string_lossy().into_owned();
+            if !self.library_roots.contains(&root) {
+                self.library_roots.push(root);
+            }
+        }
+
+        self.dirty = true;
+        report
+    }
+
+    // Пути, сохранённые до канонизации (или другим написанием корня), приводим к виду,
+    // который выдаёт сканер, вместе с ключами кэша и сохранёнными папками
+    fn canonicalize_paths(&mut self) {
+        let ids: Vec<SongId> = self.library.iter().map(|song| song.id).collect();
+        for id in ids {
+            let Some(song) = self.library.song_mut(id) else {
+                continue;
+            };
+            let canonical = scanner::canonical(Path::new(&song.path)).to_string_lossy().into_owned();
+            if canonical != song.path {
+                let old = std::mem::replace(&mut song.path, canonical.clone());
+                if let Some(stamp) = self.scan_cache.remove(&old) {
+                    self.scan_cache.insert(canonical, stamp);
+                }
+            }
+        }
+
+        let mut roots = Vec::new();
+        for root in self.library_roots.drain(..) {
+            let root = scanner::canonical(Path::new(&root)).to_string_lossy().into_owned();
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        self.library_roots = roots;
+    }
+
+    // Новый вывод создаётся до замены старого: при ошибке продолжаем играть туда же
+    fn set_output(&mut self, kind: output::SinkKind) -> io::Result<()> {
+        self.engine.send(engine::Command::SetSink(kind.create()?));
+        self.output = kind;
+        Ok(())
+    }
+
+    fn sort_library(&mut self) {
+        self.library.sort_by(|a, b| a.album_order(b));
+        self.dirty = true;
+    }
+
+    // Синтаксис запроса описан в query.rs; результаты отсортированы по релевантности
+    fn search_songs(&self, query: &str) -> Result<Vec<&Song>, query::ParseError> {
+        Ok(query::Query::parse(query)?.search(&self.library, &self.search_index))
+    }
+
+    fn create_playlist(&mut self, name: String) -> bool {
+        if self.playlists.contains_key(&name) {
+            false
+        } else {
+            self.playlists.insert(name.clone(), Playlist::new(name));
+            self.dirty = true;
+            true
+        }
+    }
+
+    // Правка плейлиста; состояние помечается изменённым, только если edit что-то вернул
+    fn edit_playlist<T>(&mut self, name: &str, edit: impl FnOnce(&mut Playlist) -> Option<T>) -> Option<T> {
+        let result = edit(self.playlists.get_mut(name)?)?;
+        self.dirty = true;
+        Some(result)
+    }
+
+    fn rename_playlist(&mut self, old_name: &str, new_name: String) -> bool {
+        if new_name.is_empty() || self.playlists.contains_key(&new_name) {
+            return false;
+        }
+        let Some(mut playlist) = self.playlists.remove(old_name) else {
+            return false;
+        };
+
+        playlist.name = new_name.clone();
+        if self.current_playlist.as_deref() == Some(old_name) {
+            self.current_playlist = Some(new_name.clone());
+        }
+        self.playlists.insert(new_name, playlist);
+        self.dirty = true;
+        true
+    }
+
+    // Удаление активного плейлиста останавливает его трек; песня из очереди доигрывает
+    fn delete_playlist(&mut self, name: &str) -> bool {
+        if self.playlists.remove(name).is_none() {
+            return false;
+        }
+        if self.current_playlist.as_deref() == Some(name) {
+            if self.from_queue.is_none() {
+                self.stop();
+            }
+            self.current_playlist = None;
+        }
+        self.dirty = true;
+        true
+    }
+
+    // Копия получает песни и режимы, но начинает воспроизведение с начала
+    fn duplicate_playlist(&mut self, name: &str, new_name: String) -> bool {
+        if new_name.is_empty() || self.playlists.contains_key(&new_name) {
+            return false;
+        }
+        let Some(original) = self.playlists.get(name) else {
+            return false;
+        };
+
+        let mut copy = Playlist::new(new_name.clone());
+        copy.songs = original.songs.clone();
+        copy.is_shuffle = original.is_shuffle;
+        copy.smart_shuffle = original.smart_shuffle;
+        copy.repeat = original.repeat;
+        copy.rules = original.rules.clone();
+        self.playlists.insert(new_name, copy);
+        self.dirty = true;
+        true
+    }
+
+    // Состав умного плейлиста задаётся правилами, вручную в него не добавить
+    fn add_song_to_playlist(&mut self, playlist_name: &str, song_index: usize) -> bool {
+        if let Some(song) = self.library.get(song_index) {
+            if let Some(playlist) = self.playlists.get_mut(playlist_name).filter(|playlist| playlist.rules.is_none()) {
+                playlist.add_song(song.id);
+                self.dirty = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Имя плейлиста берётся из имени файла; при совпадении добавляется номер
+    fn import_playlist(&mut self, path: &Path) -> io::Result<(String, playlist_files::ImportReport)> {
+        let entries = playlist_files::read_entries(path)?;
+        let (songs, report) = playlist_files::resolve(&entries, path, &self.library);
+
+        let stem = path.file_stem().map_or_else(|| "Импорт".to_string(), |stem| stem.to_string_lossy().into_owned());
+        let mut name = stem.clone();
+        let mut counter = 2;
+        while self.playlists.contains_key(&name) {
+            name = format!("{} ({})", stem, counter);
+            counter += 1;
+        }
+
+        let mut playlist = Playlist::new(name.clone());
+        for song in songs {
+            playlist.add_song(song);
+        }
+        self.playlists.insert(name.clone(), playlist);
+        self.dirty = true;
+
+        Ok((name, report))
+    }
+
+    fn export_playlist(&self, playlist_name: &str, path: &Path) -> io::Result<usize> {
+        let playlist = self.playlists.get(playlist_name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("плейлист '{}' не найден", playlist_name)))?;
+        let songs: Vec<&Song> = playlist.songs.iter().filter_map(|&id| self.library.song(id)).collect();
+        playlist_files::write_songs(path, &songs)?;
+        Ok(songs.len())
+    }
+
+    fn play_playlist(&mut self, playlist_name: &str) -> bool {
+        if self.playlists.contains_key(playlist_name) {
+            self.current_playlist = Some(playlist_name.to_string());
+            self.from_queue = None;
+            self.dirty = true;
+            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
+                // С перемешиванием первый трек тоже выбирается случайно
+                if playlist.current_index.is_none() {
+                    playlist.next_song(&self.library);
+                }
+            }
+            self.play_current();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Ставит песни библиотеки в очередь: в конец или сразу после текущего трека.
+    // Если ничего не играет, очередь сразу запускается.
+    fn enqueue(&mut self, ids: &[SongId], play_next: bool) -> usize {
+        let songs: Vec<SongId> = ids.iter().copied().filter(|&id| self.library.contains(id)).collect();
+
+        let count = songs.len();
+        if play_next {
+            self.queue.splice(0..0, songs);
+        } else {
+            self.queue.extend(songs);
+        }
+        if count > 0 {
+            self.dirty = true;
+            if !self.playing && !self.paused {
+                self.play_from_queue();
+            }
+        }
+        count
+    }
+
+    fn queue_move(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.queue.len() || to >= self.queue.len() {
+            return false;
+        }
+        let song = self.queue.remove(from);
+        self.queue.insert(to, song);
+        self.dirty = true;
+        true
+    }
+
+    fn queue_remove(&mut self, index: usize) -> Option<SongId> {
+        if index >= self.queue.len() {
+            return None;
+        }
+        self.dirty = true;
+        Some(self.queue.remove(index))
+    }
+
+    fn queue_clear(&mut self) {
+        self.queue.clear();
+        self.dirty = true;
+    }
+
+    fn play_from_queue(&mut self) -> Option<String> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        let id = self.queue.remove(0);
+        let message = format!("▶️ Играет из очереди: {}", self.song_line(id));
+        self.from_queue = Some(id);
+        self.dirty = true;
+        self.play_current();
+        Some(message)
+    }
+
+    // Строка для списков; песня могла пропасть из библиотеки, а ссылка на неё - остаться
+    fn song_line(&self, id: SongId) -> String {
+        self.library.song(id).map_or_else(|| format!("❓ Песня #{} отсутствует в библиотеке", id), Song::display)
+    }
+
+    // Убирает из плейлистов и очереди ссылки на песни, которых нет в библиотеке.
+    // Если пропала играющая песня, воспроизведение останавливается.
+    fn remove_dangling(&mut self) -> usize {
+        if self.current_song_id().is_some_and(|id| !self.library.contains(id)) {
+            self.stop();
+            self.from_queue = None;
+        }
+
+        let mut removed = 0;
+        for playlist in self.playlists.values_mut() {
+            removed += playlist.remove_dangling(&self.library);
+        }
+        let queued = self.queue.len();
+        self.queue.retain(|&id| self.library.contains(id));
+        removed += queued - self.queue.len();
+
+        if removed > 0 {
+            self.dirty = true;
+        }
+        removed
+    }
 
-        // Создаем демо-плейлисты
-        let mut rock_playlist = Playlist::new("🎸 Rock Classics".to_string());
-        let mut pop_playlist = Playlist::new("🎤 Pop Hits".to_string());
+    // Отправляет движку текущую песню: из очереди или из активного плейлиста.
+    // Играющей она считается сразу, иначе до TrackStarted enqueue запустил бы вторую.
+    fn play_current(&mut self) {
+        if let Some(song) = self.get_current_song().cloned() {
+            self.position_ms = 0;
+            self.paused = false;
+            self.engine.play(song);
+            self.set_playing(true);
+        }
+    }
+
+    // Применяет накопившиеся события движка; возвращает сообщения для показа пользователю
+    fn poll_events(&mut self) -> Vec<String> {
+        let mut messages = Vec::new();
+        for event in self.engine.poll() {
+            match event {
+                engine::Event::TrackStarted => self.count_play(),
+                // Доигранный трек сменяется следующим без участия пользователя
+                engine::Event::TrackEnded => {
+                    self.set_playing(false);
+                    let message = self.track_finished();
+                    messages.push(message.unwrap_or_else(|| "⏹️ Воспроизведение завершено".to_string()));
+                }
+                engine::Event::PositionChanged(position) => self.position_ms = position,
+                engine::Event::Skipped(direction) => messages.push(self.skipped(direction)),
+                engine::Event::Error(message) => {
+                    self.set_playing(false);
+                    messages.push(format!("⚠️ Ошибка воспроизведения: {}", message));
+                }
+            }
+        }
+        messages
+    }
 
-        for song in &self.library {
-            match song.genre.as_str() {
-                "Rock" | "Grunge" => rock_playlist.add_song(song.clone()),
-                "Pop" => pop_playlist.add_song(song.clone()),
-                _ => {}
+    fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    fn pause(&mut self) -> bool {
+        if !self.is_playing() || self.paused {
+            return false;
+        }
+        self.engine.send(engine::Command::Pause);
+        self.paused = true;
+        true
+    }
+
+    // После остановки или ошибки трек начинается заново
+    fn resume(&mut self) -> bool {
+        if self.paused {
+            self.engine.send(engine::Command::Resume);
+            self.paused = false;
+            return true;
+        }
+        if self.is_playing() || self.get_current_song().is_none() {
+            return false;
+        }
+        self.play_current();
+        true
+    }
+
+    fn stop(&mut self) {
+        self.engine.stop();
+        self.set_playing(false);
+        self.paused = false;
+        self.position_ms = 0;
+    }
+
+    // Перемотка к позиции от начала трека; за пределы трека не выходит
+    fn seek_to(&mut self, position_ms: u64) -> bool {
+        let Some(song) = self.get_current_song() else {
+            return false;
+        };
+        if !self.is_playing() {
+            return false;
+        }
+        let position_ms = position_ms.min(song.duration as u64 * 1000);
+        self.engine.send(engine::Command::Seek(position_ms));
+        self.position_ms = position_ms;
+        true
+    }
+
+    fn seek_by(&mut self, delta_ms: i64) -> bool {
+        self.seek_to(self.position_ms.saturating_add_signed(delta_ms))
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        // Песня из очереди не принадлежит плейлисту
+        let playlist_playing = playing && self.from_queue.is_none();
+        for (name, playlist) in self.playlists.iter_mut() {
+            playlist.is_playing = playlist_playing && self.current_playlist.as_ref() == Some(name);
+        }
+    }
+
+    fn current_song_id(&self) -> Option<SongId> {
+        if self.from_queue.is_some() {
+            return self.from_queue;
+        }
+        let playlist = self.playlists.get(self.current_playlist.as_ref()?)?;
+        playlist.current_song_id()
+    }
+
+    fn get_current_song(&self) -> Option<&Song> {
+        self.library.song(self.current_song_id()?)
+    }
+
+    // Играющий трек обрывает движок и отвечает Skipped; в остановленном плеере
+    // соседний трек выбирается сразу, и сообщение возвращается здесь
+    fn next_song(&mut self) -> Option<String> {
+        self.skip(engine::Direction::Next)
+    }
+
+    fn previous_song(&mut self) -> Option<String> {
+        self.skip(engine::Direction::Previous)
+    }
+
+    fn skip(&mut self, direction: engine::Direction) -> Option<String> {
+        if !self.is_playing() {
+            return Some(self.skipped(direction));
+        }
+        self.engine.send(match direction {
+            engine::Direction::Next => engine::Command::Next,
+            engine::Direction::Previous => engine::Command::Previous,
+        });
+        None
+    }
+
+    // Движок оборвал трек по Next или Previous: запускаем соседний.
+    // За последним треком воспроизведение останавливается, первый начинается заново.
+    fn skipped(&mut self, direction: engine::Direction) -> String {
+        let message = match direction {
+            engine::Direction::Next => self.play_next(),
+            engine::Direction::Previous => self.play_previous(),
+        };
+        if let Some(message) = message {
+            return message;
+        }
+        match direction {
+            engine::Direction::Next => {
+                self.stop();
+                "⏹️ Это последний трек плейлиста".to_string()
+            }
+            engine::Direction::Previous if self.get_current_song().is_some() => {
+                self.play_current();
+                "⏮️ Это первый трек плейлиста, играет сначала".to_string()
+            }
+            engine::Direction::Previous => "❌ Нечего воспроизводить!".to_string(),
+        }
+    }
+
+    fn play_next(&mut self) -> Option<String> {
+        if let Some(message) = self.play_from_queue() {
+            return Some(message);
+        }
+        // После очереди плейлист продолжается с того места, где его прервали
+        self.from_queue = None;
+        if let Some(playlist_name) = &self.current_playlist.clone() {
+            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
+                if let Some(id) = playlist.next_song(&self.library) {
+                    let message = format!("▶️ Играет: {}", self.song_line(id));
+                    self.dirty = true;
+                    self.play_current();
+                    return Some(message);
+                }
+            }
+        }
+        None
+    }
+
+    fn play_previous(&mut self) -> Option<String> {
+        // Из очереди "назад" возвращает к прерванному треку плейлиста
+        if self.from_queue.take().is_some() {
+            let message = self.get_current_song().map(|song| format!("▶️ Играет: {}", song.display()));
+            self.play_current();
+            return message;
+        }
+        if let Some(playlist_name) = &self.current_playlist.clone() {
+            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
+                if let Some(id) = playlist.previous_song() {
+                    let message = format!("▶️ Играет: {}", self.song_line(id));
+                    self.dirty = true;
+                    self.play_current();
+                    return Some(message);
+                }
+            }
+        }
+        None
+    }
+
+    // Автопереход после доигранного трека с учётом режима повтора и очереди
+    fn track_finished(&mut self) -> Option<String> {
+        let repeat = self.current_playlist.as_ref()
+            .and_then(|name| self.playlists.get(name))
+            .map_or(RepeatMode::Off, |playlist| playlist.repeat);
+
+        match repeat {
+            RepeatMode::StopAtEnd => return None,
+            // Повтор трека относится к трекам плейлиста, песни очереди играют один раз
+            RepeatMode::One if self.from_queue.is_none() => {}
+            _ => return self.play_next(),
+        }
+
+        let playlist_name = self.current_playlist.clone()?;
+        let playlist = self.playlists.get_mut(&playlist_name)?;
+        let id = playlist.track_finished(&self.library)?;
+        let message = format!("▶️ Играет: {}", self.song_line(id));
+        self.dirty = true;
+        self.play_current();
+        Some(message)
+    }
+
+    fn set_repeat(&mut self, repeat: RepeatMode) -> bool {
+        let Some(playlist) = self.current_playlist.as_ref().and_then(|name| self.playlists.get_mut(name)) else {
+            return false;
+        };
+        playlist.repeat = repeat;
+        self.dirty = true;
+        true
+    }
+
+    // Переключает по кругу: выключено → случайное → умное → выключено
+    fn toggle_shuffle(&mut self) -> Option<&'static str> {
+        let playlist_name = self.current_playlist.clone()?;
+        let playlist = self.playlists.get_mut(&playlist_name)?;
+        match (playlist.is_shuffle, playlist.smart_shuffle) {
+            (false, _) => playlist.set_shuffle(true, false),
+            (true, false) => playlist.set_shuffle(true, true),
+            (true, true) => playlist.set_shuffle(false, false),
+        }
+        self.dirty = true;
+        Some(playlist.shuffle_label())
+    }
+
+    fn count_play(&mut self) {
+        let Some(id) = self.current_song_id() else {
+            return;
+        };
+        if let Some(song) = self.library.song_mut(id) {
+            song.play_count += 1;
+            song.last_played = Some(smart::now_secs());
+            self.dirty = true;
+            self.refresh_smart_playlists();
+        }
+    }
+
+    fn rate_current_song(&mut self, rating: u8) -> bool {
+        let Some(song) = self.current_song_id().and_then(|id| self.library.song_mut(id)) else {
+            return false;
+        };
+        song.rating = rating.min(5);
+        self.dirty = true;
+        self.refresh_smart_playlists();
+        true
+    }
+
+    // Пересчитывает умные плейлисты; вызывается после любого изменения библиотеки
+    fn refresh_smart_playlists(&mut self) {
+        let now = smart::now_secs();
+        // Из играющего плейлиста текущий трек не выкидываем
+        let playing = if self.from_queue.is_none() && (self.playing || self.paused) {
+            self.current_playlist.clone()
+        } else {
+            None
+        };
+
+        for (name, playlist) in self.playlists.iter_mut() {
+            let Some(rules) = &playlist.rules else {
+                continue;
+            };
+            let ids = rules.evaluate(&self.library, now);
+            if playlist.set_songs(ids, playing.as_ref() == Some(name)) {
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn create_smart_playlist(&mut self, name: String, rules: smart::SmartRules) -> bool {
+        if name.is_empty() || self.playlists.contains_key(&name) {
+            return false;
+        }
+        self.playlists.insert(name.clone(), Playlist::smart(name, rules));
+        self.refresh_smart_playlists();
+        self.dirty = true;
+        true
+    }
+
+    fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(100);
+        self.engine.send(engine::Command::SetVolume(self.volume));
+        self.dirty = true;
+    }
+
+    fn get_recommendations(&self) -> Vec<&Song> {
+        if let Some(current_song) = self.get_current_song() {
+            // Рекомендуем песни того же жанра или артиста
+            self.library.iter()
+                .filter(|song| {
+                    song.genre == current_song.genre || 
+                    song.artist == current_song.artist
+                })
+                .take(5)
+                .collect()
+        } else {
+            // Случайные рекомендации
+            let mut rng = rand::thread_rng();
+            let mut songs: Vec<&Song> = self.library.iter().collect();
+            songs.sort_by_key(|_| rng.gen::<u32>());
+            songs.into_iter().take(5).collect()
+        }
+    }
+}
+
+fn main() {
+    println!("🎵 МУЗЫКАЛЬНЫЙ ПЛЕЕР");
+    println!("==================");
+    println!();
+
+    let mut player = match MusicPlayer::open(Path::new(storage::STATE_FILE)) {
+        Ok(player) => player,
+        Err(e) => {
+            // Не перезаписываем повреждённый файл: работаем без сохранения
+            println!("❌ Не удалось загрузить {}: {}", storage::STATE_FILE, e);
+            MusicPlayer::new()
+        }
+    };
+    let mut input = String::new();
+
+    loop {
+        print_menu();
+        input.clear();
+
+        // Пока пользователь думает, продолжаем обрабатывать события воспроизведения
+        let read = loop {
+            let _ = io::stdout().flush();
+            if let Some(read) = read_input_timeout(&mut input, EVENT_POLL_INTERVAL) {
+                break read;
+            }
+            for message in player.poll_events() {
+                println!("\n{}", message);
+                print!("Выберите действие: ");
+            }
+        };
+
+        match read {
+            Ok(_) => {
+                for message in player.poll_events() {
+                    println!("{}", message);
+                }
+
+                let choice = input.trim();
+                match choice {
+                    "1" => {
+                        show_library(&player);
+                        add_from_library_menu(&mut player);
+                    }
+                    "2" => show_playlists(&player),
+                    "3" => search_music(&mut player),
+                    "4" => create_new_playlist(&mut player),
+                    "5" => play_playlist_menu(&mut player),
+                    "6" => control_playback(&mut player),
+                    "7" => manage_volume(&mut player),
+                    "8" => show_recommendations(&player),
+                    "9" => show_current_status(&player),
+                    "10" => scan_folders_menu(&mut player),
+                    "11" => {
+                        player.sort_library();
+                        println!("🔤 Библиотека отсортирована по альбомам");
+                        show_library(&player);
+                    }
+                    "12" => browse_menu(&mut player),
+                    "13" => playlist_files_menu(&mut player),
+                    "14" => output_menu(&mut player),
+                    "15" => queue_menu(&mut player),
+                    "16" => edit_playlist_menu(&mut player),
+                    "17" => create_smart_playlist_menu(&mut player),
+                    "0" => {
+                        save_if_dirty(&mut player);
+                        println!("👋 До свидания!");
+                        break;
+                    }
+                    _ => println!("❌ Неверный выбор!"),
+                }
+            }
+            Err(_) => println!("❌ Ошибка ввода!"),
+        }
+
+        save_if_dirty(&mut player);
+
+        println!("\nНажмите Enter для продолжения...");
+        input.clear();
+        let _ = read_input(&mut player, &mut input);
+    }
+}
+
+// Поиск, просмотр и прочие действия без изменений файл не переписывают
+fn save_if_dirty(player: &mut MusicPlayer) {
+    if player.dirty {
+        if let Err(e) = player.save() {
+            println!("❌ Не удалось сохранить состояние: {}", e);
+        }
+    }
+}
+
+// Как часто главное меню проверяет события движка, ожидая ввода
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// stdin читается в отдельном потоке, чтобы ожидание ввода можно было прервать по таймауту
+fn input_lines() -> &'static Mutex<Receiver<String>> {
+    static LINES: OnceLock<Mutex<Receiver<String>>> = OnceLock::new();
+    LINES.get_or_init(|| {
+        let (lines, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if lines.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
+}
+
+// Аналог io::stdin().read_line: конец ввода - Ok(0). Пока строки нет, применяет
+// события движка, чтобы треки сменялись и в подменю, и на любом вопросе
+fn read_input(player: &mut MusicPlayer, input: &mut String) -> io::Result<usize> {
+    loop {
+        let _ = io::stdout().flush();
+        if let Some(read) = read_input_timeout(input, EVENT_POLL_INTERVAL) {
+            return read;
+        }
+        for message in player.poll_events() {
+            println!("\n{}", message);
+        }
+    }
+}
+
+// None - за отведённое время ничего не введено
+fn read_input_timeout(input: &mut String, timeout: Duration) -> Option<io::Result<usize>> {
+    let lines = input_lines().lock().unwrap_or_else(|e| e.into_inner());
+    match lines.recv_timeout(timeout) {
+        Ok(line) => {
+            input.push_str(&line);
+            Some(Ok(line.len()))
+        }
+        Err(RecvTimeoutError::Timeout) => None,
+        Err(RecvTimeoutError::Disconnected) => Some(Ok(0)),
+    }
+}
+
+fn print_menu() {
+    println!("\n🎵 ГЛАВНОЕ МЕНЮ:");
+    println!("1. 📚 Библиотека");
+    println!("2. 📁 Плейлисты");
+    println!("3. 🔍 Поиск");
+    println!("4. ➕ Создать плейлист");
+    println!("5. ▶️ Воспроизвести плейлист");
+    println!("6. 🎮 Управление воспроизведением");
+    println!("7. 🔊 Громкость");
+    println!("8. 💡 Рекомендации");
+    println!("9. 📊 Текущий статус");
+    println!("10. 📂 Сканировать папки с музыкой");
+    println!("11. 🔤 Сортировать библиотеку по альбомам");
+    println!("12. 🎤 Исполнители и альбомы");
+    println!("13. 💾 Импорт/экспорт плейлистов");
+    println!("14. 🔈 Вывод звука");
+    println!("15. 📋 Очередь воспроизведения");
+    println!("16. ✏️ Редактировать плейлист");
+    println!("17. 🧠 Создать умный плейлист");
+    println!("0. 🚪 Выход");
+    print!("\nВыберите действие: ");
+}
+
+fn show_library(player: &MusicPlayer) {
+    println!("\n📚 БИБЛИОТЕКА ({} треков):", player.library.len());
+    println!("{}", "=".repeat(50));
+    
+    for (i, song) in player.library.iter().enumerate() {
+        println!("{}. {}", i + 1, song.display());
+    }
+}
+
+fn add_from_library_menu(player: &mut MusicPlayer) {
+    let mut playlist_names: Vec<String> = player.playlists.values()
+        .filter(|playlist| playlist.rules.is_none())
+        .map(|playlist| playlist.name.clone())
+        .collect();
+    if player.library.is_empty() || playlist_names.is_empty() {
+        return;
+    }
+    playlist_names.sort();
+
+    println!("\n➕ Номер трека, чтобы добавить его в плейлист (Enter - назад):");
+    let Some(song_index) = read_choice(player, player.library.len()) else {
+        return;
+    };
+    println!("📁 Выберите плейлист:");
+    for (i, name) in playlist_names.iter().enumerate() {
+        println!("{}. {}", i + 1, name);
+    }
+    let Some(choice) = read_choice(player, playlist_names.len()) else {
+        println!("❌ Неверный выбор!");
+        return;
+    };
+    if player.add_song_to_playlist(&playlist_names[choice], song_index) {
+        println!("✅ Добавлено в '{}': {}", playlist_names[choice], player.library[song_index].display());
+    }
+}
+
+// Номер в списке из строки ввода: "1" - первый элемент
+fn parse_position(input: &str, len: usize) -> Option<usize> {
+    input.trim().parse::<usize>().ok().filter(|&n| n >= 1 && n <= len).map(|n| n - 1)
+}
+
+fn read_choice(player: &mut MusicPlayer, count: usize) -> Option<usize> {
+    let mut input = String::new();
+    read_input(player, &mut input).ok()?;
+    parse_position(&input, count)
+}
+
+fn browse_menu(player: &mut MusicPlayer) {
+    // Копия: пока ждём ввода, плеер обрабатывает события и занят изменяемой ссылкой
+    let artists: Vec<browse::ArtistEntry> = player.index.artists().into_iter().cloned().collect();
+    if artists.is_empty() {
+        println!("❌ Библиотека пуста!");
+        return;
+    }
+
+    println!("\n🎤 ИСПОЛНИТЕЛИ ({}):", artists.len());
+    println!("{}", "=".repeat(50));
+    for (i, artist) in artists.iter().enumerate() {
+        println!("{}. {}", i + 1, artist.display_info());
+    }
+    println!("Выберите исполнителя:");
+
+    let Some(artist_choice) = read_choice(player, artists.len()) else {
+        println!("❌ Неверный выбор!");
+        return;
+    };
+    let artist = &artists[artist_choice];
+    let albums = artist.albums();
+
+    println!("\n{}", artist.display_info());
+    println!("{}", "=".repeat(50));
+    for (i, album) in albums.iter().enumerate() {
+        println!("{}. {}", i + 1, album.display_info());
+    }
+    println!("{}. ▶️ Поставить в очередь всего исполнителя", albums.len() + 1);
+    println!("Выберите альбом:");
+
+    let Some(album_choice) = read_choice(player, albums.len() + 1) else {
+        println!("❌ Неверный выбор!");
+        return;
+    };
+
+    let ids: Vec<SongId> = if album_choice == albums.len() {
+        artist.ids()
+    } else {
+        let album = albums[album_choice];
+        println!("\n{}", album.display_info());
+        println!("{}", "=".repeat(50));
+
+        for (i, id) in album.ids().into_iter().enumerate() {
+            println!("{}. {}", i + 1, player.song_line(id));
+        }
+
+        println!("\n▶️ Поставить альбом в очередь? (y/n)");
+        let mut input = String::new();
+        if read_input(player, &mut input).is_err() || !input.trim().eq_ignore_ascii_case("y") {
+            return;
+        }
+        album.ids()
+    };
+
+    let was_playing = player.is_playing();
+    let count = player.enqueue(&ids, false);
+    println!("✅ Добавлено в очередь: {} (всего в очереди {})", count, player.queue.len());
+    if !was_playing {
+        if let Some(song) = player.get_current_song() {
+            println!("▶️ Играет: {}", song.display());
+        }
+    }
+}
+
+fn playlist_files_menu(player: &mut MusicPlayer) {
+    println!("\n💾 ИМПОРТ/ЭКСПОРТ ПЛЕЙЛИСТОВ:");
+    println!("1. 📥 Импортировать файл (.m3u, .m3u8, .pls, .xspf)");
+    println!("2. 📤 Экспортировать плейлист");
+    println!("3. 🔙 Назад");
+
+    let mut input = String::new();
+    if read_input(player, &mut input).is_err() {
+        return;
+    }
+
+    match input.trim() {
+        "1" => {
+            println!("📥 Введите путь к файлу плейлиста:");
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() {
+                return;
+            }
+
+            match player.import_playlist(Path::new(input.trim())) {
+                Ok((name, report)) => {
+                    println!("✅ Плейлист '{}' создан: {} треков", name, report.matched);
+                    if !report.missing.is_empty() {
+                        println!("⚠️ Не найдено в библиотеке: {}", report.missing.len());
+                        for (location, exists) in &report.missing {
+                            let reason = if *exists { "нет в библиотеке" } else { "файл не найден" };
+                            println!("  {} ({})", location, reason);
+                        }
+                    }
+                }
+                Err(e) => println!("❌ Не удалось импортировать: {}", e),
+            }
+        }
+        "2" => {
+            let playlist_names: Vec<String> = player.playlists.keys().cloned().collect();
+            if playlist_names.is_empty() {
+                println!("❌ Нет доступных плейлистов!");
+                return;
+            }
+            println!("📤 Выберите плейлист:");
+            for (i, name) in playlist_names.iter().enumerate() {
+                println!("{}. {}", i + 1, name);
+            }
+            let Some(choice) = read_choice(player, playlist_names.len()) else {
+                println!("❌ Неверный выбор!");
+                return;
+            };
+
+            println!("📤 Введите путь для сохранения (.m3u, .m3u8, .pls, .xspf):");
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() {
+                return;
+            }
+
+            match player.export_playlist(&playlist_names[choice], Path::new(input.trim())) {
+                Ok(count) => println!("✅ Сохранено треков: {}", count),
+                Err(e) => println!("❌ Не удалось экспортировать: {}", e),
+            }
+        }
+        "3" => {}
+        _ => println!("❌ Неверный выбор!"),
+    }
+}
+
+fn output_menu(player: &mut MusicPlayer) {
+    println!("\n🔈 ВЫВОД ЗВУКА (сейчас: {}):", player.output);
+    println!("1. 🔇 Без звука, в реальном времени");
+    println!("2. ⏩ Без звука, максимальная скорость");
+    println!("3. 💾 Запись в WAV-файл");
+    #[cfg(feature = "alsa")]
+    println!("4. 🔊 ALSA");
+
+    let mut input = String::new();
+    if read_input(player, &mut input).is_err() {
+        return;
+    }
+
+    let kind = match input.trim() {
+        "1" => output::SinkKind::Null { realtime: true },
+        "2" => output::SinkKind::Null { realtime: false },
+        "3" => {
+            println!("💾 Введите путь к WAV-файлу:");
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() || input.trim().is_empty() {
+                return;
+            }
+            output::SinkKind::WavFile(PathBuf::from(input.trim()))
+        }
+        #[cfg(feature = "alsa")]
+        "4" => {
+            println!("🔊 Введите устройство ALSA (Enter - default):");
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() {
+                return;
+            }
+            let device = if input.trim().is_empty() { "default" } else { input.trim() };
+            output::SinkKind::Alsa(device.to_string())
+        }
+        _ => {
+            println!("❌ Неверный выбор!");
+            return;
+        }
+    };
+
+    match player.set_output(kind) {
+        Ok(()) => println!("✅ Вывод: {}", player.output),
+        Err(e) => println!("❌ Не удалось переключить вывод: {}", e),
+    }
+}
+
+fn show_queue(player: &MusicPlayer) {
+    if player.queue.is_empty() {
+        println!("📋 Очередь пуста");
+        return;
+    }
+    println!("\n📋 ОЧЕРЕДЬ ({} треков):", player.queue.len());
+    println!("{}", "=".repeat(50));
+    for (i, &id) in player.queue.iter().enumerate() {
+        println!("{}. {}", i + 1, player.song_line(id));
+    }
+}
+
+fn queue_menu(player: &mut MusicPlayer) {
+    show_queue(player);
+    println!("\n1. ➕ Добавить в конец очереди");
+    println!("2. ⏭️ Играть следующим");
+    println!("3. ↕️ Переместить трек");
+    println!("4. ➖ Убрать трек");
+    println!("5. 🗑️ Очистить очередь");
+    println!("6. ↩️ Назад");
+
+    let mut input = String::new();
+    if read_input(player, &mut input).is_err() {
+        return;
+    }
+
+    match input.trim() {
+        choice @ ("1" | "2") => {
+            println!("🔍 Введите поисковый запрос:");
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() {
+                return;
+            }
+            let Some(results) = search_or_report(player, input.trim()) else {
+                return;
+            };
+            if results.is_empty() {
+                println!("❌ Ничего не найдено для '{}'", input.trim());
+                return;
+            }
+            for (i, song) in results.iter().enumerate() {
+                println!("{}. {}", i + 1, song.display());
+            }
+            let ids: Vec<SongId> = results.iter().map(|song| song.id).collect();
+            println!("Номер трека (Enter - все найденные):");
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() {
+                return;
+            }
+            let ids = if input.trim().is_empty() {
+                ids
+            } else if let Some(index) = parse_position(&input, ids.len()) {
+                vec![ids[index]]
+            } else {
+                println!("❌ Неверный номер трека!");
+                return;
+            };
+
+            let was_playing = player.is_playing();
+            let count = player.enqueue(&ids, choice == "2");
+            println!("✅ Добавлено в очередь: {}", count);
+            if !was_playing {
+                if let Some(song) = player.get_current_song() {
+                    println!("▶️ Играет: {}", song.display());
+                }
+            }
+        }
+        "3" => {
+            println!("↕️ Введите номер трека и новую позицию через пробел:");
+            match read_two_positions(player, player.queue.len()) {
+                Some((from, to)) if player.queue_move(from, to) => {
+                    println!("✅ Трек перемещён на позицию {}", to + 1);
+                }
+                _ => println!("❌ Неверные позиции!"),
+            }
+        }
+        "4" => {
+            println!("➖ Введите номер трека:");
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() {
+                return;
+            }
+            match parse_position(&input, player.queue.len()).and_then(|index| player.queue_remove(index)) {
+                Some(id) => println!("✅ Убран из очереди: {}", player.song_line(id)),
+                None => println!("❌ Неверный номер трека!"),
+            }
+        }
+        "5" => {
+            player.queue_clear();
+            println!("✅ Очередь очищена");
+        }
+        "6" => {}
+        _ => println!("❌ Неверный выбор!"),
+    }
+}
+
+// Читает строку; None - ошибка ввода или пустая строка
+fn read_name(player: &mut MusicPlayer) -> Option<String> {
+    let mut input = String::new();
+    read_input(player, &mut input).ok()?;
+    let name = input.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// Два номера через пробел, например "3 1"
+fn read_two_positions(player: &mut MusicPlayer, len: usize) -> Option<(usize, usize)> {
+    let mut input = String::new();
+    read_input(player, &mut input).ok()?;
+    match input.split_whitespace().map(|part| parse_position(part, len)).collect::<Vec<_>>()[..] {
+        [Some(a), Some(b)] => Some((a, b)),
+        _ => None,
+    }
+}
+
+fn edit_playlist_menu(player: &mut MusicPlayer) {
+    let mut playlist_names: Vec<String> = player.playlists.keys().cloned().collect();
+    if playlist_names.is_empty() {
+        println!("❌ Нет доступных плейлистов!");
+        return;
+    }
+    playlist_names.sort();
+
+    println!("✏️ Выберите плейлист:");
+    for (i, name) in playlist_names.iter().enumerate() {
+        println!("{}. {}", i + 1, name);
+    }
+    let Some(choice) = read_choice(player, playlist_names.len()) else {
+        println!("❌ Неверный выбор!");
+        return;
+    };
+    let name = playlist_names.swap_remove(choice);
+
+    let Some(playlist) = player.playlists.get(&name) else {
+        return;
+    };
+    let len = playlist.songs.len();
+    println!("\n{}", playlist.display_info(&player.library));
+    println!("{}", "=".repeat(50));
+    for (i, &id) in playlist.songs.iter().enumerate() {
+        let marker = if playlist.current_index == Some(i) { "▶️" } else { "  " };
+        println!("{} {}. {}", marker, i + 1, player.song_line(id));
+    }
+
+    if let Some(rules) = &playlist.rules {
+        println!("🧠 Правила: {}", rules.describe());
+    }
+    let smart = playlist.rules.is_some();
+
+    println!("\n1. ↕️ Переместить трек");
+    println!("2. 🔃 Поменять треки местами");
+    println!("3. ➖ Убрать трек");
+    println!("4. 🔤 Сортировать");
+    println!("5. 🧹 Убрать повторы");
+    println!("6. 🏷️ Переименовать");
+    println!("7. 📑 Дублировать");
+    println!("8. 🗑️ Удалить плейлист");
+    println!("9. ↩️ Назад");
+
+    let mut input = String::new();
+    if read_input(player, &mut input).is_err() {
+        return;
+    }
+
+    let choice = input.trim();
+    if smart && matches!(choice, "1" | "2" | "3" | "4" | "5") {
+        println!("❌ Состав умного плейлиста задаётся правилами");
+        return;
+    }
+
+    match choice {
+        "1" => {
+            println!("↕️ Введите номер трека и новую позицию через пробел:");
+            match read_two_positions(player, len) {
+                Some((from, to)) if player.edit_playlist(&name, |playlist| playlist.move_song(from, to).then_some(())).is_some() => {
+                    println!("✅ Трек перемещён на позицию {}", to + 1);
+                }
+                _ => println!("❌ Неверные позиции!"),
+            }
+        }
+        "2" => {
+            println!("🔃 Введите номера двух треков через пробел:");
+            match read_two_positions(player, len) {
+                Some((a, b)) if player.edit_playlist(&name, |playlist| playlist.swap_songs(a, b).then_some(())).is_some() => {
+                    println!("✅ Треки {} и {} поменялись местами", a + 1, b + 1);
+                }
+                _ => println!("❌ Неверные позиции!"),
+            }
+        }
+        "3" => {
+            println!("➖ Введите номер трека:");
+            let removed = read_choice(player, len)
+                .and_then(|index| player.edit_playlist(&name, |playlist| playlist.remove_song(index)));
+            match removed {
+                Some(id) => println!("✅ Убран из плейлиста: {}", player.song_line(id)),
+                None => println!("❌ Неверный номер трека!"),
+            }
+        }
+        "4" => {
+            println!("🔤 Сортировать по:");
+            for (i, field) in SortField::ALL.iter().enumerate() {
+                println!("{}. {}", i + 1, field.label());
+            }
+            let Some(field) = read_choice(player, SortField::ALL.len()).map(|i| SortField::ALL[i]) else {
+                println!("❌ Неверный выбор!");
+                return;
+            };
+            println!("1. По возрастанию");
+            println!("2. По убыванию");
+            let Some(direction) = read_choice(player, 2) else {
+                println!("❌ Неверный выбор!");
+                return;
+            };
+            if let Some(playlist) = player.playlists.get_mut(&name) {
+                playlist.sort_by_field(field, direction == 1, &player.library);
+                player.dirty = true;
+                println!("✅ Плейлист отсортирован: {}", field.label());
             }
         }
-
-        self.playlists.insert("Rock Classics".to_string(), rock_playlist);
-        self.playlists.insert("Pop Hits".to_string(), pop_playlist);
-    }
-
-    fn search_songs(&self, query: &str) -> Vec<&Song> {
-        let query = query.to_lowercase();
-        self.library.iter()
-            .filter(|song| {
-                song.title.to_lowercase().contains(&query) ||
-                song.artist.to_lowercase().contains(&query) ||
-                song.genre.to_lowercase().contains(&query)
-            })
-            .collect()
-    }
-
-    fn create_playlist(&mut self, name: String) -> bool {
-        if self.playlists.contains_key(&name) {
-            false
-        } else {
-            self.playlists.insert(name.clone(), Playlist::new(name));
-            true
+        "5" => {
+            let removed = player.edit_playlist(&name, |playlist| Some(playlist.remove_duplicates()).filter(|&removed| removed > 0));
+            println!("✅ Убрано повторов: {}", removed.unwrap_or(0));
         }
-    }
-
-    fn add_song_to_playlist(&mut self, playlist_name: &str, song_index: usize) -> bool {
-        if let Some(song) = self.library.get(song_index) {
-            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
-                playlist.add_song(song.clone());
-                return true;
+        "6" => {
+            println!("🏷️ Введите новое название:");
+            match read_name(player) {
+                Some(new_name) if player.rename_playlist(&name, new_name.clone()) => {
+                    println!("✅ Плейлист '{}' переименован в '{}'", name, new_name);
+                }
+                _ => println!("❌ Название пустое или уже занято!"),
             }
         }
-        false
-    }
-
-    fn play_playlist(&mut self, playlist_name: &str) -> bool {
-        if self.playlists.contains_key(playlist_name) {
-            self.current_playlist = Some(playlist_name.to_string());
-            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
-                playlist.is_playing = true;
-                if playlist.current_index.is_none() && !playlist.songs.is_empty() {
-                    playlist.current_index = Some(0);
+        "7" => {
+            println!("📑 Введите название копии:");
+            match read_name(player) {
+                Some(new_name) if player.duplicate_playlist(&name, new_name.clone()) => {
+                    println!("✅ Создана копия '{}'", new_name);
                 }
+                _ => println!("❌ Название пустое или уже занято!"),
             }
-            true
-        } else {
-            false
         }
-    }
-
-    fn get_current_song(&self) -> Option<&Song> {
-        if let Some(playlist_name) = &self.current_playlist {
-            if let Some(playlist) = self.playlists.get(playlist_name) {
-                return playlist.get_current_song();
+        "8" => {
+            println!("🗑️ Удалить плейлист '{}'? (да/нет)", name);
+            let mut input = String::new();
+            if read_input(player, &mut input).is_err() {
+                return;
+            }
+            if matches!(input.trim().to_lowercase().as_str(), "да" | "д" | "y" | "yes") && player.delete_playlist(&name) {
+                println!("✅ Плейлист '{}' удалён", name);
+            } else {
+                println!("❌ Удаление отменено");
             }
         }
-        None
+        "9" => {}
+        _ => println!("❌ Неверный выбор!"),
     }
+}
 
-    fn next_song(&mut self) -> Option<String> {
-        if let Some(playlist_name) = &self.current_playlist.clone() {
-            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
-                if let Some(song) = playlist.next_song() {
-                    return Some(format!("▶️ Играет: {}", song.display()));
+// Читает число; None - ошибка ввода или не число
+fn read_number<T: std::str::FromStr>(player: &mut MusicPlayer) -> Option<T> {
+    let mut input = String::new();
+    read_input(player, &mut input).ok()?;
+    input.trim().parse().ok()
+}
+
+fn read_smart_rule(player: &mut MusicPlayer) -> Option<smart::Rule> {
+    println!("1. Жанр из списка");
+    println!("2. Год в диапазоне");
+    println!("3. Исполнитель содержит");
+    println!("4. Короче, чем");
+    println!("5. Оценка не ниже");
+    println!("6. Не звучал N дней");
+    println!("7. Прослушиваний не меньше");
+    println!("8. Прослушиваний меньше");
+    println!("9. Поисковый запрос (как в поиске)");
+
+    let mut input = String::new();
+    read_input(player, &mut input).ok()?;
+    match input.trim() {
+        "1" => {
+            println!("Жанры через запятую:");
+            let genres: Vec<String> = read_name(player)?.split(',')
+                .map(|genre| genre.trim().to_string())
+                .filter(|genre| !genre.is_empty())
+                .collect();
+            Some(smart::Rule::GenreIn(genres))
+        }
+        "2" => {
+            println!("Годы через пробел, например \"1970 1979\":");
+            let mut input = String::new();
+            read_input(player, &mut input).ok()?;
+            let years: Vec<u16> = input.split_whitespace().filter_map(|year| year.parse().ok()).collect();
+            match years[..] {
+                [from, to] => Some(smart::Rule::YearBetween(from.min(to), from.max(to))),
+                _ => None,
+            }
+        }
+        "3" => {
+            println!("Часть имени исполнителя:");
+            Some(smart::Rule::ArtistMatches(read_name(player)?))
+        }
+        "4" => {
+            println!("Длительность (3:30 или 210):");
+            let mut input = String::new();
+            read_input(player, &mut input).ok()?;
+            Some(smart::Rule::DurationUnder(parse_time(input.trim())?))
+        }
+        "5" => {
+            println!("Оценка от 1 до 5:");
+            Some(smart::Rule::RatingAtLeast(read_number::<u8>(player).filter(|rating| (1..=5).contains(rating))?))
+        }
+        "6" => {
+            println!("Сколько дней:");
+            Some(smart::Rule::NotPlayedFor(read_number(player)?))
+        }
+        "7" => {
+            println!("Сколько прослушиваний:");
+            Some(smart::Rule::PlayCountAtLeast(read_number(player)?))
+        }
+        "8" => {
+            println!("Сколько прослушиваний:");
+            Some(smart::Rule::PlayCountBelow(read_number(player)?))
+        }
+        "9" => {
+            println!("Запрос, например: genre:rock year:..1979 -live");
+            let text = read_name(player)?;
+            match query::Query::parse(&text) {
+                Ok(query) => Some(smart::Rule::Query(query)),
+                Err(e) => {
+                    print_query_error(&text, &e);
+                    None
                 }
             }
         }
-        None
+        _ => None,
+    }
+}
+
+fn create_smart_playlist_menu(player: &mut MusicPlayer) {
+    println!("🧠 Введите название умного плейлиста:");
+    let Some(name) = read_name(player) else {
+        println!("❌ Название не может быть пустым!");
+        return;
+    };
+    if player.playlists.contains_key(&name) {
+        println!("❌ Плейлист с таким названием уже существует!");
+        return;
     }
 
-    fn previous_song(&mut self) -> Option<String> {
-        if let Some(playlist_name) = &self.current_playlist.clone() {
-            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
-                if let Some(song) = playlist.previous_song() {
-                    return Some(format!("▶️ Играет: {}", song.display()));
-                }
+    let mut rules = Vec::new();
+    loop {
+        println!("\n➕ Добавить условие? (y/n)");
+        let mut input = String::new();
+        if read_input(player, &mut input).is_err() || !input.trim().eq_ignore_ascii_case("y") {
+            break;
+        }
+        match read_smart_rule(player) {
+            Some(rule) => {
+                println!("✅ Условие: {}", rule.describe());
+                rules.push(rule);
             }
+            None => println!("❌ Неверное условие!"),
         }
-        None
     }
 
-    fn toggle_shuffle(&mut self) -> bool {
-        if let Some(playlist_name) = &self.current_playlist.clone() {
-            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
-                playlist.is_shuffle = !playlist.is_shuffle;
-                return playlist.is_shuffle;
-            }
+    let mut combine = smart::Combine::All;
+    if rules.len() > 1 {
+        println!("1. Все условия (И)");
+        println!("2. Любое условие (ИЛИ)");
+        if read_choice(player, 2) == Some(1) {
+            combine = smart::Combine::Any;
         }
-        false
     }
 
-    fn set_volume(&mut self, volume: u8) {
-        self.volume = volume.min(100);
+    println!("🔤 Сортировать по (Enter - порядок библиотеки):");
+    for (i, field) in SortField::ALL.iter().enumerate() {
+        println!("{}. {}", i + 1, field.label());
     }
+    let sort = read_choice(player, SortField::ALL.len()).map(|i| {
+        println!("1. По возрастанию");
+        println!("2. По убыванию");
+        (SortField::ALL[i], read_choice(player, 2) == Some(1))
+    });
 
-    fn get_recommendations(&self) -> Vec<&Song> {
-        if let Some(current_song) = self.get_current_song() {
-            // Рекомендуем песни того же жанра или артиста
-            self.library.iter()
-                .filter(|song| {
-                    song.genre == current_song.genre || 
-                    song.artist == current_song.artist
-                })
-                .take(5)
-                .collect()
-        } else {
-            // Случайные рекомендации
-            let mut rng = rand::thread_rng();
-            let mut songs: Vec<&Song> = self.library.iter().collect();
-            songs.sort_by_key(|_| rng.gen::<u32>());
-            songs.into_iter().take(5).collect()
-        }
+    println!("🔢 Не больше скольких треков? (Enter - без ограничения)");
+    let limit = read_number::<usize>(player).filter(|&limit| limit > 0);
+
+    let rules = smart::SmartRules { rules, combine, sort, limit };
+    let description = rules.describe();
+    if player.create_smart_playlist(name.clone(), rules) {
+        let count = player.playlists.get(&name).map_or(0, |playlist| playlist.songs.len());
+        println!("✅ Умный плейлист '{}' создан: {} треков", name, count);
+        println!("🧠 {}", description);
     }
 }
 
-fn main() {
-    println!("🎵 МУЗЫКАЛЬНЫЙ ПЛЕЕР");
-    println!("==================");
-    println!();
+fn scan_folders_menu(player: &mut MusicPlayer) {
+    if !player.library_roots.is_empty() {
+        println!("📂 Сохранённые папки:");
+        for root in &player.library_roots {
+            println!("  {}", root);
+        }
+    }
+    println!("📂 Введите папки через ';' (Enter - пересканировать сохранённые):");
 
-    let mut player = MusicPlayer::new();
     let mut input = String::new();
+    if read_input(player, &mut input).is_err() {
+        return;
+    }
 
-    loop {
-        print_menu();
-        input.clear();
-        
-        match io::stdin().read_line(&mut input) {
-            Ok(_) => {
-                let choice = input.trim();
-                match choice {
-                    "1" => show_library(&player),
-                    "2" => show_playlists(&player),
-                    "3" => search_music(&player),
-                    "4" => create_new_playlist(&mut player),
-                    "5" => play_playlist_menu(&mut player),
-                    "6" => control_playback(&mut player),
-                    "7" => manage_volume(&mut player),
-                    "8" => show_recommendations(&player),
-                    "9" => show_current_status(&player),
-                    "0" => {
-                        println!("👋 До свидания!");
-                        break;
-                    }
-                    _ => println!("❌ Неверный выбор!"),
-                }
-            }
-            Err(_) => println!("❌ Ошибка ввода!"),
-        }
+    let roots: Vec<PathBuf> = if input.trim().is_empty() {
+        player.library_roots.iter().map(PathBuf::from).collect()
+    } else {
+        input.split(';')
+            .map(str::trim)
+            .filter(|root| !root.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    };
 
-        println!("\nНажмите Enter для продолжения...");
-        input.clear();
-        let _ = io::stdin().read_line(&mut input);
+    if roots.is_empty() {
+        println!("❌ Не указано ни одной папки!");
+        return;
     }
-}
 
-fn print_menu() {
-    println!("\n🎵 ГЛАВНОЕ МЕНЮ:");
-    println!("1. 📚 Библиотека");
-    println!("2. 📁 Плейлисты");
-    println!("3. 🔍 Поиск");
-    println!("4. ➕ Создать плейлист");
-    println!("5. ▶️ Воспроизвести плейлист");
-    println!("6. 🎮 Управление воспроизведением");
-    println!("7. 🔊 Громкость");
-    println!("8. 💡 Рекомендации");
-    println!("9. 📊 Текущий статус");
-    println!("0. 🚪 Выход");
-    print!("\nВыберите действие: ");
-}
+    let report = player.scan_library(&roots);
+    println!("\n✅ Сканирование завершено:");
+    println!("  ➕ Добавлено: {}", report.added);
+    println!("  🔄 Обновлено: {}", report.updated);
+    println!("  ⏸️ Без изменений: {}", report.unchanged);
+    println!("  🗑️ Удалено (файлов больше нет): {}", report.removed);
+    println!("  👯 Дубликатов: {}", report.duplicates);
 
-fn show_library(player: &MusicPlayer) {
-    println!("\n📚 БИБЛИОТЕКА ({} треков):", player.library.len());
-    println!("{}", "=".repeat(50));
-    
-    for (i, song) in player.library.iter().enumerate() {
-        println!("{}. {}", i + 1, song.display());
+    if !report.skipped.is_empty() {
+        println!("  ⚠️ Пропущено: {}", report.skipped.len());
+        for (path, reason) in &report.skipped {
+            println!("    {}: {}", path, reason);
+        }
+    }
+
+    if !report.warnings.is_empty() {
+        println!("  🏷️ Предупреждений в тегах: {}", report.warnings.len());
+        for (path, warning) in &report.warnings {
+            println!("    {}: {}", path, warning);
+        }
     }
 }
 
@@ -356,30 +2132,52 @@ fn show_playlists(player: &MusicPlayer) {
     }
 
     for playlist in player.playlists.values() {
-        println!("{}", playlist.display_info());
+        println!("{}", playlist.display_info(&player.library));
         
         if let Some(current_playlist) = &player.current_playlist {
             if playlist.name == *current_playlist {
                 println!("  ▶️ Сейчас играет");
-                if let Some(song) = playlist.get_current_song() {
-                    println!("  🎵 {}", song.display());
+                if let Some(id) = playlist.current_song_id() {
+                    println!("  🎵 {}", player.song_line(id));
                 }
             }
         }
         
+        if let Some(rules) = &playlist.rules {
+            println!("  🧠 Правила: {}", rules.describe());
+        }
         if playlist.is_shuffle {
-            println!("  🔀 Случайный порядок");
+            println!("  🔀 Перемешивание: {}", playlist.shuffle_label());
         }
         println!();
     }
 }
 
-fn search_music(player: &MusicPlayer) {
-    println!("🔍 Введите поисковый запрос:");
+// Показывает ошибку разбора под самим запросом
+fn print_query_error(query: &str, error: &query::ParseError) {
+    println!("❌ Ошибка в запросе: {}", error);
+    println!("   {}", query);
+    println!("   {}", error.pointer());
+}
+
+fn search_or_report<'a>(player: &'a MusicPlayer, query: &str) -> Option<Vec<&'a Song>> {
+    match player.search_songs(query) {
+        Ok(results) => Some(results),
+        Err(e) => {
+            print_query_error(query, &e);
+            None
+        }
+    }
+}
+
+fn search_music(player: &mut MusicPlayer) {
+    println!("🔍 Введите поисковый запрос (например: artist:queen year:1970..1980 -live):");
     let mut input = String::new();
-    if io::stdin().read_line(&mut input).is_ok() {
+    if read_input(player, &mut input).is_ok() {
         let query = input.trim();
-        let results = player.search_songs(query);
+        let Some(results) = search_or_report(player, query) else {
+            return;
+        };
         
         if results.is_empty() {
             println!("❌ Ничего не найдено для '{}'", query);
@@ -396,7 +2194,7 @@ fn search_music(player: &MusicPlayer) {
 fn create_new_playlist(player: &mut MusicPlayer) {
     println!("➕ Введите название нового плейлиста:");
     let mut input = String::new();
-    if io::stdin().read_line(&mut input).is_ok() {
+    if read_input(player, &mut input).is_ok() {
         let name = input.trim().to_string();
         if player.create_playlist(name.clone()) {
             println!("✅ Плейлист '{}' создан!", name);
@@ -413,21 +2211,22 @@ fn play_playlist_menu(player: &mut MusicPlayer) {
     }
 
     println!("▶️ Выберите плейлист для воспроизведения:");
-    let playlist_names: Vec<_> = player.playlists.keys().collect();
+    let playlist_names: Vec<String> = player.playlists.keys().cloned().collect();
     
     for (i, name) in playlist_names.iter().enumerate() {
         println!("{}. {}", i + 1, name);
     }
 
     let mut input = String::new();
-    if io::stdin().read_line(&mut input).is_ok() {
+    if read_input(player, &mut input).is_ok() {
         if let Ok(choice) = input.trim().parse::<usize>() {
             if choice > 0 && choice <= playlist_names.len() {
-                let playlist_name = playlist_names[choice - 1];
+                let playlist_name = &playlist_names[choice - 1];
                 if player.play_playlist(playlist_name) {
                     println!("🎵 Воспроизводится: {}", playlist_name);
-                    if let Some(message) = player.next_song() {
-                        println!("{}", message);
+                    // play_playlist уже запустил текущий (или первый) трек
+                    if let Some(song) = player.get_current_song() {
+                        println!("▶️ Играет: {}", song.display());
                     }
                 }
             } else {
@@ -438,7 +2237,7 @@ fn play_playlist_menu(player: &mut MusicPlayer) {
 }
 
 fn control_playback(player: &mut MusicPlayer) {
-    if player.current_playlist.is_none() {
+    if player.current_playlist.is_none() && player.from_queue.is_none() && player.queue.is_empty() {
         println!("❌ Не выбран плейлист для воспроизведения!");
         return;
     }
@@ -447,10 +2246,15 @@ fn control_playback(player: &mut MusicPlayer) {
     println!("1. ⏭️ Следующий трек");
     println!("2. ⏮️ Предыдущий трек");
     println!("3. 🔀 Переключить перемешивание");
-    println!("4. 🔙 Назад");
+    println!("4. ⏯️ Пауза / продолжить");
+    println!("5. ⏹️ Стоп");
+    println!("6. ⏩ Перемотка");
+    println!("7. 🔁 Режим повтора");
+    println!("8. ⭐ Оценить трек");
+    println!("9. 🔙 Назад");
 
     let mut input = String::new();
-    if io::stdin().read_line(&mut input).is_ok() {
+    if read_input(player, &mut input).is_ok() {
         match input.trim() {
             "1" => {
                 if let Some(message) = player.next_song() {
@@ -463,21 +2267,100 @@ fn control_playback(player: &mut MusicPlayer) {
                 }
             }
             "3" => {
-                let shuffle_status = player.toggle_shuffle();
-                println!("🔀 Перемешивание: {}", if shuffle_status { "включено" } else { "выключено" });
+                if let Some(label) = player.toggle_shuffle() {
+                    println!("🔀 Перемешивание: {}", label);
+                }
+            }
+            "4" => {
+                if player.pause() {
+                    println!("⏸️ Пауза");
+                } else if player.resume() {
+                    println!("▶️ Воспроизведение продолжено");
+                } else {
+                    println!("❌ Нечего воспроизводить!");
+                }
+            }
+            "5" => {
+                player.stop();
+                println!("⏹️ Остановлено");
+            }
+            "6" => {
+                println!("⏩ Введите позицию (1:30 или 90) или сдвиг в секундах (+10, -10):");
+                let mut input = String::new();
+                if read_input(player, &mut input).is_err() {
+                    return;
+                }
+                let input = input.trim();
+                let seeked = if let Some(delta) = input.strip_prefix('+').and_then(|delta| delta.parse::<i64>().ok()) {
+                    player.seek_by(delta * 1000)
+                } else if let Some(delta) = input.strip_prefix('-').and_then(|delta| delta.parse::<i64>().ok()) {
+                    player.seek_by(-delta * 1000)
+                } else if let Some(position) = parse_time(input) {
+                    player.seek_to(position as u64 * 1000)
+                } else {
+                    println!("❌ Неверное значение!");
+                    return;
+                };
+
+                if seeked {
+                    println!("⏩ Позиция: {}", format_duration((player.position_ms / 1000) as u32));
+                } else {
+                    println!("❌ Сейчас ничего не играет!");
+                }
+            }
+            "7" => {
+                println!("🔁 Выберите режим повтора:");
+                for (i, mode) in RepeatMode::ALL.iter().enumerate() {
+                    println!("{}. {}", i + 1, mode.label());
+                }
+                let Some(choice) = read_choice(player, RepeatMode::ALL.len()) else {
+                    println!("❌ Неверный выбор!");
+                    return;
+                };
+                if player.set_repeat(RepeatMode::ALL[choice]) {
+                    println!("🔁 Повтор: {}", RepeatMode::ALL[choice].label());
+                }
+            }
+            "8" => {
+                println!("⭐ Оценка от 1 до 5 (0 - снять оценку):");
+                let mut input = String::new();
+                if read_input(player, &mut input).is_err() {
+                    return;
+                }
+                match input.trim().parse::<u8>() {
+                    Ok(rating) if rating <= 5 => {
+                        if player.rate_current_song(rating) {
+                            println!("⭐ Оценка: {}", rating);
+                        } else {
+                            println!("❌ Сейчас ничего не играет!");
+                        }
+                    }
+                    _ => println!("❌ Неверное значение!"),
+                }
             }
-            "4" => return,
+            "9" => {}
             _ => println!("❌ Неверный выбор!"),
         }
     }
 }
 
+// "90" или "1:30" - в секундах
+fn parse_time(text: &str) -> Option<u32> {
+    match text.split_once(':') {
+        Some((minutes, seconds)) => {
+            let seconds: u32 = seconds.parse().ok().filter(|&seconds| seconds < 60)?;
+            Some(minutes.parse::<u32>().ok()? * 60 + seconds)
+        }
+        None => text.parse().ok(),
+    }
+}
+
 fn manage_volume(player: &mut MusicPlayer) {
     println!("🔊 Текущая громкость: {}%", player.volume);
     println!("Введите новое значение (0-100):");
     
     let mut input = String::new();
-    if io::stdin().read_line(&mut input).is_ok() {
+    if read_input(player, &mut input).is_ok() {
         if let Ok(volume) = input.trim().parse::<u8>() {
             player.set_volume(volume);
             println!("🔊 Громкость установлена: {}%", player.volume);
@@ -501,16 +2384,32 @@ fn show_current_status(player: &MusicPlayer) {
     println!("\n📊 ТЕКУЩИЙ СТАТУС:");
     println!("{}", "=".repeat(50));
     println!("🔊 Громкость: {}%", player.volume);
+
+    if let Some(song) = player.get_current_song() {
+        let state = if player.paused {
+            "⏸️ На паузе"
+        } else if player.is_playing() {
+            "🎵 Сейчас играет"
+        } else {
+            "⏹️ Остановлено"
+        };
+        let source = if player.from_queue.is_some() { " (из очереди)" } else { "" };
+        println!("{}{}: {}", state, source, song.display());
+        println!("⏱️ {} / {}", format_duration((player.position_ms / 1000) as u32), song.format_duration());
+        if song.rating > 0 {
+            println!("⭐ Оценка: {}", song.rating);
+        }
+    }
+    if !player.queue.is_empty() {
+        println!("📋 В очереди: {} треков, следующий: {}", player.queue.len(), player.song_line(player.queue[0]));
+    }
     
     if let Some(playlist_name) = &player.current_playlist {
         println!("📁 Активный плейлист: {}", playlist_name);
         
         if let Some(playlist) = player.playlists.get(playlist_name) {
-            if let Some(song) = playlist.get_current_song() {
-                println!("🎵 Сейчас играет: {}", song.display());
-            }
-            
-            println!("🔀 Перемешивание: {}", if playlist.is_shuffle { "включено" } else { "выключено" });
+            println!("🔀 Перемешивание: {}", playlist.shuffle_label());
+            println!("🔁 Повтор: {}", playlist.repeat.label());
             println!("📊 Прогресс: {} / {}", 
                      playlist.current_index.map_or(0, |i| i + 1), 
                      playlist.songs.len());
@@ -522,3 +2421,327 @@ fn show_current_status(player: &MusicPlayer) {
     println!("📚 Всего треков в библиотеке: {}", player.library.len());
     println!("📁 Всего плейлистов: {}", player.playlists.len());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("player_test_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Моно 16 бит, 1000 Гц, секунда тишины
+    fn write_wav(path: &Path) {
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(36u32 + 2000).to_le_bytes());
+        file.extend_from_slice(b"WAVEfmt ");
+        for field in [16u32, 0x0001_0001, 1000, 2000, 0x0010_0002] {
+            file.extend_from_slice(&field.to_le_bytes());
+        }
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&2000u32.to_le_bytes());
+        file.resize(44 + 2000, 0);
+        fs::write(path, file).unwrap();
+    }
+
+    // Тот же путь, но относительно текущей папки
+    fn relative(path: &Path) -> PathBuf {
+        let depth = std::env::current_dir().unwrap().components().count() - 1;
+        let mut relative: PathBuf = std::iter::repeat_n("..", depth).collect();
+        relative.push(path.strip_prefix("/").unwrap());
+        relative
+    }
+
+    fn scanned_paths(player: &MusicPlayer, dir: &Path) -> Vec<String> {
+        let dir = scanner::canonical(dir);
+        let mut paths: Vec<String> = player.library.iter()
+            .filter(|song| Path::new(&song.path).starts_with(&dir))
+            .map(|song| song.path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn rescan_through_another_root_spelling_adds_nothing() {
+        let dir = temp_dir("spelling");
+        write_wav(&dir.join("a.wav"));
+        write_wav(&dir.join("b.wav"));
+        std::os::unix::fs::symlink(&dir, dir.with_extension("link")).unwrap();
+
+        let mut player = MusicPlayer::new();
+        let demo = player.library.len();
+        assert_eq!(player.scan_library(&[relative(&dir)]).added, 2);
+        let paths = scanned_paths(&player, &dir);
+
+        let mut with_slash = dir.clone().into_os_string();
+        with_slash.push("/");
+        for root in [PathBuf::from(with_slash), dir.with_extension("link"), dir.join(".")] {
+            let report = player.scan_library(std::slice::from_ref(&root));
+            assert_eq!((report.added, report.unchanged), (0, 2), "{}", root.display());
+        }
+        assert_eq!(player.library.len(), demo + 2);
+        assert_eq!(scanned_paths(&player, &dir), paths);
+        assert_eq!(player.library_roots, [scanner::canonical(&dir).to_string_lossy()]);
+
+        fs::remove_file(dir.with_extension("link")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn old_non_canonical_paths_are_migrated() {
+        let dir = temp_dir("migrate");
+        write_wav(&dir.join("a.wav"));
+        let mut player = MusicPlayer::new();
+        player.scan_library(std::slice::from_ref(&dir));
+
+        // Так хранили путь до канонизации: как его написал пользователь
+        let old = format!("{}/./a.wav", dir.display());
+        let id = player.library.iter().find(|song| song.path.ends_with("a.wav")).unwrap().id;
+        let canonical_path = std::mem::replace(&mut player.library.song_mut(id).unwrap().path, old.clone());
+        let stamp = player.scan_cache.remove(&canonical_path).unwrap();
+        player.scan_cache.insert(old, stamp);
+
+        let report = player.scan_library(&[dir.join(".")]);
+        assert_eq!((report.added, report.updated, report.unchanged), (0, 0, 1));
+        assert_eq!(player.library.song(id).unwrap().path, canonical_path);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deleted_files_leave_library_and_playlists() {
+        let dir = temp_dir("deleted");
+        fs::create_dir(dir.join("locked")).unwrap();
+        for name in ["keep.wav", "gone.wav", "locked/stays.wav"] {
+            write_wav(&dir.join(name));
+        }
+        let mut player = MusicPlayer::new();
+        player.scan_library(std::slice::from_ref(&dir));
+        let id = |player: &MusicPlayer, name: &str| player.library.iter().find(|song| song.path.ends_with(name)).map(|song| song.id);
+        let gone = id(&player, "gone.wav").unwrap();
+
+        player.create_playlist("Mix".to_string());
+        player.playlists.get_mut("Mix").unwrap().songs = vec![id(&player, "keep.wav").unwrap(), gone];
+        player.enqueue(&[gone], false);
+
+        fs::remove_file(dir.join("gone.wav")).unwrap();
+        // Файл, который не удалось прочитать, но который есть на диске, остаётся
+        fs::write(dir.join("locked/stays.wav"), b"broken").unwrap();
+        let report = player.scan_library(std::slice::from_ref(&dir));
+        assert_eq!((report.removed, report.skipped.len()), (1, 1));
+        assert_eq!(id(&player, "gone.wav"), None);
+        assert!(id(&player, "stays.wav").is_some());
+        assert!(!player.scan_cache.keys().any(|path| path.ends_with("gone.wav")));
+        assert_eq!(player.playlists["Mix"].songs, [id(&player, "keep.wav").unwrap()]);
+        assert!(player.queue.is_empty());
+        assert!(player.search_songs("gone").unwrap().is_empty());
+
+        // Демо-песни с выдуманными путями лежат вне папок сканирования и не трогаются
+        assert_eq!(player.library.iter().filter(|song| !song.path.starts_with('/')).count(), 8);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stop_discards_the_finished_track() {
+        let dir = temp_dir("stop");
+        write_wav(&dir.join("1.wav"));
+        write_wav(&dir.join("2.wav"));
+        let mut player = MusicPlayer::new();
+        player.engine = engine::Engine::spawn(Box::new(output::NullSink::new(false)), 50);
+        player.scan_library(std::slice::from_ref(&dir));
+        player.create_playlist("Mix".to_string());
+        let ids: Vec<SongId> = scanned_paths(&player, &dir).iter()
+            .map(|path| player.library.iter().find(|song| &song.path == path).unwrap().id)
+            .collect();
+        let playlist = player.playlists.get_mut("Mix").unwrap();
+        playlist.songs = ids.clone();
+        playlist.current_index = Some(0);
+        player.current_playlist = Some("Mix".to_string());
+
+        // Трек доигран, но TrackEnded ещё не разобран, когда пользователь жмёт "Стоп"
+        player.play_current();
+        std::thread::sleep(Duration::from_millis(300));
+        player.stop();
+        assert!(player.poll_events().is_empty());
+        assert_eq!(player.current_song_id(), Some(ids[0]));
+        assert!(!player.is_playing());
+
+        // В остановленном плеере "следующий" сразу запускает соседний трек
+        assert!(player.next_song().unwrap().contains("Играет"));
+        assert_eq!(player.current_song_id(), Some(ids[1]));
+        assert!(player.is_playing());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn playlist(len: u64, current: Option<usize>, repeat: RepeatMode) -> Playlist {
+        Playlist { songs: (1..=len).collect(), current_index: current, repeat, ..Playlist::new("Тест".to_string()) }
+    }
+
+    #[test]
+    fn repeat_modes_at_the_edges() {
+        let library = Library::new();
+        // Режим; доигран последний трек; "следующий" на последнем; доигран первый; "предыдущий" на первом
+        let cases = [
+            (RepeatMode::Off, None, None, Some(2), None),
+            (RepeatMode::StopAtEnd, None, None, None, None),
+            (RepeatMode::All, Some(1), Some(1), Some(2), Some(3)),
+            (RepeatMode::One, Some(3), Some(1), Some(1), Some(3)),
+        ];
+        for (repeat, finished_last, next_last, finished_first, previous_first) in cases {
+            assert_eq!(playlist(3, Some(2), repeat).track_finished(&library), finished_last, "{:?}", repeat);
+            assert_eq!(playlist(3, Some(2), repeat).next_song(&library), next_last, "{:?}", repeat);
+            assert_eq!(playlist(3, Some(0), repeat).track_finished(&library), finished_first, "{:?}", repeat);
+            assert_eq!(playlist(3, Some(0), repeat).previous_song(), previous_first, "{:?}", repeat);
+        }
+
+        // Остановка не сдвигает текущий трек: "продолжить" начнёт его заново
+        let mut stopped = playlist(3, Some(2), RepeatMode::Off);
+        assert_eq!(stopped.track_finished(&library), None);
+        assert_eq!(stopped.current_index, Some(2));
+    }
+
+    #[test]
+    fn repeat_one_still_moves_on_manual_skip() {
+        let library = Library::new();
+        let mut playlist = playlist(3, Some(1), RepeatMode::One);
+        assert_eq!(playlist.track_finished(&library), Some(2));
+        assert_eq!(playlist.next_song(&library), Some(3));
+        assert_eq!(playlist.previous_song(), Some(2));
+        assert_eq!(playlist.previous_song(), Some(1));
+        assert_eq!(playlist.current_index, Some(0));
+    }
+
+    #[test]
+    fn player_stops_after_the_track_only_in_stop_at_end() {
+        let mut player = MusicPlayer::new();
+        let ids: Vec<SongId> = player.library.iter().take(3).map(|song| song.id).collect();
+        player.create_playlist("Mix".to_string());
+        player.playlists.get_mut("Mix").unwrap().songs = ids.clone();
+        player.play_playlist("Mix");
+
+        player.set_repeat(RepeatMode::StopAtEnd);
+        assert_eq!(player.track_finished(), None);
+        assert_eq!(player.current_song_id(), Some(ids[0]));
+
+        player.set_repeat(RepeatMode::Off);
+        assert!(player.track_finished().is_some());
+        assert_eq!(player.current_song_id(), Some(ids[1]));
+        player.track_finished();
+        assert_eq!(player.track_finished(), None);
+        assert_eq!(player.current_song_id(), Some(ids[2]));
+    }
+
+    #[test]
+    fn queue_plays_before_the_playlist() {
+        let mut player = MusicPlayer::new();
+        let ids: Vec<SongId> = player.library.iter().map(|song| song.id).collect();
+        player.create_playlist("Mix".to_string());
+        player.playlists.get_mut("Mix").unwrap().songs = ids[..3].to_vec();
+        player.play_playlist("Mix");
+
+        // Пока играет плейлист, очередь ждёт; "играть следующим" встаёт в её начало
+        assert_eq!(player.enqueue(&[ids[4], 999], false), 1);
+        assert_eq!(player.enqueue(&[ids[5]], true), 1);
+        assert_eq!(player.queue, [ids[5], ids[4]]);
+        assert_eq!(player.current_song_id(), Some(ids[0]));
+
+        player.play_next();
+        assert_eq!((player.current_song_id(), player.from_queue), (Some(ids[5]), Some(ids[5])));
+        // Повтор трека на очередь не действует
+        player.set_repeat(RepeatMode::One);
+        player.track_finished();
+        assert_eq!(player.current_song_id(), Some(ids[4]));
+        assert!(player.queue.is_empty());
+
+        // "Назад" из очереди возвращает к прерванному треку, "вперёд" продолжает плейлист
+        player.play_previous();
+        assert_eq!((player.current_song_id(), player.from_queue), (Some(ids[0]), None));
+        player.enqueue(&[ids[6]], false);
+        player.set_repeat(RepeatMode::Off);
+        player.play_next();
+        assert_eq!(player.current_song_id(), Some(ids[6]));
+        player.play_next();
+        assert_eq!(player.current_song_id(), Some(ids[1]));
+    }
+
+    #[test]
+    fn enqueue_starts_playback_when_idle() {
+        let mut player = MusicPlayer::new();
+        let ids: Vec<SongId> = player.library.iter().map(|song| song.id).collect();
+        assert_eq!(player.enqueue(&[], false), 0);
+        assert!(!player.is_playing());
+
+        player.enqueue(&[ids[2], ids[3]], false);
+        assert!(player.is_playing());
+        assert_eq!(player.from_queue, Some(ids[2]));
+        assert_eq!(player.queue, [ids[3]]);
+
+        // На паузе очередь только пополняется
+        assert!(player.pause());
+        player.enqueue(&[ids[4]], true);
+        assert_eq!(player.from_queue, Some(ids[2]));
+        assert_eq!(player.queue, [ids[4], ids[3]]);
+    }
+
+    #[test]
+    fn current_index_follows_the_playing_song() {
+        type Edit = fn(&mut Playlist) -> bool;
+        // Правка и песни после неё; играет песня 3 (индекс 2)
+        let cases: [(Edit, [SongId; 5], usize); 6] = [
+            (|p| p.move_song(2, 0), [3, 1, 2, 4, 5], 0),
+            (|p| p.move_song(0, 4), [2, 3, 4, 5, 1], 1),
+            (|p| p.move_song(1, 3), [1, 3, 4, 2, 5], 1),
+            (|p| p.swap_songs(2, 4), [1, 2, 5, 4, 3], 4),
+            (|p| !p.move_song(5, 0) && !p.swap_songs(0, 5), [1, 2, 3, 4, 5], 2),
+            (|p| { p.reorder(vec![4, 3, 2, 1, 0]); true }, [5, 4, 3, 2, 1], 2),
+        ];
+        for (i, (edit, songs, current)) in cases.into_iter().enumerate() {
+            let mut playlist = playlist(5, Some(2), RepeatMode::Off);
+            assert!(edit(&mut playlist), "случай {}", i);
+            assert_eq!((playlist.songs.as_slice(), playlist.current_index), (&songs[..], Some(current)), "случай {}", i);
+            assert_eq!(playlist.current_song_id(), Some(3));
+        }
+    }
+
+    #[test]
+    fn removing_songs_around_the_current_one() {
+        let mut playlist = playlist(5, Some(2), RepeatMode::Off);
+        assert_eq!(playlist.remove_song(0), Some(1));
+        assert_eq!((playlist.current_index, playlist.current_song_id()), (Some(1), Some(3)));
+        assert_eq!(playlist.remove_song(3), Some(5));
+        assert_eq!(playlist.remove_song(3), None);
+        assert_eq!(playlist.current_song_id(), Some(3));
+
+        // Вместо удалённой играющей песни текущей становится следующая, в конце - последняя
+        assert_eq!(playlist.remove_song(1), Some(3));
+        assert_eq!(playlist.songs, [2, 4]);
+        assert_eq!(playlist.current_song_id(), Some(4));
+        assert_eq!(playlist.remove_song(1), Some(4));
+        assert_eq!(playlist.current_song_id(), Some(2));
+        assert_eq!(playlist.remove_song(0), Some(2));
+        assert_eq!(playlist.current_index, None);
+    }
+
+    #[test]
+    fn duplicates_keep_the_playing_copy() {
+        let mut playlist = playlist(0, None, RepeatMode::Off);
+        playlist.songs = vec![1, 2, 1, 3, 2, 1];
+        assert_eq!(playlist.remove_duplicates(), 3);
+        assert_eq!(playlist.songs, [1, 2, 3]);
+
+        // Играет второй экземпляр песни 1: остаётся он, а не первый
+        playlist.songs = vec![1, 2, 1, 3, 1];
+        playlist.current_index = Some(2);
+        assert_eq!(playlist.remove_duplicates(), 2);
+        assert_eq!(playlist.songs, [2, 1, 3]);
+        assert_eq!(playlist.current_index, Some(1));
+        assert_eq!(playlist.remove_duplicates(), 0);
+    }
+}
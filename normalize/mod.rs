@@ -0,0 +1,219 @@
+mod decompositions;
+
+use decompositions::DECOMPOSITIONS;
+
+// Приведение текста к виду для поиска: разложение NFKD (канонические и совместимые формы),
+// нижний регистр, без диакритики. Для смешанной библиотеки кириллица переводится в латиницу:
+// сравнение в латинской записи работает в обе стороны - "Kino" найдёт "Кино", а "Кино" - "Kino".
+
+// Нижний регистр без диакритики: "Beyoncé" → "beyonce", "Ёлка" → "елка", "Ⅳ" → "iv"
+pub fn fold(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_ascii_lowercase();
+    }
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars().flat_map(decompose).flat_map(char::to_lowercase) {
+        match c {
+            // "й", "ў" и "ї" - отдельные буквы, а не "и", "у" и "і" с диакритикой
+            '\u{306}' if folded.ends_with('и') => replace_last(&mut folded, 'й'),
+            '\u{306}' if folded.ends_with('у') => replace_last(&mut folded, 'ў'),
+            '\u{308}' if folded.ends_with('і') => replace_last(&mut folded, 'ї'),
+            _ if is_diacritic(c) => {}
+            _ => match fold_char(c) {
+                Some(replacement) => folded.push_str(replacement),
+                None => folded.push(c),
+            },
+        }
+    }
+    folded
+}
+
+// Разложение NFKD одного символа: "ạ" → "a" + точка снизу, "①" → "1", "ﬅ" → "st"
+fn decompose(c: char) -> impl Iterator<Item = char> {
+    let decomposed = DECOMPOSITIONS.binary_search_by_key(&c, |&(key, _)| key).ok().map(|i| DECOMPOSITIONS[i].1);
+    let single = if decomposed.is_none() { Some(c) } else { None };
+    decomposed.into_iter().flat_map(str::chars).chain(single)
+}
+
+fn replace_last(folded: &mut String, letter: char) {
+    folded.pop();
+    folded.push(letter);
+}
+
+// Комбинируемые знаки латиницы, греческого и кириллицы. Знаки звонкости каны (U+3099, U+309A)
+// не входят: "ガ" и "ｶﾞ" раскладываются одинаково и без их удаления.
+fn is_diacritic(c: char) -> bool {
+    matches!(c, '\u{300}'..='\u{36f}' | '\u{483}'..='\u{489}' | '\u{1ab0}'..='\u{1aff}'
+        | '\u{1dc0}'..='\u{1dff}' | '\u{20d0}'..='\u{20ff}' | '\u{fe20}'..='\u{fe2f}')
+}
+
+// Буквы, которые в Unicode не раскладываются на основу и знак (уже в нижнем регистре)
+fn fold_char(c: char) -> Option<&'static str> {
+    Some(match c {
+        'æ' => "ae",
+        'ƀ' | 'ɓ' => "b",
+        'ƈ' | 'ȼ' => "c",
+        'ð' | 'đ' | 'ɖ' | 'ɗ' => "d",
+        'ɇ' => "e",
+        'ƒ' => "f",
+        'ǥ' | 'ɠ' => "g",
+        'ħ' => "h",
+        'ı' | 'ɨ' => "i",
+        'ɉ' => "j",
+        'ƙ' => "k",
+        'ł' | 'ƚ' => "l",
+        'ŋ' | 'ɲ' | 'ƞ' => "n",
+        'ø' | 'ɵ' => "o",
+        'œ' => "oe",
+        'ƥ' => "p",
+        'ɍ' => "r",
+        'ß' => "ss",
+        'ŧ' | 'ƭ' | 'ƫ' => "t",
+        'þ' => "th",
+        'ʉ' => "u",
+        'ƴ' | 'ɏ' => "y",
+        'ƶ' | 'ȥ' => "z",
+        _ => return None,
+    })
+}
+
+// Латинская запись свёрнутого текста; None - кириллицы в тексте нет
+pub fn transliterate(folded: &str) -> Option<String> {
+    if !folded.chars().any(is_cyrillic) {
+        return None;
+    }
+    let mut latin = String::with_capacity(folded.len() * 2);
+    for c in folded.chars() {
+        match latin_letter(c) {
+            Some(letter) => latin.push_str(letter),
+            None => latin.push(c),
+        }
+    }
+    Some(latin)
+}
+
+fn is_cyrillic(c: char) -> bool {
+    ('\u{400}'..='\u{4ff}').contains(&c)
+}
+
+// Транслитерация по образцу загранпаспорта, с привычными "y" для "й" и "ы"
+fn latin_letter(c: char) -> Option<&'static str> {
+    Some(match c {
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' | 'ґ' => "g",
+        'д' => "d",
+        'е' | 'э' => "e",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'і' => "i",
+        'й' | 'ы' => "y",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ю' => "yu",
+        'я' => "ya",
+        'є' => "ye",
+        'ї' => "yi",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_removes_case_and_diacritics() {
+        assert_eq!(fold("Bohemian RHAPSODY"), "bohemian rhapsody");
+        assert_eq!(fold("Beyoncé"), "beyonce");
+        assert_eq!(fold("Beyonce\u{301}"), "beyonce"); // разложенная форма
+        assert_eq!(fold("Motörhead"), "motorhead");
+        assert_eq!(fold("Sigur Rós – Ágætis byrjun"), "sigur ros – agaetis byrjun");
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("Þú"), "thu");
+        assert_eq!(fold("Łódź"), "lodz");
+    }
+
+    #[test]
+    fn fold_keeps_russian_letters_apart() {
+        assert_eq!(fold("Ёлка"), "елка");
+        assert_eq!(fold("ЛЁД"), "лед");
+        assert_eq!(fold("Чайф"), "чайф");
+        // "й" из "и" + кратка остаётся "й", а не превращается в "и"
+        assert_eq!(fold("Чаи\u{306}ф"), "чайф");
+        assert_eq!(fold("Е\u{308}лка"), "елка");
+    }
+
+    #[test]
+    fn fold_unifies_compatibility_forms() {
+        assert_eq!(fold("ＡＢＢＡ！"), "abba!");
+        assert_eq!(fold("ﬁnal ﬂight"), "final flight");
+        assert_eq!(fold("Œuvre"), "oeuvre");
+        assert_eq!(fold("E=mc²"), "e=mc2");
+        assert_eq!(fold("a\u{a0}b\u{2009}c\u{3000}d"), "a b c d");
+    }
+
+    #[test]
+    fn fold_handles_vietnamese_and_pinyin() {
+        // Латиница Extended Additional и Extended-B: точка снизу, рожок, гачек
+        assert_eq!(fold("Sơn Tùng M-TP – Lạc Trôi"), "son tung m-tp – lac troi");
+        assert_eq!(fold("Mỹ Tâm, Đàm Vĩnh Hưng"), "my tam, dam vinh hung");
+        assert_eq!(fold("Wáng Fēi – Hóngdòu, nǚ"), "wang fei – hongdou, nu");
+        assert_eq!(fold("ỨNG"), "ung");
+        // Разложенная и составная записи совпадают
+        assert_eq!(fold("Ha\u{323}"), fold("Hạ"));
+    }
+
+    #[test]
+    fn fold_expands_numbers_and_symbols() {
+        assert_eq!(fold("Led Zeppelin Ⅳ"), "led zeppelin iv");
+        assert_eq!(fold("ⅲ ⅻ"), "iii xii");
+        assert_eq!(fold("① ⑳ ⒜"), "1 20 (a)");
+        assert_eq!(fold("Song™ ½ x⁴⁵"), "songtm 1⁄2 x45");
+        assert_eq!(fold("ﬅar ﬆyle"), "star style");
+        assert_eq!(fold("𝐁𝐨𝐥𝐝"), "bold");
+    }
+
+    #[test]
+    fn fold_unifies_halfwidth_kana() {
+        assert_eq!(fold("ｶﾞｸﾄ"), fold("ガクト"));
+        assert_eq!(fold("ｱｲｳ"), "アイウ");
+        // Звонкость не теряется: "ガ" и "カ" остаются разными
+        assert_ne!(fold("ガ"), fold("カ"));
+    }
+
+    #[test]
+    fn fold_keeps_belarusian_and_ukrainian_letters() {
+        assert_eq!(fold("Ўладзімір, Їжак, Йод"), "ўладзімір, їжак, йод");
+        assert_eq!(transliterate(&fold("Їжак")), Some("yizhak".to_string()));
+    }
+
+    #[test]
+    fn transliterate_only_cyrillic() {
+        assert_eq!(transliterate("beyonce"), None);
+        assert_eq!(transliterate("кино"), Some("kino".to_string()));
+        assert_eq!(transliterate("мумий тролль"), Some("mumiy troll".to_string()));
+        assert_eq!(transliterate("щука, ёж и чайф"), Some("shchuka, ёzh i chayf".to_string()));
+        assert_eq!(transliterate(&fold("Ёжик в тумане")), Some("ezhik v tumane".to_string()));
+        assert_eq!(transliterate("океан ельзи"), Some("okean elzi".to_string()));
+        assert_eq!(transliterate("їжак і ґанок"), Some("yizhak i ganok".to_string()));
+        // Смешанный текст: латиница и цифры остаются как есть
+        assert_eq!(transliterate("ddt - это 1995"), Some("ddt - eto 1995".to_string()));
+    }
+}
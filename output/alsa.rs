@@ -0,0 +1,123 @@
+use std::ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void, CString};
+use std::io;
+use std::ptr;
+
+use super::Sink;
+use crate::decode::StreamInfo;
+
+// Минимальная привязка к libasound. Через устройство "default" звук идёт
+// и в PulseAudio/PipeWire, если в системе настроен их плагин ALSA.
+#[repr(C)]
+struct SndPcm {
+    _private: [u8; 0],
+}
+
+const SND_PCM_STREAM_PLAYBACK: c_int = 0;
+const SND_PCM_FORMAT_FLOAT_LE: c_int = 14;
+const SND_PCM_ACCESS_RW_INTERLEAVED: c_int = 3;
+const LATENCY_US: c_uint = 200_000;
+
+#[link(name = "asound")]
+extern "C" {
+    fn snd_pcm_open(pcm: *mut *mut SndPcm, name: *const c_char, stream: c_int, mode: c_int) -> c_int;
+    fn snd_pcm_set_params(
+        pcm: *mut SndPcm,
+        format: c_int,
+        access: c_int,
+        channels: c_uint,
+        rate: c_uint,
+        soft_resample: c_int,
+        latency: c_uint,
+    ) -> c_int;
+    fn snd_pcm_writei(pcm: *mut SndPcm, buffer: *const c_void, frames: c_ulong) -> c_long;
+    fn snd_pcm_recover(pcm: *mut SndPcm, err: c_int, silent: c_int) -> c_int;
+    fn snd_pcm_drain(pcm: *mut SndPcm) -> c_int;
+    fn snd_pcm_drop(pcm: *mut SndPcm) -> c_int;
+    fn snd_pcm_prepare(pcm: *mut SndPcm) -> c_int;
+    fn snd_pcm_close(pcm: *mut SndPcm) -> c_int;
+}
+
+pub struct AlsaSink {
+    pcm: *mut SndPcm,
+    channels: usize,
+}
+
+// Дескриптор используется только из одного потока за раз
+unsafe impl Send for AlsaSink {}
+
+fn check(code: c_int, what: &str) -> io::Result<()> {
+    if code < 0 {
+        Err(io::Error::other(format!("ALSA: {} (код {})", what, code)))
+    } else {
+        Ok(())
+    }
+}
+
+impl AlsaSink {
+    pub fn new(device: &str) -> io::Result<Self> {
+        let name = CString::new(device).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "некорректное имя устройства"))?;
+        let mut pcm = ptr::null_mut();
+        check(unsafe { snd_pcm_open(&mut pcm, name.as_ptr(), SND_PCM_STREAM_PLAYBACK, 0) }, "не удалось открыть устройство")?;
+        Ok(AlsaSink { pcm, channels: 0 })
+    }
+}
+
+impl Sink for AlsaSink {
+    fn configure(&mut self, info: StreamInfo) -> io::Result<()> {
+        // Перед сменой параметров доигрываем то, что уже в буфере
+        unsafe { snd_pcm_drain(self.pcm) };
+        let code = unsafe {
+            snd_pcm_set_params(
+                self.pcm,
+                SND_PCM_FORMAT_FLOAT_LE,
+                SND_PCM_ACCESS_RW_INTERLEAVED,
+                info.channels as c_uint,
+                info.sample_rate,
+                1,
+                LATENCY_US,
+            )
+        };
+        check(code, "устройство не поддерживает формат потока")?;
+        self.channels = info.channels as usize;
+        Ok(())
+    }
+
+    fn write(&mut self, mut samples: &[f32]) -> io::Result<()> {
+        if self.channels == 0 {
+            return Err(io::Error::other("вывод не настроен"));
+        }
+        while !samples.is_empty() {
+            let frames = samples.len() / self.channels;
+            let written = unsafe { snd_pcm_writei(self.pcm, samples.as_ptr().cast(), frames as c_ulong) };
+            if written < 0 {
+                // Опустошение буфера (underrun) и приостановка - восстанавливаемые ошибки
+                check(unsafe { snd_pcm_recover(self.pcm, written as c_int, 1) }, "ошибка записи")?;
+                continue;
+            }
+            samples = &samples[written as usize * self.channels..];
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        check(unsafe { snd_pcm_drain(self.pcm) }, "ошибка при доигрывании буфера")
+    }
+
+    // Выбрасываем недоигранное, иначе после перемотки ещё звучал бы старый фрагмент.
+    // После drop устройство нужно заново подготовить к записи.
+    fn reset(&mut self) {
+        unsafe {
+            snd_pcm_drop(self.pcm);
+            snd_pcm_prepare(self.pcm);
+        }
+    }
+}
+
+impl Drop for AlsaSink {
+    fn drop(&mut self) {
+        unsafe {
+            snd_pcm_drop(self.pcm);
+            snd_pcm_close(self.pcm);
+        }
+    }
+}
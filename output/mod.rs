@@ -0,0 +1,60 @@
+#[cfg(feature = "alsa")]
+mod alsa;
+mod null;
+mod wav;
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::decode::StreamInfo;
+
+pub use null::NullSink;
+pub use wav::WavFileSink;
+
+// Куда уходят декодированные кадры. Сэмплы - чередующиеся по каналам f32,
+// громкость к этому моменту уже применена.
+pub trait Sink: Send {
+    // Вызывается перед первым кадром трека; параметры потока могут меняться от трека к треку
+    fn configure(&mut self, info: StreamInfo) -> io::Result<()>;
+
+    // Может блокироваться, пока устройство не примет данные
+    fn write(&mut self, samples: &[f32]) -> io::Result<()>;
+
+    // Дожидается, пока записанное будет доиграно (или сохранено)
+    fn flush(&mut self) -> io::Result<()>;
+
+    // Поток прерывался (пауза, перемотка): следующий кадр играется с этого момента
+    fn reset(&mut self) {}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkKind {
+    Null { realtime: bool }, // realtime: false - "играть" так быстро, как получается
+    WavFile(PathBuf),
+    #[cfg(feature = "alsa")]
+    Alsa(String), // имя устройства, например "default"
+}
+
+impl SinkKind {
+    pub fn create(&self) -> io::Result<Box<dyn Sink>> {
+        Ok(match self {
+            SinkKind::Null { realtime } => Box::new(NullSink::new(*realtime)),
+            SinkKind::WavFile(path) => Box::new(WavFileSink::new(path.clone())),
+            #[cfg(feature = "alsa")]
+            SinkKind::Alsa(device) => Box::new(alsa::AlsaSink::new(device)?),
+        })
+    }
+}
+
+impl fmt::Display for SinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SinkKind::Null { realtime: true } => write!(f, "без звука (в реальном времени)"),
+            SinkKind::Null { realtime: false } => write!(f, "без звука (максимальная скорость)"),
+            SinkKind::WavFile(path) => write!(f, "WAV-файл {}", path.display()),
+            #[cfg(feature = "alsa")]
+            SinkKind::Alsa(device) => write!(f, "ALSA ({})", device),
+        }
+    }
+}
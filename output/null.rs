@@ -0,0 +1,90 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Sink;
+use crate::decode::StreamInfo;
+
+// Выбрасывает кадры. В режиме реального времени выдерживает темп воспроизведения,
+// поэтому плеер без звуковой карты ведёт себя так же, как с ней.
+pub struct NullSink {
+    realtime: bool,
+    info: Option<StreamInfo>,
+    started: Instant,
+    frames: u64, // записано с последнего configure или reset
+}
+
+impl NullSink {
+    pub fn new(realtime: bool) -> Self {
+        NullSink { realtime, info: None, started: Instant::now(), frames: 0 }
+    }
+}
+
+impl Sink for NullSink {
+    fn configure(&mut self, info: StreamInfo) -> io::Result<()> {
+        self.info = Some(info);
+        self.started = Instant::now();
+        self.frames = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let Some(info) = self.info else {
+            return Err(io::Error::other("вывод не настроен"));
+        };
+        self.frames += (samples.len() / info.channels as usize) as u64;
+
+        if self.realtime {
+            let due = self.started + Duration::from_millis(info.frames_to_ms(self.frames));
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Темп отсчитывается заново, иначе после паузы догоняли бы пропущенное время
+    fn reset(&mut self) {
+        self.started = Instant::now();
+        self.frames = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO: StreamInfo = StreamInfo { sample_rate: 1000, channels: 1, total_frames: None };
+
+    #[test]
+    fn fast_mode_does_not_wait() {
+        let mut sink = NullSink::new(false);
+        sink.configure(INFO).unwrap();
+        let started = Instant::now();
+        sink.write(&[0.0; 5000]).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn pacing_restarts_after_reset() {
+        let mut sink = NullSink::new(true);
+        sink.configure(INFO).unwrap();
+        sink.write(&[0.0; 50]).unwrap();
+
+        // Пауза: без reset следующие 150 мс "догнались" бы мгновенно
+        thread::sleep(Duration::from_millis(200));
+        sink.reset();
+        let resumed = Instant::now();
+        sink.write(&[0.0; 150]).unwrap();
+        assert!(resumed.elapsed() >= Duration::from_millis(140));
+    }
+
+    #[test]
+    fn write_before_configure_is_an_error() {
+        assert!(NullSink::new(true).write(&[0.0; 2]).is_err());
+    }
+}
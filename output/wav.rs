@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use super::Sink;
+use crate::decode::StreamInfo;
+
+const HEADER_LEN: u32 = 44;
+
+// Пишет всё воспроизведённое в 16-битный PCM WAV. Если параметры потока меняются,
+// текущий файл закрывается и начинается следующий: out.wav, out-2.wav, ...
+pub struct WavFileSink {
+    path: PathBuf,
+    file: Option<(BufWriter<File>, StreamInfo)>,
+    data_len: u32,
+    files_written: usize,
+}
+
+impl WavFileSink {
+    pub fn new(path: PathBuf) -> Self {
+        WavFileSink { path, file: None, data_len: 0, files_written: 0 }
+    }
+
+    fn next_path(&self) -> PathBuf {
+        if self.files_written == 0 {
+            return self.path.clone();
+        }
+        let stem = self.path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        self.path.with_file_name(format!("{}-{}.wav", stem, self.files_written + 1))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let Some((mut writer, _)) = self.file.take() else {
+            return Ok(());
+        };
+        // Размеры в заголовке известны только в конце
+        writer.seek(SeekFrom::Start(4))?;
+        writer.write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        writer.seek(SeekFrom::Start(40))?;
+        writer.write_all(&self.data_len.to_le_bytes())?;
+        writer.flush()
+    }
+}
+
+fn header(info: StreamInfo) -> Vec<u8> {
+    let block_align = info.channels * 2;
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(HEADER_LEN - 8).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&info.channels.to_le_bytes());
+    header.extend_from_slice(&info.sample_rate.to_le_bytes());
+    header.extend_from_slice(&(info.sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header
+}
+
+impl Sink for WavFileSink {
+    fn configure(&mut self, info: StreamInfo) -> io::Result<()> {
+        let same_format = self.file.as_ref().is_some_and(|(_, current)| {
+            current.sample_rate == info.sample_rate && current.channels == info.channels
+        });
+        if same_format {
+            return Ok(());
+        }
+
+        self.finish()?;
+        let mut writer = BufWriter::new(File::create(self.next_path())?);
+        writer.write_all(&header(info))?;
+        self.file = Some((writer, info));
+        self.data_len = 0;
+        self.files_written += 1;
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let Some((writer, _)) = self.file.as_mut() else {
+            return Err(io::Error::other("вывод не настроен"));
+        };
+
+        let len = samples.len() as u32 * 2;
+        if self.data_len.checked_add(len).is_none_or(|total| total > u32::MAX - HEADER_LEN) {
+            return Err(io::Error::other("WAV-файл достиг предельного размера 4 ГБ"));
+        }
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        self.data_len += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some((writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for WavFileSink {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wav_sink_test_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn info(sample_rate: u32, channels: u16) -> StreamInfo {
+        StreamInfo { sample_rate, channels, total_frames: None }
+    }
+
+    fn le_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn samples(bytes: &[u8]) -> Vec<i16> {
+        bytes[HEADER_LEN as usize..].chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect()
+    }
+
+    #[test]
+    fn header_sizes_and_samples() {
+        let dir = temp_dir("sizes");
+        let path = dir.join("out.wav");
+        let mut sink = WavFileSink::new(path.clone());
+        sink.configure(info(44_100, 2)).unwrap();
+        sink.write(&[0.0, 1.0, -1.0, 0.5]).unwrap();
+        // Повторная настройка с тем же форматом продолжает тот же файл
+        sink.configure(info(44_100, 2)).unwrap();
+        sink.write(&[2.0, -2.0]).unwrap();
+        drop(sink);
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN as usize + 12);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(le_u32(&bytes, 4), bytes.len() as u32 - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([bytes[22], bytes[23]]), 2);
+        assert_eq!(le_u32(&bytes, 24), 44_100);
+        assert_eq!(le_u32(&bytes, 28), 44_100 * 4);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(le_u32(&bytes, 40), 12);
+        // Значения за пределами [-1, 1] обрезаются
+        assert_eq!(samples(&bytes), [0, 32767, -32767, 16384, 32767, -32767]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_change_starts_next_file() {
+        let dir = temp_dir("next");
+        let mut sink = WavFileSink::new(dir.join("out.wav"));
+        assert!(sink.write(&[0.0]).is_err());
+
+        sink.configure(info(8_000, 1)).unwrap();
+        sink.write(&[0.25; 3]).unwrap();
+        sink.configure(info(16_000, 1)).unwrap();
+        sink.write(&[-0.25; 5]).unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+
+        let first = fs::read(dir.join("out.wav")).unwrap();
+        assert_eq!((le_u32(&first, 4), le_u32(&first, 24), le_u32(&first, 40)), (36 + 6, 8_000, 6));
+        assert_eq!(samples(&first), [8192; 3]);
+
+        let second = fs::read(dir.join("out-2.wav")).unwrap();
+        assert_eq!((le_u32(&second, 4), le_u32(&second, 24), le_u32(&second, 40)), (36 + 10, 16_000, 10));
+        assert_eq!(samples(&second), [-8192; 5]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use super::Entry;
+
+// Простой M3U - просто пути по одному в строке; расширенный добавляет
+// заголовок #EXTM3U и строки "#EXTINF:длительность,название" перед путями,
+// а VLC - ещё "#EXTVLCOPT:start-time=..." для треков из CUE
+pub fn parse(text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut pending = Entry::default();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            (pending.duration, pending.title) = parse_extinf(info);
+        } else if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            pending.set_vlc_option(option);
+        } else if !line.starts_with('#') {
+            entries.push(Entry { location: line.to_string(), ..std::mem::take(&mut pending) });
+        }
+    }
+
+    entries
+}
+
+// "354,Queen - Bohemian Rhapsody" или "-1 tvg-id=\"x\",Название"
+fn parse_extinf(info: &str) -> (Option<u32>, Option<String>) {
+    let (head, title) = match info.split_once(',') {
+        Some((head, title)) => (head, Some(title.trim().to_string()).filter(|title| !title.is_empty())),
+        None => (info, None),
+    };
+
+    let duration = head
+        .split_whitespace()
+        .next()
+        .and_then(|seconds| seconds.parse::<i64>().ok())
+        .filter(|&seconds| seconds >= 0)
+        .map(|seconds| seconds as u32);
+
+    (duration, title)
+}
+
+pub fn write(entries: &[Entry]) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        let duration = entry.duration.map_or(-1, |seconds| seconds as i64);
+        text.push_str(&format!("#EXTINF:{},{}\n", duration, entry.display_title().unwrap_or_default()));
+        for option in entry.vlc_options() {
+            text.push_str(&format!("#EXTVLCOPT:{}\n", option));
+        }
+        text.push_str(&entry.location);
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(location: &str, creator: &str, title: &str, duration: Option<u32>) -> Entry {
+        Entry { location: location.to_string(), title: Some(title.to_string()), creator: Some(creator.to_string()), duration, ..Entry::default() }
+    }
+
+    #[test]
+    fn round_trip_keeps_locations_titles_and_durations() {
+        let entries = vec![
+            entry("Кино/Группа крови.mp3", "Кино", "Группа крови", Some(285)),
+            entry("/music/Beyoncé/Halo.flac", "Beyoncé", "Halo, live", None),
+        ];
+        let parsed = parse(&write(&entries));
+
+        assert_eq!(parsed.len(), 2);
+        for (original, parsed) in entries.iter().zip(&parsed) {
+            assert_eq!(parsed.location, original.location);
+            assert_eq!(parsed.duration, original.duration);
+            // У M3U нет отдельного поля исполнителя
+            assert_eq!(parsed.title, original.display_title());
+        }
+    }
+
+    #[test]
+    fn vlc_options_belong_to_the_next_path() {
+        let text = "#EXTM3U\n#EXTINF:120,Трек 2\n#EXTVLCOPT:start-time=83.4\n#EXTVLCOPT:stop-time=203.013\nalbum.flac\nother.mp3\n";
+        let parsed = parse(text);
+        assert_eq!((parsed[0].start_ms, parsed[0].end_ms), (Some(83_400), Some(203_013)));
+        assert_eq!((parsed[1].start_ms, parsed[1].end_ms), (None, None));
+
+        let written = write(&parsed);
+        assert!(written.contains("#EXTVLCOPT:start-time=83.400\n#EXTVLCOPT:stop-time=203.013\nalbum.flac"));
+    }
+
+    #[test]
+    fn parses_plain_and_extended_lines() {
+        let text = "# комментарий\nfirst.mp3\n#EXTINF:-1 tvg-id=\"x\",Радио\nhttp://radio/stream\n\n#EXTINF:abc\nthird.mp3\n";
+        let parsed = parse(text);
+        assert_eq!(parsed.iter().map(|entry| entry.location.as_str()).collect::<Vec<_>>(), ["first.mp3", "http://radio/stream", "third.mp3"]);
+        assert_eq!(parsed[0].title, None);
+        assert_eq!((parsed[1].duration, parsed[1].title.as_deref()), (None, Some("Радио")));
+        assert_eq!((parsed[2].duration, parsed[2].title.as_deref()), (None, None));
+    }
+}
@@ -0,0 +1,385 @@
+mod m3u;
+mod pls;
+mod xspf;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::library::SongId;
+use crate::Song;
+
+// Одна запись внешнего плейлиста: путь плюс то, что о треке знает сам файл
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub location: String,
+    pub title: Option<String>,
+    pub creator: Option<String>,
+    pub duration: Option<u32>, // в секундах
+    pub start_ms: Option<u64>, // границы трека из CUE внутри файла
+    pub end_ms: Option<u64>,
+}
+
+// Трек из CUE может начинаться на долю кадра раньше или позже, чем записано в плейлисте
+const START_TOLERANCE_MS: u64 = 1000;
+
+impl Entry {
+    // Название для форматов без отдельного поля исполнителя
+    fn display_title(&self) -> Option<String> {
+        match (&self.creator, &self.title) {
+            (Some(creator), Some(title)) => Some(format!("{} - {}", creator, title)),
+            (None, title) => title.clone(),
+            (creator, None) => creator.clone(),
+        }
+    }
+
+    // Границы трека записываются опциями VLC: "start-time=83.400", "stop-time=..." (в секундах)
+    fn vlc_options(&self) -> Vec<String> {
+        let seconds = |ms: u64| format!("{}.{:03}", ms / 1000, ms % 1000);
+        let mut options = Vec::new();
+        if let Some(start) = self.start_ms {
+            options.push(format!("start-time={}", seconds(start)));
+        }
+        if let Some(end) = self.end_ms {
+            options.push(format!("stop-time={}", seconds(end)));
+        }
+        options
+    }
+
+    fn set_vlc_option(&mut self, option: &str) {
+        let Some((name, value)) = option.trim().split_once('=') else {
+            return;
+        };
+        let ms = value.trim().parse::<f64>().ok()
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+            .map(|seconds| (seconds * 1000.0).round() as u64);
+        match name.trim() {
+            "start-time" => self.start_ms = ms,
+            "stop-time" => self.end_ms = ms,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub matched: usize,
+    pub missing: Vec<(String, bool)>, // записи, которых нет в библиотеке, и есть ли файл на диске
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> io::Result<Self> {
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+        match ext.as_str() {
+            "m3u" | "m3u8" => Ok(Format::M3u),
+            "pls" => Ok(Format::Pls),
+            "xspf" => Ok(Format::Xspf),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("неизвестный формат плейлиста: .{}", ext))),
+        }
+    }
+}
+
+pub fn read_entries(path: &Path) -> io::Result<Vec<Entry>> {
+    let format = Format::from_path(path)?;
+    let data = fs::read(path)?;
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&data);
+    let text = String::from_utf8_lossy(data);
+
+    match format {
+        Format::M3u => Ok(m3u::parse(&text)),
+        Format::Pls => Ok(pls::parse(&text)),
+        Format::Xspf => xspf::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+pub fn write_songs(path: &Path, songs: &[&Song]) -> io::Result<()> {
+    let format = Format::from_path(path)?;
+    let base_dir = absolute(path.parent().unwrap_or(Path::new("")));
+
+    let entries: Vec<Entry> = songs.iter()
+        .map(|song| Entry {
+            // XSPF требует URI, остальным форматам привычнее пути
+            location: if format == Format::Xspf {
+                path_to_file_uri(&absolute(Path::new(&song.path)))
+            } else {
+                relative_location(&song.path, &base_dir)
+            },
+            title: Some(song.title.clone()),
+            creator: Some(song.artist.clone()),
+            duration: Some(song.duration),
+            start_ms: song.segment.map(|segment| segment.start_ms),
+            end_ms: song.segment.and_then(|segment| segment.end_ms),
+        })
+        .collect();
+
+    let text = match format {
+        Format::M3u => m3u::write(&entries),
+        Format::Pls => pls::write(&entries),
+        Format::Xspf => xspf::write(&entries),
+    };
+    fs::write(path, text)
+}
+
+// Сопоставляет записи с песнями библиотеки по пути.
+// Относительные пути в плейлисте считаются от папки самого плейлиста.
+// Трек из CUE узнаётся по началу (start-time в M3U и XSPF). В PLS его записать
+// негде, поэтому запись без начала соответствует первому треку файла.
+pub fn resolve(entries: &[Entry], playlist_path: &Path, library: &[Song]) -> (Vec<SongId>, ImportReport) {
+    let base_dir = absolute(playlist_path.parent().unwrap_or(Path::new("")));
+    let mut by_path: HashMap<PathBuf, Vec<&Song>> = HashMap::new();
+    for song in library {
+        by_path.entry(absolute(Path::new(&song.path))).or_default().push(song);
+    }
+
+    let mut songs = Vec::new();
+    let mut report = ImportReport::default();
+
+    for entry in entries {
+        let Some(location) = location_to_path(&entry.location) else {
+            report.missing.push((entry.location.clone(), false));
+            continue;
+        };
+        let full_path = normalize(&base_dir.join(location));
+        match by_path.get(&full_path).and_then(|songs| find_track(songs, entry.start_ms)) {
+            Some(song) => {
+                songs.push(song.id);
+                report.matched += 1;
+            }
+            None => report.missing.push((entry.location.clone(), full_path.exists())),
+        }
+    }
+
+    (songs, report)
+}
+
+fn find_track<'a>(songs: &[&'a Song], start_ms: Option<u64>) -> Option<&'a Song> {
+    let start = |song: &Song| song.segment.map_or(0, |segment| segment.start_ms);
+    match (songs, start_ms) {
+        // Обычный файл целиком, даже если плейлист просит начать не с начала
+        ([song], _) if song.segment.is_none() => Some(song),
+        (_, Some(wanted)) => songs.iter().copied()
+            .min_by_key(|song| start(song).abs_diff(wanted))
+            .filter(|song| start(song).abs_diff(wanted) <= START_TOLERANCE_MS),
+        (_, None) => songs.iter().copied().min_by_key(|song| start(song)),
+    }
+}
+
+// Путь из записи плейлиста: обычный путь или file:// URI. Другие схемы (http и т.п.) - None.
+fn location_to_path(location: &str) -> Option<PathBuf> {
+    let Some(rest) = location.strip_prefix("file://") else {
+        let has_scheme = location.split_once("://").is_some_and(|(scheme, _)| {
+            scheme.len() > 1 && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        });
+        return if has_scheme { None } else { Some(PathBuf::from(location)) };
+    };
+
+    // file:///path и file://localhost/path
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    let decoded = percent_decode(path);
+    // file:///C:/Music -> C:/Music
+    let bytes = decoded.as_bytes();
+    if bytes.len() > 2 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return Some(PathBuf::from(&decoded[1..]));
+    }
+    Some(PathBuf::from(decoded))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn path_to_file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    let path = path.to_string_lossy().replace('\\', "/");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+fn relative_location(song_path: &str, base_dir: &Path) -> String {
+    let song_path = absolute(Path::new(song_path));
+    match song_path.strip_prefix(base_dir) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => song_path.to_string_lossy().into_owned(),
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        normalize(path)
+    } else {
+        let cwd = env::current_dir().unwrap_or_default();
+        normalize(&cwd.join(path))
+    }
+}
+
+// Убирает "." и ".." без обращения к файловой системе
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push(component);
+                }
+            }
+            _ => result.push(component),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("playlist_files_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn library(dir: &Path) -> Vec<Song> {
+        let songs = [
+            ("Группа крови", "Кино", dir.join("Кино/Группа крови.mp3")),
+            ("Halo", "Beyoncé", dir.join("sub dir/Halo #1.flac")),
+            ("Far away", "Someone", env::temp_dir().join("elsewhere/far away.ogg")),
+        ];
+        songs.into_iter().enumerate()
+            .map(|(i, (title, artist, path))| {
+                let mut song = Song::new(title.to_string(), artist.to_string(), 200, "Rock".to_string(), 2000, path.to_string_lossy().into_owned());
+                song.id = i as SongId + 1;
+                song
+            })
+            .collect()
+    }
+
+    #[test]
+    fn export_then_import_finds_the_same_songs() {
+        for ext in ["m3u", "m3u8", "pls", "xspf"] {
+            let dir = temp_dir(ext);
+            let library = library(&dir);
+            let playlist = dir.join(format!("Мой плейлист.{}", ext));
+            let order: Vec<&Song> = vec![&library[2], &library[0], &library[1], &library[0]];
+
+            write_songs(&playlist, &order).unwrap();
+            let entries = read_entries(&playlist).unwrap();
+            let (ids, report) = resolve(&entries, &playlist, &library);
+
+            assert_eq!(ids, [3, 1, 2, 1], "{}", ext);
+            assert_eq!(report.matched, 4);
+            assert!(report.missing.is_empty());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn cue_tracks_keep_their_segment() {
+        let dir = temp_dir("cue");
+        let image = dir.join("album.flac").to_string_lossy().into_owned();
+        let library: Vec<Song> = [(0, Some(200_000)), (200_000, Some(415_320)), (415_320, None)].into_iter().enumerate()
+            .map(|(i, (start_ms, end_ms))| {
+                let mut song = Song::new(format!("Трек {}", i + 1), "Artist".to_string(), 200, "Rock".to_string(), 2000, image.clone());
+                song.id = i as SongId + 1;
+                song.segment = Some(crate::Segment { start_ms, end_ms });
+                song
+            })
+            .collect();
+        let order: Vec<&Song> = vec![&library[2], &library[0], &library[1]];
+
+        for (ext, expected) in [("m3u", [3, 1, 2]), ("xspf", [3, 1, 2]), ("pls", [1, 1, 1])] {
+            let playlist = dir.join(format!("list.{}", ext));
+            write_songs(&playlist, &order).unwrap();
+            let (ids, report) = resolve(&read_entries(&playlist).unwrap(), &playlist, &library);
+            assert_eq!(ids, expected, "{}", ext);
+            assert!(report.missing.is_empty());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn start_time_matches_the_nearest_segment_or_nothing() {
+        let library: Vec<Song> = [0, 200_000].into_iter().enumerate()
+            .map(|(i, start_ms)| {
+                let mut song = Song::new(String::new(), String::new(), 0, String::new(), 0, "/a.flac".to_string());
+                song.id = i as SongId + 1;
+                song.segment = Some(crate::Segment { start_ms, end_ms: None });
+                song
+            })
+            .collect();
+        let songs: Vec<&Song> = library.iter().collect();
+        assert_eq!(find_track(&songs, Some(200_400)).map(|song| song.id), Some(2));
+        assert_eq!(find_track(&songs, Some(100_000)).map(|song| song.id), None);
+        assert_eq!(find_track(&songs, None).map(|song| song.id), Some(1));
+    }
+
+    #[test]
+    fn export_uses_relative_paths_inside_the_playlist_folder() {
+        let dir = temp_dir("relative");
+        let library = library(&dir);
+        let playlist = dir.join("list.m3u");
+        write_songs(&playlist, &[&library[0], &library[2]]).unwrap();
+
+        let locations: Vec<String> = read_entries(&playlist).unwrap().into_iter().map(|entry| entry.location).collect();
+        assert_eq!(locations[0], Path::new("Кино").join("Группа крови.mp3").to_string_lossy());
+        assert!(Path::new(&locations[1]).is_absolute());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_entries_are_reported() {
+        let dir = temp_dir("missing");
+        let library = library(&dir);
+        let playlist = dir.join("list.m3u");
+        fs::write(&playlist, "Кино/Группа крови.mp3\nnot here.mp3\nhttp://radio/stream\n").unwrap();
+        fs::write(dir.join("not here.mp3"), b"").unwrap();
+
+        let (ids, report) = resolve(&read_entries(&playlist).unwrap(), &playlist, &library);
+        assert_eq!(ids, [1]);
+        assert_eq!(report.missing, [("not here.mp3".to_string(), true), ("http://radio/stream".to_string(), false)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_uris_are_decoded() {
+        assert_eq!(location_to_path("file:///music/a%20b%D0%9A.mp3"), Some(PathBuf::from("/music/a bК.mp3")));
+        assert_eq!(location_to_path("file://localhost/x.mp3"), Some(PathBuf::from("/x.mp3")));
+        assert_eq!(location_to_path("file:///C:/Music/x.mp3"), Some(PathBuf::from("C:/Music/x.mp3")));
+        assert_eq!(location_to_path("https://example.com/x.mp3"), None);
+        assert_eq!(path_to_file_uri(Path::new("/a b/Кино.mp3")), "file:///a%20b/%D0%9A%D0%B8%D0%BD%D0%BE.mp3");
+    }
+}
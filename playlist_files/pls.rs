@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use super::Entry;
+
+// PLS - INI-файл с секцией [playlist] и ключами FileN/TitleN/LengthN.
+// Границ трека внутри файла (CUE) в формате нет.
+pub fn parse(text: &str) -> Vec<Entry> {
+    let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let (field, number) = match key.find(|c: char| c.is_ascii_digit()) {
+            Some(position) => key.split_at(position),
+            None => continue,
+        };
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+
+        let entry = entries.entry(number).or_default();
+        match field {
+            "file" => entry.location = value.to_string(),
+            "title" if !value.is_empty() => entry.title = Some(value.to_string()),
+            // -1 означает неизвестную длительность (например, у потоков)
+            "length" => entry.duration = value.parse::<i64>().ok().filter(|&seconds| seconds >= 0).map(|seconds| seconds as u32),
+            _ => {}
+        }
+    }
+
+    entries.into_values().filter(|entry| !entry.location.is_empty()).collect()
+}
+
+pub fn write(entries: &[Entry]) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let number = i + 1;
+        text.push_str(&format!("File{}={}\n", number, entry.location));
+        if let Some(title) = entry.display_title() {
+            text.push_str(&format!("Title{}={}\n", number, title));
+        }
+        text.push_str(&format!("Length{}={}\n", number, entry.duration.map_or(-1, |seconds| seconds as i64)));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_keeps_locations_titles_and_durations() {
+        let entries = vec![
+            Entry { location: "Мумий Тролль/Утекай.mp3".to_string(), title: Some("Утекай".to_string()), creator: Some("Мумий Тролль".to_string()), duration: Some(222), ..Entry::default() },
+            Entry { location: "/abs/path/song.ogg".to_string(), ..Entry::default() },
+        ];
+        let parsed = parse(&write(&entries));
+
+        assert_eq!(parsed.len(), 2);
+        for (original, parsed) in entries.iter().zip(&parsed) {
+            assert_eq!(parsed.location, original.location);
+            assert_eq!(parsed.duration, original.duration);
+            assert_eq!(parsed.title, original.display_title());
+        }
+    }
+
+    #[test]
+    fn entries_follow_numbers_not_line_order() {
+        let text = "[playlist]\nTitle2=Второй\nFile2=b.mp3\nfile1 = a.mp3\nLength1=60\nTitle3=без файла\nNumberOfEntries=3\n";
+        let parsed = parse(text);
+        assert_eq!(parsed.iter().map(|entry| entry.location.as_str()).collect::<Vec<_>>(), ["a.mp3", "b.mp3"]);
+        assert_eq!(parsed[0].duration, Some(60));
+        assert_eq!(parsed[1].title.as_deref(), Some("Второй"));
+    }
+}
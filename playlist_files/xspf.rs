@@ -0,0 +1,259 @@
+use super::Entry;
+
+// Из XML нам нужны только элементы и текст; атрибуты, комментарии,
+// инструкции обработки и DOCTYPE пропускаются
+enum Token<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Text(String),
+}
+
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut current: Option<Entry> = None;
+
+    for token in tokenize(text)? {
+        match token {
+            Token::Open(name) => {
+                if name == "track" && stack.last() == Some(&"trackList") {
+                    current = Some(Entry::default());
+                }
+                stack.push(name);
+            }
+            Token::Close(name) => {
+                if stack.pop() != Some(name) {
+                    return Err(format!("неожиданный закрывающий тег </{}>", name));
+                }
+                if name == "track" {
+                    if let Some(entry) = current.take().filter(|entry| !entry.location.is_empty()) {
+                        entries.push(entry);
+                    }
+                }
+            }
+            Token::Text(value) => {
+                let Some(entry) = current.as_mut() else {
+                    continue;
+                };
+                // Границы трека из CUE: <extension><vlc:option>start-time=...</vlc:option></extension>
+                if stack.ends_with(&["track", "extension", "option"]) {
+                    entry.set_vlc_option(&value);
+                    continue;
+                }
+                // Поля трека - только прямые потомки <track>
+                if stack.len() < 2 || stack[stack.len() - 2] != "track" {
+                    continue;
+                }
+                let value = value.trim();
+                match stack.last() {
+                    // У трека может быть несколько location - берём первый
+                    Some(&"location") if entry.location.is_empty() => entry.location = value.to_string(),
+                    Some(&"title") => entry.title = Some(value.to_string()),
+                    Some(&"creator") => entry.creator = Some(value.to_string()),
+                    Some(&"duration") => {
+                        entry.duration = value.parse::<u64>().ok().map(|millis| ((millis + 500) / 1000) as u32);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if let Some(name) = stack.last() {
+        return Err(format!("тег <{}> не закрыт", name));
+    }
+    Ok(entries)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after.find("-->").ok_or("незакрытый комментарий")?;
+            rest = &after[end + 3..];
+        } else if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").ok_or("незакрытая секция CDATA")?;
+            tokens.push(Token::Text(after[..end].to_string()));
+            rest = &after[end + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest.find('>').ok_or("незакрытая служебная конструкция")?;
+            rest = &rest[end + 1..];
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').ok_or("незакрытый тег")?;
+            tokens.push(Token::Close(local_name(after[..end].trim())));
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('<') {
+            let end = tag_end(after).ok_or("незакрытый тег")?;
+            let inner = &after[..end];
+            let self_closing = inner.ends_with('/');
+            let inner = inner.trim_end_matches('/');
+            let name_end = inner.find(|c: char| c.is_whitespace()).unwrap_or(inner.len());
+            let name = local_name(&inner[..name_end]);
+            if name.is_empty() {
+                return Err("тег без имени".to_string());
+            }
+
+            tokens.push(Token::Open(name));
+            if self_closing {
+                tokens.push(Token::Close(name));
+            }
+            rest = &after[end + 1..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            tokens.push(Token::Text(unescape(&rest[..end])?));
+            rest = &rest[end..];
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Конец тега с учётом того, что '>' может встретиться внутри значения атрибута
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or("незакрытая ссылка на символ")? + start;
+        let entity = &rest[start + 1..end];
+
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    u32::from_str_radix(hex, 16).ok()
+                } else {
+                    entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok())
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+
+        result.push(decoded.ok_or_else(|| format!("неизвестная ссылка &{};", entity))?);
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+pub fn write(entries: &[Entry]) -> String {
+    let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    text.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\" xmlns:vlc=\"http://www.videolan.org/vlc/playlist/ns/0/\">\n");
+    text.push_str("  <trackList>\n");
+
+    for entry in entries {
+        text.push_str("    <track>\n");
+        text.push_str(&format!("      <location>{}</location>\n", escape(&entry.location)));
+        if let Some(title) = &entry.title {
+            text.push_str(&format!("      <title>{}</title>\n", escape(title)));
+        }
+        if let Some(creator) = &entry.creator {
+            text.push_str(&format!("      <creator>{}</creator>\n", escape(creator)));
+        }
+        // В XSPF длительность хранится в миллисекундах
+        if let Some(duration) = entry.duration {
+            text.push_str(&format!("      <duration>{}</duration>\n", duration as u64 * 1000));
+        }
+        let options = entry.vlc_options();
+        if !options.is_empty() {
+            text.push_str("      <extension application=\"http://www.videolan.org/vlc/playlist/0\">\n");
+            for option in options {
+                text.push_str(&format!("        <vlc:option>{}</vlc:option>\n", escape(&option)));
+            }
+            text.push_str("      </extension>\n");
+        }
+        text.push_str("    </track>\n");
+    }
+
+    text.push_str("  </trackList>\n");
+    text.push_str("</playlist>\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_escapes_xml() {
+        let entries = vec![
+            Entry {
+                location: "file:///music/Rock%20&%20Roll/a.mp3".to_string(),
+                title: Some("<Rock & Roll> \"live\" 'mix'".to_string()),
+                creator: Some("Ария".to_string()),
+                duration: Some(301),
+                start_ms: Some(83_400),
+                end_ms: Some(384_013),
+            },
+            Entry { location: "file:///music/b.mp3".to_string(), ..Entry::default() },
+        ];
+        let text = write(&entries);
+        assert!(text.contains("&lt;Rock &amp; Roll&gt; &quot;live&quot; &apos;mix&apos;"));
+        assert_eq!(parse(&text).unwrap(), entries);
+    }
+
+    #[test]
+    fn reads_cdata_entities_and_skips_nested_fields() {
+        let text = r#"<?xml version="1.0"?>
+<!-- экспорт -->
+<playlist xmlns="http://xspf.org/ns/0/"><title>Список</title><trackList>
+  <track><location><![CDATA[a & b.mp3]]></location><location>second.mp3</location>
+    <title>&#x41A;&#1080;&#x43D;&#x43E;</title><duration>1499</duration>
+    <extension application="x"><title>не то</title></extension></track>
+  <track><title>без пути</title></track>
+</trackList></playlist>"#;
+        let parsed = parse(text).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].location, "a & b.mp3");
+        assert_eq!(parsed[0].title.as_deref(), Some("Кино"));
+        assert_eq!(parsed[0].duration, Some(1));
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        assert!(parse("<playlist><trackList></playlist>").is_err());
+        assert!(parse("<playlist><trackList>").is_err());
+        assert!(parse("<playlist>&unknown;</playlist>").is_err());
+        assert!(parse("<playlist><!-- </playlist>").is_err());
+    }
+}
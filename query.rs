@@ -0,0 +1,682 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fuzzy::{self, Pattern};
+use crate::library::{Library, SongId};
+use crate::normalize;
+use crate::search_index::SearchIndex;
+use crate::{parse_time, Song};
+
+// Поисковый запрос:
+//   queen                    - слово в названии, исполнителе, альбоме, композиторе или жанре
+//   "bohemian rhapsody"      - фраза целиком
+//   artist:queen             - слово в конкретном поле; artist:"led zeppelin"
+//   year:1970..1980          - диапазон; year:1970.. и year:..1980 - открытые
+//   duration:>300            - сравнение: > >= < <= =; длительность можно как 5:00
+//   -live                    - исключить
+//   rock OR pop              - хотя бы одна из групп условий
+// Слова и фразы прощают опечатки ("bohemian rapsody"), но такие совпадения идут ниже точных;
+// исключения (-live) срабатывают только на точное совпадение.
+// Регистр, диакритика и ё не важны, кириллица и латиница взаимозаменяемы (см. normalize.rs).
+// Запрос хранится вместе с исходным текстом, в файле состояния - только текст.
+// pub(crate), как и Library: методы принимают приватный Song.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Query {
+    source: String,
+    groups: Vec<Vec<Term>>, // группы через OR, условия в группе - через И
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Term {
+    negated: bool,
+    kind: TermKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TermKind {
+    Text(Needle),
+    Field(TextField, Needle),
+    Number(NumberField, Comparison),
+}
+
+// Искомый текст после normalize::fold, его латинская запись и она же,
+// разобранная для нечёткого сравнения
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Needle {
+    text: String,
+    latin: String,
+    pattern: Pattern,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Composer,
+    Comment,
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumberField {
+    Year,
+    Duration,
+    Track,
+    Disc,
+    Plays,
+    Rating,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq(u32),
+    Less(u32),
+    LessEq(u32),
+    Greater(u32),
+    GreaterEq(u32),
+    Between(u32, u32), // включительно
+}
+
+const TEXT_FIELDS: &[(&str, TextField)] = &[
+    ("title", TextField::Title),
+    ("artist", TextField::Artist),
+    ("album", TextField::Album),
+    ("albumartist", TextField::AlbumArtist),
+    ("genre", TextField::Genre),
+    ("composer", TextField::Composer),
+    ("comment", TextField::Comment),
+    ("path", TextField::Path),
+];
+
+const NUMBER_FIELDS: &[(&str, NumberField)] = &[
+    ("year", NumberField::Year),
+    ("duration", NumberField::Duration),
+    ("track", NumberField::Track),
+    ("disc", NumberField::Disc),
+    ("plays", NumberField::Plays),
+    ("rating", NumberField::Rating),
+];
+
+// Вес совпадения слова без поля: название важнее жанра
+const TEXT_WEIGHTS: &[(TextField, f64)] = &[
+    (TextField::Title, 3.0),
+    (TextField::Artist, 2.5),
+    (TextField::AlbumArtist, 2.0),
+    (TextField::Album, 1.5),
+    (TextField::Composer, 1.0),
+    (TextField::Genre, 1.0),
+];
+
+// Ошибка разбора с местом в запросе (в символах), чтобы показать его под строкой
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+    pub length: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (позиция {})", self.message, self.position + 1)
+    }
+}
+
+impl ParseError {
+    fn new(message: String, position: usize, length: usize) -> Self {
+        ParseError { message, position, length: length.max(1) }
+    }
+
+    // Строка с "^^^" под ошибочной частью запроса
+    pub fn pointer(&self) -> String {
+        format!("{}{}", " ".repeat(self.position), "^".repeat(self.length))
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Term(Term),
+    Or,
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&keep) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    // Текст в кавычках; pos стоит на открывающей кавычке
+    fn quoted(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let text = self.take_while(|c| c != '"');
+        if self.peek() != Some('"') {
+            return Err(ParseError::new("незакрытая кавычка".to_string(), start, self.pos - start));
+        }
+        self.pos += 1;
+        Ok(text)
+    }
+
+    fn next(&mut self) -> Option<Result<(Token, usize, usize), ParseError>> {
+        self.take_while(char::is_whitespace);
+        let start = self.pos;
+        let first = self.peek()?;
+
+        let negated = first == '-';
+        if negated {
+            self.pos += 1;
+            if self.peek().is_none_or(char::is_whitespace) {
+                return Some(Err(ParseError::new("после '-' ожидается условие".to_string(), start, 1)));
+            }
+        }
+
+        let token = if self.peek() == Some('"') {
+            self.quoted().and_then(|phrase| {
+                let phrase = normalize::fold(phrase.trim());
+                if phrase.is_empty() {
+                    return Err(ParseError::new("пустая фраза в кавычках".to_string(), start, self.pos - start));
+                }
+                Ok(Token::Term(Term { negated, kind: TermKind::Text(Needle::new(phrase)) }))
+            })
+        } else {
+            let name_start = self.pos;
+            let word = self.take_while(|c| !c.is_whitespace() && c != ':' && c != '"');
+            if self.peek() == Some(':') {
+                self.pos += 1;
+                let value_start = self.pos;
+                let value = if self.peek() == Some('"') {
+                    self.quoted()
+                } else {
+                    Ok(self.take_while(|c| !c.is_whitespace()))
+                };
+                value.and_then(|value| {
+                    let kind = field_term(&word, name_start, &value, value_start, self.pos - value_start)?;
+                    Ok(Token::Term(Term { negated, kind }))
+                })
+            } else if word == "OR" && !negated {
+                Ok(Token::Or)
+            } else {
+                Ok(Token::Term(Term { negated, kind: TermKind::Text(Needle::new(normalize::fold(&word))) }))
+            }
+        };
+
+        Some(token.map(|token| (token, start, self.pos - start)))
+    }
+}
+
+fn field_term(name: &str, name_start: usize, value: &str, value_start: usize, value_len: usize) -> Result<TermKind, ParseError> {
+    let lower = name.to_lowercase();
+    if let Some(&(_, field)) = TEXT_FIELDS.iter().find(|(known, _)| *known == lower) {
+        let value = normalize::fold(value.trim());
+        if value.is_empty() {
+            return Err(ParseError::new(format!("пустое значение у поля \"{}\"", name), value_start, 1));
+        }
+        return Ok(TermKind::Field(field, Needle::new(value)));
+    }
+    if let Some(&(_, field)) = NUMBER_FIELDS.iter().find(|(known, _)| *known == lower) {
+        let comparison = parse_comparison(field, value)
+            .map_err(|message| ParseError::new(message, value_start, value_len))?;
+        return Ok(TermKind::Number(field, comparison));
+    }
+
+    let known = TEXT_FIELDS.iter().map(|(known, _)| *known).chain(NUMBER_FIELDS.iter().map(|(known, _)| *known));
+    let message = match known.clone().min_by_key(|known| fuzzy::edit_distance(known, &lower)) {
+        Some(closest) if fuzzy::edit_distance(closest, &lower) <= 2 => {
+            format!("неизвестное поле \"{}\" - может быть, \"{}\"?", name, closest)
+        }
+        _ => format!("неизвестное поле \"{}\"; есть: {}", name, known.collect::<Vec<_>>().join(", ")),
+    };
+    Err(ParseError::new(message, name_start, name.chars().count()))
+}
+
+fn parse_number(field: NumberField, text: &str) -> Result<u32, String> {
+    let number = match field {
+        NumberField::Duration => parse_time(text),
+        _ => text.parse().ok(),
+    };
+    number.ok_or_else(|| match field {
+        NumberField::Duration => format!("ожидается длительность (300 или 5:00), а не \"{}\"", text),
+        _ => format!("ожидается число, а не \"{}\"", text),
+    })
+}
+
+fn parse_comparison(field: NumberField, value: &str) -> Result<Comparison, String> {
+    if value.is_empty() {
+        return Err("пустое значение".to_string());
+    }
+    if let Some((from, to)) = value.split_once("..") {
+        return match (from.is_empty(), to.is_empty()) {
+            (true, true) => Err("у диапазона нет ни начала, ни конца".to_string()),
+            (false, true) => Ok(Comparison::GreaterEq(parse_number(field, from)?)),
+            (true, false) => Ok(Comparison::LessEq(parse_number(field, to)?)),
+            (false, false) => {
+                let (from, to) = (parse_number(field, from)?, parse_number(field, to)?);
+                if from > to {
+                    return Err(format!("начало диапазона больше конца: {}..{}", from, to));
+                }
+                Ok(Comparison::Between(from, to))
+            }
+        };
+    }
+
+    let operator_len = value.chars().take_while(|c| "<>=".contains(*c)).count();
+    let (operator, number) = value.split_at(operator_len);
+    let number = parse_number(field, number)?;
+    match operator {
+        "" | "=" => Ok(Comparison::Eq(number)),
+        ">" => Ok(Comparison::Greater(number)),
+        ">=" => Ok(Comparison::GreaterEq(number)),
+        "<" => Ok(Comparison::Less(number)),
+        "<=" => Ok(Comparison::LessEq(number)),
+        _ => Err(format!("неизвестное сравнение \"{}\"; есть: > >= < <= =", operator)),
+    }
+}
+
+impl Query {
+    pub fn parse(source: &str) -> Result<Query, ParseError> {
+        let mut lexer = Lexer { chars: source.chars().collect(), pos: 0 };
+        let mut groups = vec![Vec::new()];
+        let mut last_or = None;
+
+        while let Some(token) = lexer.next() {
+            let (token, start, length) = token?;
+            match token {
+                Token::Term(term) => {
+                    groups.last_mut().unwrap().push(term);
+                    last_or = None;
+                }
+                Token::Or => {
+                    if groups.last().unwrap().is_empty() {
+                        return Err(ParseError::new("OR должно стоять между условиями".to_string(), start, length));
+                    }
+                    groups.push(Vec::new());
+                    last_or = Some(start);
+                }
+            }
+        }
+        if let Some(start) = last_or {
+            return Err(ParseError::new("после OR ожидается условие".to_string(), start, 2));
+        }
+
+        Ok(Query { source: source.trim().to_string(), groups })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, song: &Song) -> bool {
+        self.score(song).is_some()
+    }
+
+    // Релевантность песни; None - не подходит. Пустой запрос подходит ко всему.
+    pub fn score(&self, song: &Song) -> Option<f64> {
+        self.groups.iter()
+            .filter_map(|terms| terms.iter().map(|term| term.score(song)).sum::<Option<f64>>())
+            .max_by(f64::total_cmp)
+    }
+
+    // Подходящие песни, самые релевантные первыми; при равенстве - в порядке библиотеки.
+    // Оцениваются только кандидаты из индекса, если запрос позволяет их отобрать.
+    pub fn search<'a>(&self, library: &'a Library, index: &SearchIndex) -> Vec<&'a Song> {
+        let songs: Vec<&Song> = match self.candidates(index) {
+            Some(ids) => {
+                let mut songs: Vec<&Song> = ids.into_iter().filter_map(|id| library.song(id)).collect();
+                songs.sort_by_key(|song| library.position(song.id));
+                songs
+            }
+            None => library.iter().collect(),
+        };
+        let mut found: Vec<(f64, &Song)> = songs.into_iter().filter_map(|song| Some((self.score(song)?, song))).collect();
+        found.sort_by(|a, b| b.0.total_cmp(&a.0));
+        found.into_iter().map(|(_, song)| song).collect()
+    }
+
+    // Песни, среди которых заведомо есть все подходящие; None - нужен полный просмотр.
+    // В группе достаточно одного слова по индексируемым полям: кандидаты групп
+    // пересекаются по её условиям и объединяются между группами.
+    fn candidates(&self, index: &SearchIndex) -> Option<HashSet<SongId>> {
+        let mut candidates = HashSet::new();
+        for terms in &self.groups {
+            let group = intersect(terms.iter().filter_map(|term| term.candidates(index)))?;
+            candidates.extend(group);
+        }
+        Some(candidates)
+    }
+}
+
+impl TryFrom<String> for Query {
+    type Error = ParseError;
+
+    fn try_from(source: String) -> Result<Self, ParseError> {
+        Query::parse(&source)
+    }
+}
+
+impl From<Query> for String {
+    fn from(query: Query) -> String {
+        query.source
+    }
+}
+
+impl TextField {
+    // Поля, которые есть в SearchIndex
+    fn is_indexed(self) -> bool {
+        TEXT_WEIGHTS.iter().any(|&(indexed, _)| indexed == self)
+    }
+}
+
+// Пересечение множеств; None - ещё ничего не пересекали
+fn intersect(sets: impl Iterator<Item = HashSet<SongId>>) -> Option<HashSet<SongId>> {
+    sets.reduce(|a, b| a.intersection(&b).copied().collect())
+}
+
+fn text_value(song: &Song, field: TextField) -> &str {
+    match field {
+        TextField::Title => &song.title,
+        TextField::Artist => &song.artist,
+        TextField::Album => &song.album,
+        TextField::AlbumArtist => song.album_artist_name(),
+        TextField::Genre => &song.genre,
+        TextField::Composer => &song.composer,
+        TextField::Comment => &song.comment,
+        TextField::Path => &song.path,
+    }
+}
+
+// Насколько хорошо искомое стоит в тексте: всё поле, начало поля, целое слово,
+// начало слова, кусок внутри слова - по убыванию
+fn placement(text: &str, needle: &str) -> Option<f64> {
+    if text == needle {
+        return Some(2.0);
+    }
+    let position = text.find(needle)?;
+    let word_start = text[..position].chars().next_back().is_none_or(|c| !c.is_alphanumeric());
+    let word_end = text[position + needle.len()..].chars().next().is_none_or(|c| !c.is_alphanumeric());
+    Some(match (position == 0, word_start, word_end) {
+        (true, _, _) => 1.75,
+        (false, true, true) => 1.5,
+        (false, true, false) => 1.25,
+        _ => 1.0,
+    })
+}
+
+impl Needle {
+    fn new(text: String) -> Self {
+        let latin = normalize::transliterate(&text).unwrap_or_else(|| text.clone());
+        let pattern = Pattern::new(&latin);
+        Needle { text, latin, pattern }
+    }
+
+    // Песни, где это может найтись: каждое слово искомого - часть слова песни
+    // (как написано или в латинской записи) либо похоже на него
+    fn candidates(&self, index: &SearchIndex) -> Option<HashSet<SongId>> {
+        let mut candidates = intersect(fuzzy::words(&self.text).map(|word| index.containing(word)))?;
+        if self.latin != self.text {
+            candidates.extend(intersect(fuzzy::words(&self.latin).map(|word| index.containing(word))).unwrap_or_default());
+        }
+        candidates.extend(intersect(self.pattern.words().iter().map(|word| index.similar(word))).unwrap_or_default());
+        Some(candidates)
+    }
+
+    // Совпадение как написано лучше совпадения через транслитерацию,
+    // а то - похожих слов, если fuzzy
+    fn score(&self, text: &str, fuzzy: bool) -> Option<f64> {
+        let text = normalize::fold(text);
+        if let Some(score) = placement(&text, &self.text) {
+            return Some(score);
+        }
+        let latin = normalize::transliterate(&text);
+        let text = latin.as_deref().unwrap_or(&text);
+        if latin.is_some() || self.latin != self.text {
+            if let Some(score) = placement(text, &self.latin) {
+                return Some(0.9 * score);
+            }
+        }
+        if !fuzzy {
+            return None;
+        }
+        // Каждая опечатка заметно снижает оценку, но похожее слово остаётся в выдаче
+        let typos = self.pattern.typos(text)?;
+        Some((0.9 - 0.2 * typos as f64).max(0.1))
+    }
+}
+
+impl Comparison {
+    fn matches(self, value: u32) -> bool {
+        match self {
+            Comparison::Eq(n) => value == n,
+            Comparison::Less(n) => value < n,
+            Comparison::LessEq(n) => value <= n,
+            Comparison::Greater(n) => value > n,
+            Comparison::GreaterEq(n) => value >= n,
+            Comparison::Between(from, to) => (from..=to).contains(&value),
+        }
+    }
+}
+
+impl Term {
+    // Исключения и числа индекс не сужают
+    fn candidates(&self, index: &SearchIndex) -> Option<HashSet<SongId>> {
+        match &self.kind {
+            _ if self.negated => None,
+            TermKind::Text(needle) => needle.candidates(index),
+            TermKind::Field(field, needle) if field.is_indexed() => needle.candidates(index),
+            _ => None,
+        }
+    }
+
+    fn score(&self, song: &Song) -> Option<f64> {
+        // Исключают только точные совпадения: похожее слово - не повод убирать песню
+        let fuzzy = !self.negated;
+        let score = match &self.kind {
+            TermKind::Text(needle) => TEXT_WEIGHTS.iter()
+                .filter_map(|&(field, weight)| Some(weight * needle.score(text_value(song, field), fuzzy)?))
+                .max_by(f64::total_cmp),
+            TermKind::Field(field, needle) => needle.score(text_value(song, *field), fuzzy),
+            TermKind::Number(field, comparison) => {
+                let value = match field {
+                    NumberField::Year => song.year as u32,
+                    NumberField::Duration => song.duration,
+                    NumberField::Track => song.track,
+                    NumberField::Disc => song.disc,
+                    NumberField::Plays => song.play_count,
+                    NumberField::Rating => song.rating as u32,
+                };
+                comparison.matches(value).then_some(1.0)
+            }
+        };
+
+        match (self.negated, score) {
+            (false, score) => score,
+            (true, Some(_)) => None,
+            (true, None) => Some(0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(title: &str, artist: &str, genre: &str, year: u16, duration: u32) -> Song {
+        Song::new(title.to_string(), artist.to_string(), duration, genre.to_string(), year, format!("/music/{}.mp3", title))
+    }
+
+    fn library() -> Library {
+        let mut songs = vec![
+            song("Bohemian Rhapsody", "Queen", "Rock", 1975, 354),
+            song("Bohemian Rhapsody (Live)", "Queen", "Rock", 1986, 360),
+            song("Stairway to Heaven", "Led Zeppelin", "Rock", 1971, 482),
+            song("Группа крови", "Кино", "Рок", 1988, 285),
+            song("Hey Jude", "The Beatles", "Pop", 1968, 431),
+            song("Rockstar", "Nickelback", "Pop", 2005, 255),
+        ];
+        songs[0].album = "A Night at the Opera".to_string();
+        songs[4].play_count = 12;
+        songs[4].rating = 5;
+        Library::from_songs(songs)
+    }
+
+    fn titles(query: &str) -> Vec<String> {
+        let library = library();
+        let index = SearchIndex::build(&library);
+        let query = Query::parse(query).unwrap();
+        query.search(&library, &index).into_iter().map(|song| song.title.clone()).collect()
+    }
+
+    fn error(query: &str) -> ParseError {
+        Query::parse(query).unwrap_err()
+    }
+
+    #[test]
+    fn parses_fields_ranges_and_groups() {
+        let query = Query::parse("  Artist:\"Led Zeppelin\" year:1970..1980 -live OR duration:>=5:00 ").unwrap();
+        assert_eq!(query.source(), "Artist:\"Led Zeppelin\" year:1970..1980 -live OR duration:>=5:00");
+        assert_eq!(query.groups.len(), 2);
+        let [artist, year, live] = query.groups[0].as_slice() else {
+            panic!("{:?}", query.groups);
+        };
+        assert!(matches!(&artist.kind, TermKind::Field(TextField::Artist, needle) if needle.text == "led zeppelin"));
+        assert_eq!(year.kind, TermKind::Number(NumberField::Year, Comparison::Between(1970, 1980)));
+        assert!(live.negated);
+        assert_eq!(query.groups[1][0].kind, TermKind::Number(NumberField::Duration, Comparison::GreaterEq(300)));
+
+        for (value, comparison) in [
+            ("1975", Comparison::Eq(1975)),
+            ("=1975", Comparison::Eq(1975)),
+            ("<1975", Comparison::Less(1975)),
+            ("<=1975", Comparison::LessEq(1975)),
+            (">1975", Comparison::Greater(1975)),
+            ("1975..", Comparison::GreaterEq(1975)),
+            ("..1975", Comparison::LessEq(1975)),
+        ] {
+            assert_eq!(parse_comparison(NumberField::Year, value), Ok(comparison), "{}", value);
+        }
+
+        // Пустой запрос подходит ко всему
+        assert_eq!(titles("   ").len(), 6);
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_part() {
+        let e = error("queen \"bohemian");
+        assert_eq!((e.message.as_str(), e.position, e.length), ("незакрытая кавычка", 6, 9));
+        assert_eq!(e.pointer(), "      ^^^^^^^^^");
+        assert_eq!(e.to_string(), "незакрытая кавычка (позиция 7)");
+
+        let e = error("artis:queen");
+        assert_eq!((e.position, e.length), (0, 5));
+        assert!(e.message.contains("может быть, \"artist\""), "{}", e.message);
+        assert!(error("colour:red").message.contains("есть: title, artist"));
+
+        let e = error("rock OR");
+        assert_eq!((e.message.as_str(), e.position, e.length), ("после OR ожидается условие", 5, 2));
+        assert_eq!(error("OR rock").message, "OR должно стоять между условиями");
+        assert_eq!(error("rock OR OR pop").position, 8);
+        assert_eq!(error("rock - pop").position, 5);
+        assert_eq!(error("rock -").position, 5);
+        assert_eq!(error("\"  \"").message, "пустая фраза в кавычках");
+        assert_eq!(error("artist:").message, "пустое значение у поля \"artist\"");
+
+        let e = error("year:1980..1970");
+        assert_eq!((e.position, e.length), (5, 10));
+        assert!(e.message.contains("начало диапазона больше конца"));
+        assert_eq!(error("year:..").message, "у диапазона нет ни начала, ни конца");
+        assert_eq!(error("year:").message, "пустое значение");
+        assert_eq!(error("year:abc").message, "ожидается число, а не \"abc\"");
+        assert!(error("duration:5:75").message.starts_with("ожидается длительность"));
+        assert!(error("plays:=>3").message.starts_with("неизвестное сравнение \"=>\""));
+        // Позиции - в символах, а не байтах
+        assert_eq!(error("кино year:x").position, 10);
+    }
+
+    #[test]
+    fn fields_numbers_negation_and_or() {
+        // Равные оценки - в порядке библиотеки
+        assert_eq!(titles("year:1970..1980"), ["Bohemian Rhapsody", "Stairway to Heaven"]);
+        assert_eq!(titles("duration:>6:00 OR plays:>=10"), ["Stairway to Heaven", "Hey Jude"]);
+        assert_eq!(titles("rating:5"), ["Hey Jude"]);
+        assert_eq!(titles("bohemian -live"), ["Bohemian Rhapsody"]);
+        assert_eq!(titles("album:opera"), ["Bohemian Rhapsody"]);
+        assert_eq!(titles("path:\"/music/hey\""), ["Hey Jude"]);
+        assert!(titles("genre:jazz OR year:1900").is_empty());
+    }
+
+    #[test]
+    fn better_placement_ranks_higher() {
+        // Всё поле > начало поля; название важнее жанра. "Рок" в латинице - "rok", не "rock"
+        assert_eq!(titles("rock"), ["Rockstar", "Bohemian Rhapsody", "Bohemian Rhapsody (Live)", "Stairway to Heaven"]);
+        assert_eq!(placement("bohemian rhapsody", "bohemian rhapsody"), Some(2.0));
+        assert_eq!(placement("bohemian rhapsody", "bohemian"), Some(1.75));
+        assert_eq!(placement("the bohemian way", "bohemian"), Some(1.5));
+        assert_eq!(placement("the bohemians", "bohemian"), Some(1.25));
+        assert_eq!(placement("antibohemian", "bohemian"), Some(1.0));
+        assert_eq!(placement("rhapsody", "bohemian"), None);
+    }
+
+    #[test]
+    fn typos_match_but_rank_below_exact() {
+        assert_eq!(titles("bohemian rapsody"), ["Bohemian Rhapsody", "Bohemian Rhapsody (Live)"]);
+        assert_eq!(titles("stairway heavem"), ["Stairway to Heaven"]);
+        let library = library();
+        let exact = Query::parse("zeppelin").unwrap().score(&library[2]).unwrap();
+        let typo = Query::parse("zepelin").unwrap().score(&library[2]).unwrap();
+        assert!(typo < exact, "{} {}", typo, exact);
+        // Короткие слова должны совпасть точно, а исключение - только точное
+        assert!(titles("jide").is_empty());
+        assert_eq!(titles("queen -rhapsodi").len(), 2);
+    }
+
+    #[test]
+    fn cyrillic_and_latin_find_each_other() {
+        assert_eq!(titles("kino"), ["Группа крови"]);
+        assert_eq!(titles("artist:КИНО"), ["Группа крови"]);
+        assert_eq!(titles("\"gruppa krovi\""), ["Группа крови"]);
+        let library = library();
+        let direct = Query::parse("кино").unwrap().score(&library[3]).unwrap();
+        let latin = Query::parse("kino").unwrap().score(&library[3]).unwrap();
+        assert!(latin < direct);
+    }
+
+    #[test]
+    fn search_with_index_matches_a_full_scan() {
+        let library = library();
+        let index = SearchIndex::build(&library);
+        for text in ["queen", "rock OR pop", "-rock", "year:..1975", "bohemain", "крови live", "кино OR hey", "zep"] {
+            let query = Query::parse(text).unwrap();
+            let found: Vec<SongId> = query.search(&library, &index).iter().map(|song| song.id).collect();
+            let mut scanned: Vec<(f64, SongId)> = library.iter()
+                .filter_map(|song| Some((query.score(song)?, song.id)))
+                .collect();
+            scanned.sort_by(|a, b| b.0.total_cmp(&a.0));
+            assert_eq!(found, scanned.into_iter().map(|(_, id)| id).collect::<Vec<_>>(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn stored_as_source_text() {
+        let query = Query::parse("artist:queen year:>1980").unwrap();
+        let json = serde_json::to_string(&query).unwrap();
+        assert_eq!(json, "\"artist:queen year:>1980\"");
+        assert_eq!(serde_json::from_str::<Query>(&json).unwrap(), query);
+        assert!(serde_json::from_str::<Query>("\"year:abc\"").is_err());
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cue::{self, CueSheet};
+use crate::tags::{self, TagWarning};
+use crate::Song;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Mp3,
+    Flac,
+    Ogg,
+    Mp4,
+    Wav,
+}
+
+impl AudioFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "mp3" => Some(AudioFormat::Mp3),
+            "flac" => Some(AudioFormat::Flac),
+            "ogg" | "oga" | "opus" => Some(AudioFormat::Ogg),
+            "m4a" | "mp4" => Some(AudioFormat::Mp4),
+            "wav" => Some(AudioFormat::Wav),
+            _ => None,
+        }
+    }
+
+    // Определяем формат по первым байтам файла
+    pub fn sniff(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"ID3") {
+            Some(AudioFormat::Mp3)
+        } else if header.starts_with(b"fLaC") {
+            Some(AudioFormat::Flac)
+        } else if header.starts_with(b"OggS") {
+            Some(AudioFormat::Ogg)
+        } else if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WAVE" {
+            Some(AudioFormat::Wav)
+        } else if header.len() >= 8 && &header[4..8] == b"ftyp" {
+            Some(AudioFormat::Mp4)
+        } else if header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0 && header[1] & 0x06 != 0 {
+            // Кадр MPEG без тега ID3 (нулевой layer - это ADTS AAC, не MP3)
+            Some(AudioFormat::Mp3)
+        } else {
+            None
+        }
+    }
+}
+
+// Размер и время изменения файла на момент последнего сканирования
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub size: u64,
+    pub modified: u64, // в миллисекундах от UNIX_EPOCH
+}
+
+impl FileStamp {
+    fn from_metadata(metadata: &fs::Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        FileStamp { size: metadata.len(), modified }
+    }
+
+    // Файл, разбитый CUE, надо перечитать и при изменении самого CUE
+    fn with_cue(self, cue: FileStamp) -> Self {
+        FileStamp { size: self.size + cue.size, modified: self.modified.max(cue.modified) }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize, // файлов больше нет
+    pub duplicates: usize,
+    pub skipped: Vec<(String, String)>, // путь и причина
+    pub warnings: Vec<(String, TagWarning)>,
+}
+
+impl ScanReport {
+    fn skip(&mut self, path: &Path, reason: impl ToString) {
+        self.skipped.push((path.display().to_string(), reason.to_string()));
+    }
+}
+
+pub struct ScannedFile {
+    pub path: String, // канонический, см. canonical
+    pub stamp: FileStamp,
+    pub songs: Option<Vec<Song>>, // None - файл не менялся с прошлого сканирования
+}
+
+// Один и тот же файл может попасть в сканер разными путями: относительным, со слэшем
+// в конце, через символическую ссылку. Библиотека хранит абсолютный путь без ссылок;
+// если файла уже нет, путь остаётся как был.
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Обходит корневые папки и возвращает найденные аудиофайлы с каноническими путями.
+// Файлы, у которых размер и время изменения совпадают с `known`, не читаются.
+pub fn scan(roots: &[PathBuf], known: &HashMap<String, FileStamp>, report: &mut ScanReport) -> Vec<ScannedFile> {
+    let mut found = Vec::new();
+    let mut seen_files = HashSet::new();
+    let mut seen_dirs = HashSet::new();
+    let mut pending: Vec<PathBuf> = roots.to_vec();
+    let mut sheets: Vec<(CueSheet, FileStamp)> = Vec::new();
+    let mut cue_files: HashMap<PathBuf, (usize, usize)> = HashMap::new(); // аудиофайл → (CUE, FILE в нём)
+
+    while let Some(dir) = pending.pop() {
+        // Защита от циклов через символические ссылки
+        match fs::canonicalize(&dir) {
+            Ok(canonical) => {
+                if !seen_dirs.insert(canonical) {
+                    continue;
+                }
+            }
+            Err(e) => {
+                report.skip(&dir, e);
+                continue;
+            }
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                report.skip(&dir, e);
+                continue;
+            }
+        };
+
+        let mut entries: Vec<_> = entries.filter_map(Result::ok).map(|entry| entry.path()).collect();
+        entries.sort();
+
+        // CUE разбираем раньше аудиофайлов папки, чтобы знать, какие из них делить на треки
+        for path in entries.iter().filter(|path| is_cue(path)) {
+            match read_cue(path) {
+                Ok((sheet, stamp)) => {
+                    // Если файл описан в нескольких CUE, делится он по последнему
+                    for (file_index, file) in sheet.files.iter().enumerate() {
+                        cue_files.insert(canonical(&file.path), (sheets.len(), file_index));
+                    }
+                    sheets.push((sheet, stamp));
+                }
+                Err(e) => report.skip(path, e),
+            }
+        }
+
+        for path in entries {
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    report.skip(&path, e);
+                    continue;
+                }
+            };
+
+            if metadata.is_dir() {
+                pending.push(path);
+                continue;
+            }
+
+            let Some(expected) = AudioFormat::from_extension(&path) else {
+                continue;
+            };
+
+            let path = canonical(&path);
+            if !seen_files.insert(path.clone()) {
+                report.duplicates += 1;
+                continue;
+            }
+
+            let key = path.to_string_lossy().into_owned();
+            let cue = cue_files.get(&path).map(|&(sheet, file)| (&sheets[sheet].0, &sheets[sheet].0.files[file], sheets[sheet].1));
+            let mut stamp = FileStamp::from_metadata(&metadata);
+            if let Some((_, _, cue_stamp)) = cue {
+                stamp = stamp.with_cue(cue_stamp);
+            }
+            if known.get(&key) == Some(&stamp) {
+                report.unchanged += 1;
+                found.push(ScannedFile { path: key, stamp, songs: None });
+                continue;
+            }
+
+            match read_song(&path, expected) {
+                Ok((song, warnings)) => {
+                    report.warnings.extend(warnings.into_iter().map(|warning| (key.clone(), warning)));
+                    let songs = match cue {
+                        Some((sheet, file, _)) => cue::split(sheet, file, &song),
+                        None => Vec::new(),
+                    };
+                    // CUE без единого INDEX 01 - оставляем файл целиком
+                    let songs = if songs.is_empty() { vec![song] } else { songs };
+                    found.push(ScannedFile { path: key, stamp, songs: Some(songs) });
+                }
+                Err(e) => report.skip(&path, e),
+            }
+        }
+    }
+
+    found
+}
+
+fn is_cue(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+fn read_cue(path: &Path) -> io::Result<(CueSheet, FileStamp)> {
+    let stamp = FileStamp::from_metadata(&fs::metadata(path)?);
+    let text = cue::decode(&fs::read(path)?);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let sheet = cue::parse(&text, dir).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((sheet, stamp))
+}
+
+fn read_header(path: &Path) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(12);
+    fs::File::open(path)?.take(12).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+fn read_song(path: &Path, expected: AudioFormat) -> io::Result<(Song, Vec<TagWarning>)> {
+    let header = read_header(path)?;
+    match AudioFormat::sniff(&header) {
+        Some(format) if format == expected => {}
+        Some(format) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("содержимое ({:?}) не совпадает с расширением ({:?})", format, expected),
+            ))
+        }
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "не похоже на аудиофайл")),
+    }
+
+    let title = path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+
+    let mut song = Song::new(
+        title,
+        "Неизвестный исполнитель".to_string(),
+        0,
+        "Unknown".to_string(),
+        0,
+        path.to_string_lossy().into_owned(),
+    );
+
+    let (tags, warnings) = tags::read_tags(path, expected)?;
+    tags.apply(&mut song);
+    Ok((song, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Моно 16 бит, 1000 Гц: seconds секунд тишины
+    fn wav(seconds: u32) -> Vec<u8> {
+        let data_len = seconds * 1000 * 2;
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(36 + data_len).to_le_bytes());
+        file.extend_from_slice(b"WAVEfmt ");
+        for field in [16u32, 0x0001_0001, 1000, 2000, 0x0010_0002] {
+            file.extend_from_slice(&field.to_le_bytes());
+        }
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&data_len.to_le_bytes());
+        file.resize(44 + data_len as usize, 0);
+        file
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("scanner_test_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn songs(found: &[ScannedFile]) -> Vec<&Song> {
+        found.iter().flat_map(|file| file.songs.iter().flatten()).collect()
+    }
+
+    #[test]
+    fn sniff_recognises_magic_bytes() {
+        let mpeg_frame = [0xFF, 0xFB, 0x90, 0x00];
+        let adts = [0xFF, 0xF1, 0x50, 0x80];
+        let cases: [(&[u8], Option<AudioFormat>); 11] = [
+            (b"ID3\x04\x00", Some(AudioFormat::Mp3)),
+            (&mpeg_frame, Some(AudioFormat::Mp3)),
+            (&adts, None), // AAC в ADTS, а не MP3
+            (b"fLaC\x00\x00\x00\x22", Some(AudioFormat::Flac)),
+            (b"OggS\x00\x02", Some(AudioFormat::Ogg)),
+            (b"RIFF\x24\x00\x00\x00WAVE", Some(AudioFormat::Wav)),
+            (b"RIFF\x24\x00\x00\x00AVI ", None),
+            (b"\x00\x00\x00\x20ftypM4A ", Some(AudioFormat::Mp4)),
+            (b"RIFF", None),
+            (&[0xFF], None),
+            (b"", None),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(AudioFormat::sniff(header), expected, "{:?}", header);
+        }
+
+        assert_eq!(AudioFormat::from_extension(Path::new("a/b.OPUS")), Some(AudioFormat::Ogg));
+        assert_eq!(AudioFormat::from_extension(Path::new("a/b.m4a")), Some(AudioFormat::Mp4));
+        assert_eq!(AudioFormat::from_extension(Path::new("a/cover.jpg")), None);
+        assert_eq!(AudioFormat::from_extension(Path::new("a/noext")), None);
+    }
+
+    #[test]
+    fn unchanged_files_are_not_read_again() {
+        let dir = temp_dir("stamps");
+        let roots = vec![dir.clone()];
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("one.wav"), wav(2)).unwrap();
+        fs::write(dir.join("sub/two.wav"), wav(3)).unwrap();
+        fs::write(dir.join("fake.wav"), b"ID3 but named .wav").unwrap();
+        fs::write(dir.join("notes.txt"), b"not audio").unwrap();
+
+        let mut report = ScanReport::default();
+        let found = scan(&roots, &HashMap::new(), &mut report);
+        assert_eq!(found.len(), 2);
+        assert_eq!(report.skipped.len(), 1, "{:?}", report.skipped);
+        assert!(report.skipped[0].1.contains("не совпадает с расширением"));
+        let mut durations: Vec<u32> = songs(&found).iter().map(|song| song.duration).collect();
+        durations.sort_unstable();
+        assert_eq!(durations, [2, 3]);
+
+        // Размер и время изменения совпали - файл не читается
+        let mut known: HashMap<String, FileStamp> = found.iter().map(|file| (file.path.clone(), file.stamp)).collect();
+        let mut report = ScanReport::default();
+        let again = scan(&roots, &known, &mut report);
+        assert_eq!(report.unchanged, 2);
+        assert!(again.iter().all(|file| file.songs.is_none()));
+
+        // Изменился размер - перечитывается только этот файл
+        fs::write(dir.join("one.wav"), wav(4)).unwrap();
+        known.values_mut().for_each(|stamp| stamp.modified = 0);
+        let one = canonical(&dir.join("one.wav")).to_string_lossy().into_owned();
+        known.insert(one.clone(), FileStamp { size: 44 + 4000, modified: 0 });
+        let mut report = ScanReport::default();
+        let again = scan(&roots, &known, &mut report);
+        assert_eq!(report.unchanged, 0);
+        assert_eq!(songs(&again).len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn same_file_through_another_path_is_a_duplicate() {
+        let dir = temp_dir("paths");
+        fs::create_dir(dir.join("music")).unwrap();
+        fs::write(dir.join("music/song.wav"), wav(1)).unwrap();
+        std::os::unix::fs::symlink(dir.join("music"), dir.join("link")).unwrap();
+
+        let roots = [dir.join("music/"), dir.join("link"), dir.join("music/../music")];
+        let mut report = ScanReport::default();
+        let found = scan(&roots, &HashMap::new(), &mut report);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, canonical(&dir.join("music/song.wav")).to_string_lossy());
+        assert_eq!(songs(&found)[0].path, found[0].path);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cue_splits_its_file_once() {
+        let dir = temp_dir("cue");
+        let roots = vec![dir.clone()];
+        fs::write(dir.join("album.wav"), wav(10)).unwrap();
+        let sheet = "PERFORMER \"Кино\"\nFILE \"album.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:04:00\n";
+        fs::write(dir.join("album.cue"), sheet).unwrap();
+        // Вторая копия того же CUE (например, в другой кодировке) не удваивает треки
+        fs::write(dir.join("album (utf-8).cue"), sheet).unwrap();
+        fs::write(dir.join("broken.cue"), "TRACK 01 AUDIO\n").unwrap();
+
+        let mut report = ScanReport::default();
+        let found = scan(&roots, &HashMap::new(), &mut report);
+        assert_eq!(found.len(), 1);
+        let tracks = songs(&found);
+        let starts: Vec<u64> = tracks.iter().map(|song| song.segment.unwrap().start_ms).collect();
+        assert_eq!(starts, [0, 4000]);
+        assert_eq!(tracks.iter().map(|song| song.duration).collect::<Vec<_>>(), [4, 6]);
+        assert!(tracks.iter().all(|song| song.artist == "Кино" && song.path == found[0].path));
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].0.ends_with("broken.cue"));
+
+        // Правка CUE, по которому делится файл (последний по имени), меняет отпечаток - файл перечитывается
+        let known = HashMap::from([(found[0].path.clone(), found[0].stamp)]);
+        fs::write(dir.join("album.cue"), format!("{}REM\n", sheet)).unwrap();
+        let mut report = ScanReport::default();
+        let again = scan(&roots, &known, &mut report);
+        assert_eq!(report.unchanged, 0);
+        assert_eq!(songs(&again).len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::RangeInclusive;
+
+use crate::library::SongId;
+use crate::{fuzzy, normalize, Song};
+
+// Обратный индекс для поиска: слово → песни. Слова берутся из тех же полей, что ищет
+// слово без поля в query.rs, после normalize::fold, а у кириллицы - ещё и в латинской записи.
+// Индекс только отбирает кандидатов, оценку и точную проверку делает Query.
+// В файл состояния индекс не пишется: при загрузке он строится заново по библиотеке.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    words: BTreeMap<String, BTreeSet<SongId>>,
+    suffixes: BTreeSet<(String, String)>, // (окончание слова, слово) - поиск по части слова
+    deletions: BTreeSet<(String, String)>, // (слово без одной буквы, слово) - одна опечатка
+    lengths: BTreeSet<(usize, String)>, // (длина, слово) - две опечатки в длинном слове
+}
+
+// Какие слова могут быть на одну и на две опечатки от искомого (см. fuzzy::typo_budget)
+const ONE_TYPO_WORDS: RangeInclusive<usize> = 4..=9;
+const LONG_WORD: usize = 7;
+
+fn suffixes(word: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    word.char_indices().map(|(start, _)| (word[start..].to_string(), word.to_string()))
+}
+
+// Слово целиком и без каждой из букв
+fn deletions(word: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    let without = word.char_indices().map(|(start, c)| format!("{}{}", &word[..start], &word[start + c.len_utf8()..]));
+    std::iter::once(word.to_string()).chain(without).map(|deletion| (deletion, word.to_string()))
+}
+
+fn indexed_texts(song: &Song) -> [&str; 6] {
+    [&song.title, &song.artist, song.album_artist_name(), &song.album, &song.genre, &song.composer]
+}
+
+fn song_words(song: &Song) -> HashSet<String> {
+    let mut words = HashSet::new();
+    for text in indexed_texts(song) {
+        let folded = normalize::fold(text);
+        words.extend(fuzzy::words(&folded).map(str::to_string));
+        if let Some(latin) = normalize::transliterate(&folded) {
+            words.extend(fuzzy::words(&latin).map(str::to_string));
+        }
+    }
+    words
+}
+
+impl SearchIndex {
+    pub fn build(library: &[Song]) -> Self {
+        let mut index = SearchIndex::default();
+        for song in library {
+            index.add(song);
+        }
+        index
+    }
+
+    pub fn add(&mut self, song: &Song) {
+        for word in song_words(song) {
+            let songs = self.words.entry(word.clone()).or_default();
+            let is_new = songs.is_empty();
+            songs.insert(song.id);
+            if is_new {
+                self.add_lookups(&word);
+            }
+        }
+    }
+
+    // Слово без песен удаляется вместе со своими записями во вспомогательных таблицах
+    pub fn remove(&mut self, song: &Song) {
+        for word in song_words(song) {
+            let Some(songs) = self.words.get_mut(&word) else {
+                continue;
+            };
+            songs.remove(&song.id);
+            if songs.is_empty() {
+                self.words.remove(&word);
+                self.remove_lookups(&word);
+            }
+        }
+    }
+
+    fn add_lookups(&mut self, word: &str) {
+        let length = word.chars().count();
+        self.suffixes.extend(suffixes(word));
+        if ONE_TYPO_WORDS.contains(&length) {
+            self.deletions.extend(deletions(word));
+        }
+        if length >= LONG_WORD {
+            self.lengths.insert((length, word.to_string()));
+        }
+    }
+
+    fn remove_lookups(&mut self, word: &str) {
+        for entry in suffixes(word) {
+            self.suffixes.remove(&entry);
+        }
+        for entry in deletions(word) {
+            self.deletions.remove(&entry);
+        }
+        self.lengths.remove(&(word.chars().count(), word.to_string()));
+    }
+
+    // Песни, в которых есть слово, содержащее part; начало слова тоже подходит,
+    // поэтому недописанное слово находится по мере набора
+    pub fn containing(&self, part: &str) -> HashSet<SongId> {
+        let words: HashSet<&str> = self.suffixes.range((part.to_string(), String::new())..)
+            .take_while(|(suffix, _)| suffix.starts_with(part))
+            .map(|(_, word)| word.as_str())
+            .collect();
+        words.into_iter().flat_map(|word| &self.words[word]).copied().collect()
+    }
+
+    // Песни со словом, похожим на wanted (см. fuzzy::similar). Слова, отличающиеся
+    // одной опечаткой, совпадают, если убрать по букве из каждого (или из одного),
+    // поэтому их ищем по таблице удалений; с двумя опечатками перебираем слова близкой длины.
+    pub fn similar(&self, wanted: &[char]) -> HashSet<SongId> {
+        let text: String = wanted.iter().collect();
+        let words: HashSet<&str> = match fuzzy::typo_budget(wanted.len()) {
+            0 => self.words.get_key_value(&text).map(|(word, _)| word.as_str()).into_iter().collect(),
+            1 => deletions(&text)
+                .flat_map(|(deletion, _)| {
+                    self.deletions.range((deletion.clone(), String::new())..)
+                        .take_while(move |(other, _)| *other == deletion)
+                        .map(|(_, word)| word.as_str())
+                })
+                .collect(),
+            budget => self.lengths.range((wanted.len() - budget, String::new())..(wanted.len() + budget + 1, String::new()))
+                .map(|(_, word)| word.as_str())
+                .collect(),
+        };
+        words.into_iter()
+            .filter(|word| fuzzy::similar(word, wanted).is_some())
+            .flat_map(|word| &self.words[word])
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> Vec<Song> {
+        let songs = [
+            ("Bohemian Rhapsody", "Queen", "Rock"),
+            ("We Will Rock You", "Queen", "Rock"),
+            ("Smells Like Teen Spirit", "Nirvana", "Grunge"),
+            ("Группа крови", "Кино", "Рок"),
+            ("Zvezda po imeni Solntse", "Kino", "Rock"),
+            ("Déjà Vu", "Beyoncé", "R&B"),
+            ("Paranoid Android", "Radiohead", "Alternative"),
+        ];
+        songs.iter().enumerate()
+            .map(|(i, &(title, artist, genre))| {
+                let mut song = Song::new(title.to_string(), artist.to_string(), 200, genre.to_string(), 2000, String::new());
+                song.id = i as SongId + 1;
+                song
+            })
+            .collect()
+    }
+
+    // Что должны вернуть запросы, если перебрать все слова всех песен
+    fn scan(library: &[Song], matches: impl Fn(&str) -> bool) -> HashSet<SongId> {
+        library.iter().filter(|song| song_words(song).iter().any(|word| matches(word))).map(|song| song.id).collect()
+    }
+
+    fn assert_lookups_match(index: &SearchIndex, library: &[Song]) {
+        let words: HashSet<String> = library.iter().flat_map(song_words).collect();
+        let mut parts: HashSet<String> = words.iter().flat_map(|word| suffixes(word).map(|(suffix, _)| suffix)).collect();
+        parts.extend(["", "zz", "ро", "q"].map(str::to_string));
+        for part in &parts {
+            assert_eq!(index.containing(part), scan(library, |word| word.contains(part.as_str())), "часть {:?}", part);
+        }
+
+        let mut wanted: Vec<String> = words.iter().flat_map(|word| deletions(word).map(|(deletion, _)| deletion)).collect();
+        wanted.extend(["qeen", "nirvanna", "radoihead", "bohemain", "rhapsodyy", "rapsodi", "paranod", "andriod", "spirti", "solnste"].map(str::to_string));
+        for word in &wanted {
+            let chars: Vec<char> = word.chars().collect();
+            assert_eq!(index.similar(&chars), scan(library, |other| fuzzy::similar(other, &chars).is_some()), "слово {:?}", word);
+        }
+    }
+
+    #[test]
+    fn lookups_agree_with_a_full_scan() {
+        let library = library();
+        let index = SearchIndex::build(&library);
+        assert_lookups_match(&index, &library);
+
+        assert_eq!(index.containing("rock"), HashSet::from([1, 2, 5]));
+        assert_eq!(index.containing("kin"), HashSet::from([4, 5])); // "кино" в латинице
+        assert_eq!(index.containing("deja"), HashSet::from([6]));
+        assert_eq!(index.similar(&['q', 'e', 'e', 'n']), HashSet::new()); // короткое слово - без опечаток
+        assert_eq!(index.similar(&"radoihead".chars().collect::<Vec<_>>()), HashSet::from([7]));
+    }
+
+    #[test]
+    fn add_and_remove_keep_lookups_consistent() {
+        let library = library();
+        let mut index = SearchIndex::build(&library);
+
+        // Убираем песни по одной: слова, общие с другими песнями, остаются
+        let mut left = library.clone();
+        for removed in [1, 4, 6] {
+            let position = left.iter().position(|song| song.id == removed).unwrap();
+            index.remove(&left.remove(position));
+            let rebuilt = SearchIndex::build(&left);
+            assert_eq!(index.words, rebuilt.words);
+            assert_eq!(index.suffixes, rebuilt.suffixes);
+            assert_eq!(index.deletions, rebuilt.deletions);
+            assert_eq!(index.lengths, rebuilt.lengths);
+            assert_lookups_match(&index, &left);
+        }
+        assert_eq!(index.containing("queen"), HashSet::from([2]));
+        assert!(index.containing("bohemian").is_empty());
+
+        // Правка тегов - это удаление старой версии песни и добавление новой
+        let mut edited = left[0].clone();
+        index.remove(&edited);
+        edited.title = "Radio Ga Ga".to_string();
+        index.add(&edited);
+        left[0] = edited;
+        assert_lookups_match(&index, &left);
+        assert_eq!(index.containing("radio"), HashSet::from([2, 7]));
+
+        for song in &left {
+            index.remove(song);
+        }
+        assert!(index.words.is_empty() && index.suffixes.is_empty() && index.deletions.is_empty() && index.lengths.is_empty());
+    }
+}
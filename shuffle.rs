@@ -0,0 +1,400 @@
+use std::collections::{BTreeSet, HashMap};
+
+use rand::Rng;
+
+use crate::Song;
+
+// Насколько реже слушанные треки сдвигаются к началу цикла (в долях цикла)
+const PLAY_COUNT_BIAS: f64 = 0.15;
+// Среди скольких ближайших треков искать другой жанр; дальше разнесение важнее
+const GENRE_LOOKAHEAD: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Random,
+    Spread, // разносит исполнителей и жанры по циклу
+}
+
+// Порядок перемешанного воспроизведения: каждый трек звучит один раз за цикл,
+// а "предыдущий" возвращает к тому, что действительно играло.
+// Хранит индексы в Playlist::songs, поэтому плейлист сообщает о вставках и удалениях.
+#[derive(Debug, Default)]
+pub struct ShuffleState {
+    upcoming: Vec<usize>, // оставшиеся в текущем цикле; следующий - последний
+    history: Vec<usize>,  // сыгранные, для кнопки "предыдущий"
+    forward: Vec<usize>,  // куда вернуться после "предыдущего"
+    started: bool,        // цикл уже начат
+}
+
+// Перестановка Фишера-Йетса
+fn permutation(indices: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut order: Vec<usize> = indices.collect();
+    let mut rng = rand::thread_rng();
+    for i in (1..order.len()).rev() {
+        let j = rng.gen_range(0..=i);
+        order.swap(i, j);
+    }
+    order
+}
+
+// Номер значения поля у каждой песни (без учёта регистра) и число разных значений.
+// Песни, которых нет в библиотеке (None), получают номер пустой строки.
+fn intern(songs: &[Option<&Song>], field: impl Fn(&Song) -> &str) -> (Vec<usize>, usize) {
+    let mut keys: HashMap<String, usize> = HashMap::new();
+    let ids = songs.iter()
+        .map(|song| {
+            let key = song.map(|song| field(song).to_lowercase()).unwrap_or_default();
+            let next = keys.len();
+            *keys.entry(key).or_insert(next)
+        })
+        .collect();
+    (ids, keys.len())
+}
+
+// Разносит треки по циклу по образцу "dithering" из Spotify: треки одного исполнителя
+// ставятся примерно через равные доли цикла со случайным сдвигом, реже слушанные - чуть
+// раньше. Песни, которых нет в библиотеке (None), идут как трек без исполнителя.
+fn spread(songs: &[Option<&Song>], indices: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut rng = rand::thread_rng();
+    let (artist, artist_count) = intern(songs, |song| &song.artist);
+    let (genre, _) = intern(songs, |song| &song.genre);
+    let play_count = |i: usize| songs[i].map_or(0, |song| song.play_count);
+
+    let mut artists: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in indices {
+        artists.entry(artist[i]).or_default().push(i);
+    }
+    let max_plays = artists.values().flatten().map(|&i| play_count(i)).max().unwrap_or(0);
+
+    let mut placed: Vec<(f64, usize)> = Vec::new();
+    for tracks in artists.into_values() {
+        let step = 1.0 / tracks.len() as f64;
+        let offset = rng.gen_range(0.0..step);
+        for (k, i) in permutation(tracks.into_iter()).into_iter().enumerate() {
+            let mut position = offset + k as f64 * step + rng.gen_range(-0.1..0.1) * step;
+            if max_plays > 0 {
+                position -= PLAY_COUNT_BIAS * (1.0 - play_count(i) as f64 / max_plays as f64);
+            }
+            placed.push((position, i));
+        }
+    }
+    placed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    // Как и upcoming, следующий - последний: выбранный трек снимается без сдвига всего списка
+    let mut remaining: Vec<usize> = placed.into_iter().map(|(_, i)| i).collect();
+
+    // Сколько треков исполнителя осталось; (осталось, исполнитель) - чтобы сразу найти самого частого
+    let mut counts = vec![0; artist_count];
+    for &i in &remaining {
+        counts[artist[i]] += 1;
+    }
+    let mut by_count: BTreeSet<(usize, usize)> = counts.iter().enumerate()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(artist, &count)| (count, artist))
+        .collect();
+
+    // Жадно выбираем из разнесённого порядка первый трек, не совпадающий с предыдущим
+    // по исполнителю (и по возможности по жанру среди ближайших). Исполнителя, которому
+    // иначе не хватит промежутков до конца цикла, ставим без очереди.
+    let mut order = Vec::with_capacity(remaining.len());
+    while let Some(&(busiest_count, busiest)) = by_count.last() {
+        let previous = order.last().copied();
+        let differs = |i: usize| previous.is_none_or(|p| artist[p] != artist[i]);
+        let other_genre = |i: usize| previous.is_none_or(|p| genre[p] != genre[i]);
+
+        let busiest_first = (busiest_count * 2 > remaining.len())
+            .then(|| remaining.iter().rposition(|&i| artist[i] == busiest))
+            .flatten()
+            .filter(|&j| differs(remaining[j]));
+        let nearest = remaining.len().saturating_sub(GENRE_LOOKAHEAD);
+        let pick = busiest_first
+            .or_else(|| remaining[nearest..].iter().rposition(|&i| differs(i) && other_genre(i)).map(|j| nearest + j))
+            .or_else(|| remaining.iter().rposition(|&i| differs(i)))
+            .unwrap_or(remaining.len() - 1);
+
+        let i = remaining.remove(pick);
+        let a = artist[i];
+        by_count.remove(&(counts[a], a));
+        counts[a] -= 1;
+        if counts[a] > 0 {
+            by_count.insert((counts[a], a));
+        }
+        order.push(i);
+    }
+    order
+}
+
+// Порядок цикла от первого трека к последнему
+fn cycle(songs: &[Option<&Song>], indices: impl Iterator<Item = usize>, strategy: Strategy) -> Vec<usize> {
+    let mut order = match strategy {
+        Strategy::Random => permutation(indices),
+        Strategy::Spread => spread(songs, indices),
+    };
+    // upcoming забирается с конца
+    order.reverse();
+    order
+}
+
+impl ShuffleState {
+    // wraps: начинать ли новый цикл, когда текущий закончился
+    pub fn next(&mut self, songs: &[Option<&Song>], current: Option<usize>, wraps: bool, strategy: Strategy) -> Option<usize> {
+        let len = songs.len();
+        if let Some(next) = self.forward.pop() {
+            self.history.extend(current);
+            return Some(next);
+        }
+
+        if !self.started {
+            // Первый цикл: уже играющий трек считается сыгранным
+            self.upcoming = cycle(songs, (0..len).filter(|&i| Some(i) != current), strategy);
+            self.started = true;
+        }
+
+        if self.upcoming.is_empty() {
+            if !wraps {
+                self.started = false;
+                return None;
+            }
+            self.upcoming = cycle(songs, 0..len, strategy);
+            // Новый цикл не должен начинаться с трека, которым закончился предыдущий
+            if self.upcoming.len() > 1 && self.upcoming.last() == current.as_ref() {
+                let last = self.upcoming.len() - 1;
+                self.upcoming.swap(0, last);
+            }
+        }
+
+        let next = self.upcoming.pop()?;
+        self.history.extend(current);
+        Some(next)
+    }
+
+    pub fn previous(&mut self, current: Option<usize>) -> Option<usize> {
+        let previous = self.history.pop()?;
+        self.forward.extend(current);
+        Some(previous)
+    }
+
+    // Новый трек со индексом index попадает в случайное место текущего цикла
+    pub fn inserted(&mut self, index: usize) {
+        for i in self.indices_mut() {
+            if *i >= index {
+                *i += 1;
+            }
+        }
+        if self.started {
+            let position = rand::thread_rng().gen_range(0..=self.upcoming.len());
+            self.upcoming.insert(position, index);
+        }
+    }
+
+    pub fn removed(&mut self, index: usize) {
+        for list in [&mut self.upcoming, &mut self.history, &mut self.forward] {
+            list.retain(|&i| i != index);
+        }
+        for i in self.indices_mut() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+    }
+
+    // Плейлист переставлен: new_position[старый индекс] = новый индекс.
+    // Цикл и история сохраняются - они о песнях, а не о местах.
+    pub fn reordered(&mut self, new_position: &[usize]) {
+        for i in self.indices_mut() {
+            *i = new_position[*i];
+        }
+    }
+
+    fn indices_mut(&mut self) -> impl Iterator<Item = &mut usize> {
+        self.upcoming.iter_mut().chain(self.history.iter_mut()).chain(self.forward.iter_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(artist: &str, genre: &str) -> Song {
+        Song::new(String::new(), artist.to_string(), 200, genre.to_string(), 2000, String::new())
+    }
+
+    fn artists_in_a_row(songs: &[Option<&Song>], order: &[usize]) -> usize {
+        let artist = |i: usize| songs[i].map(|song| song.artist.to_lowercase());
+        order.windows(2).filter(|pair| artist(pair[0]) == artist(pair[1])).count()
+    }
+
+    #[test]
+    fn spread_never_repeats_an_artist_when_it_can_be_avoided() {
+        // 5 + 4 + 3: самому частому хватает промежутков, только если его не откладывать
+        let library: Vec<Song> = [("Queen", 5), ("QUEEN ", 0), ("Nirvana", 4), ("Kino", 3)].into_iter()
+            .flat_map(|(artist, count)| (0..count).map(move |_| song(artist, "Rock")))
+            .collect();
+        let songs: Vec<Option<&Song>> = library.iter().map(Some).collect();
+        for _ in 0..200 {
+            let order = spread(&songs, 0..songs.len());
+            assert_eq!(artists_in_a_row(&songs, &order), 0, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn spread_keeps_every_track_once() {
+        let library = [song("A", "Rock"), song("a", "Pop"), song("B", "Rock"), song("A", "Jazz")];
+        let mut songs: Vec<Option<&Song>> = library.iter().map(Some).collect();
+        songs.push(None); // песни нет в библиотеке
+        let mut order = spread(&songs, 0..songs.len());
+        order.sort_unstable();
+        assert_eq!(order, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn spread_with_one_artist_only_reorders() {
+        let library: Vec<Song> = (0..6).map(|_| song("Kino", "Rock")).collect();
+        let songs: Vec<Option<&Song>> = library.iter().map(Some).collect();
+        let mut order = spread(&songs, 1..songs.len());
+        order.sort_unstable();
+        assert_eq!(order, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn spread_prefers_another_genre_nearby() {
+        let library = [song("A", "Rock"), song("B", "Rock"), song("C", "Pop"), song("D", "Pop")];
+        let songs: Vec<Option<&Song>> = library.iter().map(Some).collect();
+        let genre = |i: usize| &library[i].genre;
+        for _ in 0..100 {
+            let order = spread(&songs, 0..songs.len());
+            assert!(order.windows(2).all(|pair| genre(pair[0]) != genre(pair[1])), "{:?}", order);
+        }
+    }
+
+    // Играет цикл до конца (или n треков), как плеер: каждый следующий становится текущим
+    fn play(state: &mut ShuffleState, songs: &[Option<&Song>], current: &mut Option<usize>, n: usize, wraps: bool) -> Vec<usize> {
+        let mut played = Vec::new();
+        for _ in 0..n {
+            let Some(next) = state.next(songs, *current, wraps, Strategy::Random) else {
+                break;
+            };
+            played.push(next);
+            *current = Some(next);
+        }
+        played
+    }
+
+    #[test]
+    fn cycle_plays_every_track_once_after_the_current_one() {
+        let songs: Vec<Option<&Song>> = vec![None; 6];
+        let mut state = ShuffleState::default();
+        let mut current = Some(2);
+        let mut played = play(&mut state, &songs, &mut current, 10, false);
+        assert_eq!(played.len(), 5);
+        played.sort_unstable();
+        assert_eq!(played, [0, 1, 3, 4, 5]);
+
+        // Без повтора цикл закончился (play получил None), следующий запрос начинает новый
+        assert!(state.next(&songs, current, false, Strategy::Random).is_some());
+    }
+
+    #[test]
+    fn new_cycle_does_not_start_with_the_last_track() {
+        let songs: Vec<Option<&Song>> = vec![None; 3];
+        for _ in 0..100 {
+            let mut state = ShuffleState::default();
+            let mut current = None;
+            let first = play(&mut state, &songs, &mut current, 3, true);
+            let second = play(&mut state, &songs, &mut current, 3, true);
+            assert_ne!(first[2], second[0], "{:?} {:?}", first, second);
+            let mut sorted = second.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, [0, 1, 2]);
+        }
+    }
+
+    #[test]
+    fn previous_returns_to_what_actually_played() {
+        let songs: Vec<Option<&Song>> = vec![None; 5];
+        let mut state = ShuffleState::default();
+        let mut current = None;
+        let played = play(&mut state, &songs, &mut current, 3, true);
+
+        assert_eq!(state.previous(current), Some(played[1]));
+        assert_eq!(state.previous(Some(played[1])), Some(played[0]));
+        assert_eq!(state.previous(Some(played[0])), None);
+
+        // "Следующий" проходит тот же путь обратно, а потом продолжает цикл
+        assert_eq!(state.next(&songs, Some(played[0]), true, Strategy::Random), Some(played[1]));
+        assert_eq!(state.next(&songs, Some(played[1]), true, Strategy::Random), Some(played[2]));
+        let mut current = Some(played[2]);
+        let rest = play(&mut state, &songs, &mut current, 2, true);
+        let mut all: Vec<usize> = played.iter().chain(&rest).copied().collect();
+        all.sort_unstable();
+        assert_eq!(all, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn inserted_track_joins_the_cycle_and_shifts_indices() {
+        let songs: Vec<Option<&Song>> = vec![None; 4];
+        let mut state = ShuffleState::default();
+        let mut current = None;
+        let played = play(&mut state, &songs, &mut current, 2, false);
+
+        // Новая песня встаёт на место 0: все индексы сдвигаются
+        state.inserted(0);
+        let songs: Vec<Option<&Song>> = vec![None; 5];
+        let mut current = current.map(|i| i + 1);
+        assert_eq!(state.previous(current), Some(played[0] + 1));
+        current = state.next(&songs, Some(played[0] + 1), false, Strategy::Random);
+        assert_eq!(current, Some(played[1] + 1));
+
+        let mut rest = play(&mut state, &songs, &mut current, 10, false);
+        rest.sort_unstable();
+        let mut expected: Vec<usize> = (0..5).filter(|&i| i != played[0] + 1 && i != played[1] + 1).collect();
+        expected.sort_unstable();
+        assert_eq!(rest, expected);
+    }
+
+    #[test]
+    fn removed_track_leaves_history_and_cycle() {
+        let songs: Vec<Option<&Song>> = vec![None; 5];
+        let mut state = ShuffleState::default();
+        let mut current = None;
+        let played = play(&mut state, &songs, &mut current, 3, false);
+
+        // Удаляем второй сыгранный: "предыдущий" перескакивает через него
+        let removed = played[1];
+        state.removed(removed);
+        let shift = |i: usize| if i > removed { i - 1 } else { i };
+        let songs: Vec<Option<&Song>> = vec![None; 4];
+        let current = Some(shift(played[2]));
+        assert_eq!(state.previous(current), Some(shift(played[0])));
+
+        let mut current = Some(shift(played[0]));
+        let mut rest = play(&mut state, &songs, &mut current, 10, false);
+        assert_eq!(rest[0], shift(played[2]));
+        rest.sort_unstable();
+        let mut expected: Vec<usize> = (0..5).filter(|i| !played.contains(i)).map(shift).collect();
+        expected.insert(0, shift(played[2]));
+        expected.sort_unstable();
+        assert_eq!(rest, expected);
+    }
+
+    #[test]
+    fn reordered_keeps_the_cycle_of_songs() {
+        let songs: Vec<Option<&Song>> = vec![None; 4];
+        let mut state = ShuffleState::default();
+        let mut current = None;
+        let played = play(&mut state, &songs, &mut current, 2, false);
+
+        // Плейлист перевёрнут: старый индекс i становится 3 - i
+        let new_position = [3, 2, 1, 0];
+        state.reordered(&new_position);
+        let current = Some(new_position[played[1]]);
+        assert_eq!(state.previous(current), Some(new_position[played[0]]));
+
+        let mut current = Some(new_position[played[0]]);
+        let mut rest = play(&mut state, &songs, &mut current, 10, false);
+        assert_eq!(rest[0], new_position[played[1]]);
+        rest.sort_unstable();
+        let mut expected: Vec<usize> = (0..4).filter(|i| *i != played[0]).map(|i| new_position[i]).collect();
+        expected.sort_unstable();
+        assert_eq!(rest, expected);
+    }
+}
@@ -0,0 +1,211 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::library::{Library, SongId};
+use crate::query::Query;
+use crate::{format_duration, Song, SortField};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Условие умного плейлиста
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rule {
+    GenreIn(Vec<String>),
+    YearBetween(u16, u16), // включительно
+    ArtistMatches(String), // подстрока без учёта регистра
+    DurationUnder(u32),    // в секундах
+    RatingAtLeast(u8),
+    NotPlayedFor(u32),     // дней; ни разу не игравшие тоже подходят
+    PlayCountAtLeast(u32),
+    PlayCountBelow(u32),
+    Query(Query), // поисковый запрос, см. query.rs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Combine {
+    All, // И
+    Any, // ИЛИ
+}
+
+// Плейлист, содержимое которого вычисляется по библиотеке
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmartRules {
+    pub rules: Vec<Rule>,
+    pub combine: Combine,
+    pub sort: Option<(SortField, bool)>, // поле и "по убыванию"
+    pub limit: Option<usize>,
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+impl Rule {
+    fn matches(&self, song: &Song, now: u64) -> bool {
+        match self {
+            Rule::GenreIn(genres) => genres.iter().any(|genre| genre.to_lowercase() == song.genre.to_lowercase()),
+            Rule::YearBetween(from, to) => (*from..=*to).contains(&song.year),
+            Rule::ArtistMatches(artist) => song.artist.to_lowercase().contains(&artist.to_lowercase()),
+            Rule::DurationUnder(seconds) => song.duration < *seconds,
+            Rule::RatingAtLeast(rating) => song.rating >= *rating,
+            Rule::NotPlayedFor(days) => song.last_played.is_none_or(|played| played + *days as u64 * SECONDS_PER_DAY <= now),
+            Rule::PlayCountAtLeast(count) => song.play_count >= *count,
+            Rule::PlayCountBelow(count) => song.play_count < *count,
+            Rule::Query(query) => query.matches(song),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Rule::GenreIn(genres) => format!("жанр: {}", genres.join(", ")),
+            Rule::YearBetween(from, to) => format!("год {}–{}", from, to),
+            Rule::ArtistMatches(artist) => format!("исполнитель содержит \"{}\"", artist),
+            Rule::DurationUnder(seconds) => format!("короче {}", format_duration(*seconds)),
+            Rule::RatingAtLeast(rating) => format!("оценка ≥ {}", rating),
+            Rule::NotPlayedFor(days) => format!("не звучал {} дн.", days),
+            Rule::PlayCountAtLeast(count) => format!("прослушиваний ≥ {}", count),
+            Rule::PlayCountBelow(count) => format!("прослушиваний < {}", count),
+            Rule::Query(query) => format!("запрос \"{}\"", query.source()),
+        }
+    }
+}
+
+impl SmartRules {
+    // Без условий подходит вся библиотека
+    fn matches(&self, song: &Song, now: u64) -> bool {
+        match self.combine {
+            Combine::All => self.rules.iter().all(|rule| rule.matches(song, now)),
+            Combine::Any => self.rules.is_empty() || self.rules.iter().any(|rule| rule.matches(song, now)),
+        }
+    }
+
+    // Без сортировки песни идут в порядке библиотеки
+    pub fn evaluate(&self, library: &Library, now: u64) -> Vec<SongId> {
+        let mut songs: Vec<&Song> = library.iter().filter(|song| self.matches(song, now)).collect();
+        if let Some((field, descending)) = self.sort {
+            songs.sort_by(|a, b| {
+                let ordering = field.compare(a, b);
+                if descending { ordering.reverse() } else { ordering }
+            });
+        }
+        if let Some(limit) = self.limit {
+            songs.truncate(limit);
+        }
+        songs.into_iter().map(|song| song.id).collect()
+    }
+
+    pub fn describe(&self) -> String {
+        let separator = match self.combine {
+            Combine::All => " И ",
+            Combine::Any => " ИЛИ ",
+        };
+        let mut text = if self.rules.is_empty() {
+            "вся библиотека".to_string()
+        } else {
+            self.rules.iter().map(Rule::describe).collect::<Vec<_>>().join(separator)
+        };
+        if let Some((field, descending)) = self.sort {
+            text.push_str(&format!("; по полю \"{}\" {}", field.label(), if descending { "↓" } else { "↑" }));
+        }
+        if let Some(limit) = self.limit {
+            text.push_str(&format!("; не больше {} треков", limit));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1000 * SECONDS_PER_DAY;
+
+    fn song(artist: &str, genre: &str, year: u16, duration: u32, rating: u8, play_count: u32, last_played: Option<u64>) -> Song {
+        let mut song = Song::new(format!("{} {}", artist, year), artist.to_string(), duration, genre.to_string(), year, String::new());
+        song.rating = rating;
+        song.play_count = play_count;
+        song.last_played = last_played;
+        song
+    }
+
+    fn library() -> Library {
+        Library::from_songs(vec![
+            song("Queen", "Rock", 1970, 180, 5, 0, None),
+            song("Queen II", "Pop", 1979, 179, 3, 3, Some(NOW - 7 * SECONDS_PER_DAY)),
+            song("Кино", "Rock", 1980, 300, 0, 10, Some(NOW - 7 * SECONDS_PER_DAY + 1)),
+            song("Abba", "rock", 1969, 181, 4, 1, Some(NOW)),
+        ])
+    }
+
+    fn only(rules: Vec<Rule>, combine: Combine) -> SmartRules {
+        SmartRules { rules, combine, sort: None, limit: None }
+    }
+
+    #[test]
+    fn every_rule_on_its_boundaries() {
+        let library = library();
+        let query = |text: &str| Rule::Query(Query::parse(text).unwrap());
+        let cases = [
+            (Rule::GenreIn(vec!["ROCK".to_string()]), vec![1, 3, 4]),
+            (Rule::GenreIn(vec![]), vec![]),
+            (Rule::YearBetween(1970, 1979), vec![1, 2]),
+            (Rule::YearBetween(1979, 1979), vec![2]),
+            (Rule::YearBetween(1980, 1970), vec![]),
+            (Rule::ArtistMatches("queen".to_string()), vec![1, 2]),
+            (Rule::ArtistMatches("КИНО".to_string()), vec![3]),
+            (Rule::ArtistMatches(String::new()), vec![1, 2, 3, 4]),
+            (Rule::DurationUnder(180), vec![2]),
+            (Rule::DurationUnder(181), vec![1, 2]),
+            (Rule::DurationUnder(0), vec![]),
+            (Rule::RatingAtLeast(4), vec![1, 4]),
+            (Rule::RatingAtLeast(0), vec![1, 2, 3, 4]),
+            // Ни разу не игравшая подходит; ровно 7 дней назад - уже да, на секунду позже - ещё нет
+            (Rule::NotPlayedFor(7), vec![1, 2]),
+            (Rule::NotPlayedFor(0), vec![1, 2, 3, 4]),
+            (Rule::NotPlayedFor(u32::MAX), vec![1]),
+            (Rule::PlayCountAtLeast(3), vec![2, 3]),
+            (Rule::PlayCountAtLeast(0), vec![1, 2, 3, 4]),
+            (Rule::PlayCountBelow(1), vec![1]),
+            (Rule::PlayCountBelow(0), vec![]),
+            (query("genre:rock year:..1975"), vec![1, 4]),
+            (query("-queen"), vec![3, 4]),
+        ];
+
+        for (rule, expected) in cases {
+            let rules = only(vec![rule.clone()], Combine::All);
+            assert_eq!(rules.evaluate(&library, NOW), expected, "{}", rule.describe());
+        }
+    }
+
+    #[test]
+    fn broken_queries_never_become_rules() {
+        for text in ["year:abc", "\"rock", "genre:", "OR rock", "foo:bar"] {
+            assert!(Query::parse(text).is_err(), "{}", text);
+            let json = serde_json::json!({ "Query": text });
+            assert!(serde_json::from_value::<Rule>(json).is_err(), "{}", text);
+        }
+
+        let rule = Rule::Query(Query::parse("genre:rock").unwrap());
+        let json = serde_json::to_value(&rule).unwrap();
+        assert_eq!(json, serde_json::json!({ "Query": "genre:rock" }));
+        assert_eq!(serde_json::from_value::<Rule>(json).unwrap(), rule);
+    }
+
+    #[test]
+    fn combine_sort_and_limit() {
+        let library = library();
+        let rules = vec![Rule::GenreIn(vec!["Pop".to_string()]), Rule::RatingAtLeast(5)];
+        assert_eq!(only(rules.clone(), Combine::All).evaluate(&library, NOW), Vec::<SongId>::new());
+        assert_eq!(only(rules.clone(), Combine::Any).evaluate(&library, NOW), [1, 2]);
+        // Без условий подходит всё при любом способе объединения
+        assert_eq!(only(vec![], Combine::All).evaluate(&library, NOW), [1, 2, 3, 4]);
+        assert_eq!(only(vec![], Combine::Any).evaluate(&library, NOW), [1, 2, 3, 4]);
+
+        let top = SmartRules { sort: Some((SortField::PlayCount, true)), limit: Some(2), ..only(vec![], Combine::All) };
+        assert_eq!(top.evaluate(&library, NOW), [3, 2]);
+        assert_eq!(top.describe(), "вся библиотека; по полю \"число прослушиваний\" ↓; не больше 2 треков");
+        let none = SmartRules { limit: Some(0), ..only(rules, Combine::Any) };
+        assert!(none.evaluate(&library, NOW).is_empty());
+    }
+}
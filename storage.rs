@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::scanner::FileStamp;
+use crate::library::SongId;
+use crate::{Playlist, Song};
+
+pub const STATE_FILE: &str = "music_player_state.json";
+
+// Миграции схемы: MIGRATIONS[i] переводит файл из версии i + 1 в версию i + 2.
+// Новое поле в Song или Playlist = новая функция в конце списка.
+type Migration = fn(&mut Map<String, Value>);
+const MIGRATIONS: &[Migration] = &[
+    migrate_v1_scan_cache,
+    migrate_v2_album_fields,
+    migrate_v3_cue_segments,
+    migrate_v4_repeat_mode,
+    migrate_v5_play_counts,
+    migrate_v6_play_queue,
+    migrate_v7_playlist_names,
+    migrate_v8_song_ids,
+    migrate_v9_smart_playlists,
+    migrate_v10_search_index,
+    migrate_v11_cover_art,
+    migrate_v12_drop_search_index,
+];
+
+pub const SCHEMA_VERSION: u64 = MIGRATIONS.len() as u64 + 1;
+
+// Состояние плеера в том виде, в каком оно лежит на диске
+#[derive(Deserialize)]
+pub struct State {
+    pub library: Vec<Song>,
+    pub playlists: HashMap<String, Playlist>,
+    pub current_playlist: Option<String>,
+    pub volume: u8,
+    pub library_roots: Vec<String>,
+    pub scan_cache: HashMap<String, FileStamp>,
+    pub queue: Vec<SongId>,
+}
+
+// То же состояние, но по ссылкам, чтобы не клонировать библиотеку при каждом сохранении
+#[derive(Serialize)]
+pub struct StateRef<'a> {
+    pub version: u64,
+    pub library: &'a [Song],
+    pub playlists: &'a HashMap<String, Playlist>,
+    pub current_playlist: &'a Option<String>,
+    pub volume: u8,
+    pub library_roots: &'a [String],
+    pub scan_cache: &'a HashMap<String, FileStamp>,
+    pub queue: &'a [SongId],
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// v2: папки библиотеки и кэш сканера для инкрементального пересканирования
+fn migrate_v1_scan_cache(root: &mut Map<String, Value>) {
+    root.entry("library_roots").or_insert_with(|| Value::Array(Vec::new()));
+    root.entry("scan_cache").or_insert_with(|| Value::Object(Map::new()));
+}
+
+// v3: альбом, номер трека и диска, композитор, комментарий и MusicBrainz ID у песен
+fn migrate_v2_album_fields(root: &mut Map<String, Value>) {
+    let add_fields = |song: &mut Value| {
+        if let Some(song) = song.as_object_mut() {
+            for field in ["album", "album_artist", "composer", "comment"] {
+                song.entry(field).or_insert_with(|| Value::from(""));
+            }
+            song.entry("track").or_insert_with(|| Value::from(0));
+            song.entry("disc").or_insert_with(|| Value::from(0));
+            song.entry("mbid").or_insert(Value::Null);
+        }
+    };
+
+    if let Some(Value::Array(library)) = root.get_mut("library") {
+        library.iter_mut().for_each(add_fields);
+    }
+    if let Some(Value::Object(playlists)) = root.get_mut("playlists") {
+        for playlist in playlists.values_mut() {
+            if let Some(Value::Array(songs)) = playlist.get_mut("songs") {
+                songs.iter_mut().for_each(add_fields);
+            }
+        }
+    }
+}
+
+// v4: границы трека внутри файла для песен из CUE
+fn migrate_v3_cue_segments(root: &mut Map<String, Value>) {
+    let add_segment = |song: &mut Value| {
+        if let Some(song) = song.as_object_mut() {
+            song.entry("segment").or_insert(Value::Null);
+        }
+    };
+
+    if let Some(Value::Array(library)) = root.get_mut("library") {
+        library.iter_mut().for_each(add_segment);
+    }
+    if let Some(Value::Object(playlists)) = root.get_mut("playlists") {
+        for playlist in playlists.values_mut() {
+            if let Some(Value::Array(songs)) = playlist.get_mut("songs") {
+                songs.iter_mut().for_each(add_segment);
+            }
+        }
+    }
+}
+
+// v5: режим повтора у плейлистов. Раньше плейлисты всегда зацикливались - сохраняем это.
+fn migrate_v4_repeat_mode(root: &mut Map<String, Value>) {
+    if let Some(Value::Object(playlists)) = root.get_mut("playlists") {
+        for playlist in playlists.values_mut().filter_map(Value::as_object_mut) {
+            playlist.entry("repeat").or_insert_with(|| Value::from("All"));
+        }
+    }
+}
+
+// v6: счётчик прослушиваний у песен и умное перемешивание у плейлистов
+fn migrate_v5_play_counts(root: &mut Map<String, Value>) {
+    let add_count = |song: &mut Value| {
+        if let Some(song) = song.as_object_mut() {
+            song.entry("play_count").or_insert_with(|| Value::from(0));
+        }
+    };
+
+    if let Some(Value::Array(library)) = root.get_mut("library") {
+        library.iter_mut().for_each(add_count);
+    }
+    if let Some(Value::Object(playlists)) = root.get_mut("playlists") {
+        for playlist in playlists.values_mut().filter_map(Value::as_object_mut) {
+            playlist.entry("smart_shuffle").or_insert(Value::Bool(false));
+            if let Some(Value::Array(songs)) = playlist.get_mut("songs") {
+                songs.iter_mut().for_each(add_count);
+            }
+        }
+    }
+}
+
+// v7: очередь воспроизведения
+fn migrate_v6_play_queue(root: &mut Map<String, Value>) {
+    root.entry("queue").or_insert_with(|| Value::Array(Vec::new()));
+}
+
+// v8: ключ плейлиста совпадает с его именем. Демо-плейлисты хранились под ключом
+// без эмодзи ("Rock Classics" для "🎸 Rock Classics"). Если имя уже занято другим
+// ключом, плейлист сохраняет ключ и в качестве имени.
+fn migrate_v7_playlist_names(root: &mut Map<String, Value>) {
+    let Some(Value::Object(playlists)) = root.remove("playlists") else {
+        return;
+    };
+    let mut current = root.get("current_playlist").and_then(Value::as_str).map(str::to_string);
+
+    let keys: Vec<String> = playlists.keys().cloned().collect();
+    let mut renamed = Map::new();
+    for (key, mut playlist) in playlists {
+        let name = playlist.get("name").and_then(Value::as_str).map(str::to_string);
+        let new_key = match name {
+            Some(name) if name == key || !(keys.contains(&name) || renamed.contains_key(&name)) => name,
+            _ => key.clone(),
+        };
+        if let Some(playlist) = playlist.as_object_mut() {
+            playlist.insert("name".to_string(), Value::from(new_key.clone()));
+        }
+        if current.as_deref() == Some(key.as_str()) {
+            current = Some(new_key.clone());
+        }
+        renamed.insert(new_key, playlist);
+    }
+
+    root.insert("playlists".to_string(), Value::Object(renamed));
+    if let Some(current) = current {
+        root.insert("current_playlist".to_string(), Value::from(current));
+    }
+}
+
+// v9: у песен библиотеки постоянные id, плейлисты и очередь хранят id вместо копий.
+// Копии сопоставляются с библиотекой по ключу (Song::key); если песни в библиотеке уже нет,
+// она возвращается туда, чтобы плейлист ничего не потерял.
+fn migrate_v8_song_ids(root: &mut Map<String, Value>) {
+    let key = |song: &Value| -> Option<String> {
+        let path = song.get("path")?.as_str()?;
+        let start = song.get("segment").and_then(|segment| segment.get("start_ms")).and_then(Value::as_u64);
+        Some(match start {
+            Some(start) => format!("{}#{}", path, start),
+            None => path.to_string(),
+        })
+    };
+
+    let mut library = match root.remove("library") {
+        Some(Value::Array(library)) => library,
+        _ => Vec::new(),
+    };
+    let mut ids: HashMap<String, u64> = HashMap::new();
+    for (i, song) in library.iter_mut().enumerate() {
+        let id = i as u64 + 1;
+        if let Some(key) = key(song) {
+            ids.entry(key).or_insert(id);
+        }
+        if let Some(song) = song.as_object_mut() {
+            song.insert("id".to_string(), Value::from(id));
+        }
+    }
+
+    let mut to_ids = |songs: Option<&mut Value>| {
+        let Some(Value::Array(songs)) = songs else {
+            return;
+        };
+        for song in songs.iter_mut() {
+            let Some(key) = key(song) else {
+                continue;
+            };
+            let id = *ids.entry(key).or_insert_with(|| {
+                let id = library.len() as u64 + 1;
+                let mut copy = song.clone();
+                if let Some(copy) = copy.as_object_mut() {
+                    copy.insert("id".to_string(), Value::from(id));
+                }
+                library.push(copy);
+                id
+            });
+            *song = Value::from(id);
+        }
+        // Записи без пути ни с чем не сопоставить
+        songs.retain(Value::is_u64);
+    };
+
+    if let Some(Value::Object(playlists)) = root.get_mut("playlists") {
+        for playlist in playlists.values_mut() {
+            to_ids(playlist.get_mut("songs"));
+        }
+    }
+    to_ids(root.get_mut("queue"));
+
+    root.insert("library".to_string(), Value::Array(library));
+}
+
+// v10: оценка и время последнего прослушивания у песен, правила у плейлистов
+fn migrate_v9_smart_playlists(root: &mut Map<String, Value>) {
+    if let Some(Value::Array(library)) = root.get_mut("library") {
+        for song in library.iter_mut().filter_map(Value::as_object_mut) {
+            song.entry("rating").or_insert_with(|| Value::from(0));
+            song.entry("last_played").or_insert(Value::Null);
+        }
+    }
+    if let Some(Value::Object(playlists)) = root.get_mut("playlists") {
+        for playlist in playlists.values_mut().filter_map(Value::as_object_mut) {
+            playlist.entry("rules").or_insert(Value::Null);
+        }
+    }
+}
+
+// v11: поисковый индекс; построится при первой загрузке
+fn migrate_v10_search_index(root: &mut Map<String, Value>) {
+    root.entry("search_index").or_insert(Value::Null);
+}
+
+// v12: положение обложки внутри файла; появится при следующем сканировании
+fn migrate_v11_cover_art(root: &mut Map<String, Value>) {
+    if let Some(Value::Array(library)) = root.get_mut("library") {
+        for song in library.iter_mut().filter_map(Value::as_object_mut) {
+            song.entry("cover").or_insert(Value::Null);
+        }
+    }
+}
+
+// v13: поисковый индекс больше не хранится. Таблицы для поиска по части слова и с опечатками
+// всё равно строились при загрузке, а сохранённые слова раздували файл и экономили немного.
+fn migrate_v12_drop_search_index(root: &mut Map<String, Value>) {
+    root.remove("search_index");
+}
+
+fn migrate(mut value: Value) -> io::Result<Value> {
+    let root = value
+        .as_object_mut()
+        .ok_or_else(|| invalid_data("файл состояния не является JSON-объектом".to_string()))?;
+
+    let version = root.get("version").and_then(Value::as_u64).unwrap_or(1);
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(invalid_data(format!(
+            "неподдерживаемая версия файла состояния: {} (ожидается не выше {})",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(root);
+    }
+    root.insert("version".to_string(), Value::from(SCHEMA_VERSION));
+
+    Ok(value)
+}
+
+// Возвращает None, если файла состояния ещё нет (первый запуск)
+pub fn load(path: &Path) -> io::Result<Option<State>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let value: Value = serde_json::from_slice(&data).map_err(|e| invalid_data(e.to_string()))?;
+    let value = migrate(value)?;
+    let state = serde_json::from_value(value).map_err(|e| invalid_data(e.to_string()))?;
+    Ok(Some(state))
+}
+
+// Пишем во временный файл и переименовываем его, чтобы сбой посреди записи
+// не оставил на диске обрезанный JSON
+pub fn save(path: &Path, state: &StateRef) -> io::Result<()> {
+    let data = serde_json::to_vec_pretty(state).map_err(io::Error::other)?;
+
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+
+    {
+        let mut file = fs::File::create(tmp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+    }
+    fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::RepeatMode;
+
+    // Файлы состояния, сохранённые плеером каждой старой версии схемы.
+    // В v7 вручную добавлены копия песни в очереди и трек из CUE, которого нет в библиотеке.
+    const FIXTURES: [&str; 12] = [
+        include_str!("fixtures/state_v1.json"),
+        include_str!("fixtures/state_v2.json"),
+        include_str!("fixtures/state_v3.json"),
+        include_str!("fixtures/state_v4.json"),
+        include_str!("fixtures/state_v5.json"),
+        include_str!("fixtures/state_v6.json"),
+        include_str!("fixtures/state_v7.json"),
+        include_str!("fixtures/state_v8.json"),
+        include_str!("fixtures/state_v9.json"),
+        include_str!("fixtures/state_v10.json"),
+        include_str!("fixtures/state_v11.json"),
+        include_str!("fixtures/state_v12.json"),
+    ];
+
+    fn from_json(text: &str) -> io::Result<State> {
+        let value = migrate(serde_json::from_str(text).unwrap())?;
+        serde_json::from_value(value).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    fn song_by_id(state: &State, id: SongId) -> &Song {
+        state.library.iter().find(|song| song.id == id).unwrap()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("storage_test_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn every_old_version_loads() {
+        for (i, text) in FIXTURES.iter().enumerate() {
+            let version = i as u64 + 1;
+            let original: Value = serde_json::from_str(text).unwrap();
+            assert_eq!(original["version"], version);
+
+            let state = from_json(text).unwrap_or_else(|e| panic!("v{}: {}", version, e));
+            assert!(!state.library.is_empty(), "v{}", version);
+            assert_eq!(state.playlists.len(), 2, "v{}", version);
+
+            let mut ids: Vec<SongId> = state.library.iter().map(|song| song.id).collect();
+            ids.sort_unstable();
+            ids.dedup();
+            assert_eq!(ids.len(), state.library.len(), "v{}: id повторяются", version);
+
+            for (key, playlist) in &state.playlists {
+                assert_eq!(key, &playlist.name, "v{}", version);
+                assert!(!playlist.songs.is_empty(), "v{}", version);
+                for &id in playlist.songs.iter().chain(&state.queue) {
+                    song_by_id(&state, id);
+                }
+                // До v5 плейлисты всегда зацикливались
+                if version < 5 {
+                    assert_eq!(playlist.repeat, RepeatMode::All, "v{}", version);
+                }
+            }
+            let current = state.current_playlist.as_ref().unwrap();
+            assert!(state.playlists.contains_key(current), "v{}: {}", version, current);
+        }
+    }
+
+    #[test]
+    fn v1_gets_empty_scan_data_and_renamed_playlists() {
+        let state = from_json(FIXTURES[0]).unwrap();
+        assert!(state.library_roots.is_empty());
+        assert!(state.scan_cache.is_empty());
+        assert!(state.queue.is_empty());
+        assert_eq!(state.current_playlist.as_deref(), Some("🎸 Rock Classics"));
+
+        let queen = state.library.iter().find(|song| song.artist == "Queen").unwrap();
+        assert_eq!((queen.album.as_str(), queen.track, queen.segment, queen.play_count), ("", 0, None, 0));
+        assert_eq!((queen.rating, queen.last_played, queen.cover), (0, None, None));
+        assert_eq!(state.library.iter().map(|song| song.id).max(), Some(state.library.len() as u64));
+    }
+
+    #[test]
+    fn v7_copies_become_library_ids() {
+        let original: Value = serde_json::from_str(FIXTURES[6]).unwrap();
+        let library_len = original["library"].as_array().unwrap().len();
+        let state = from_json(FIXTURES[6]).unwrap();
+
+        assert_eq!(state.current_playlist.as_deref(), Some("🎤 Pop Hits"));
+        assert_eq!(state.scan_cache.len(), original["scan_cache"].as_object().unwrap().len());
+
+        // Копия из очереди находит песню библиотеки, а не дублирует её
+        assert_eq!(state.queue.len(), 1);
+        let queued = song_by_id(&state, state.queue[0]);
+        assert!(queued.path.ends_with("p16.wav"));
+        assert!(state.queue[0] <= library_len as u64);
+
+        // Трек из CUE есть только в плейлисте - он возвращается в библиотеку
+        assert_eq!(state.library.len(), library_len + 1);
+        let rock = &state.playlists["🎸 Rock Classics"];
+        let cue = song_by_id(&state, *rock.songs.last().unwrap());
+        assert_eq!(cue.id, library_len as u64 + 1);
+        assert_eq!(cue.artist, "Кино");
+        assert_eq!(cue.segment, Some(crate::Segment { start_ms: 285000, end_ms: Some(539986) }));
+    }
+
+    #[test]
+    fn v11_songs_get_cover_and_v12_index_is_dropped() {
+        for text in [FIXTURES[10], FIXTURES[11]] {
+            let original: Value = serde_json::from_str(text).unwrap();
+            assert!(original["search_index"].is_object());
+
+            let value = migrate(original).unwrap();
+            assert_eq!(value["version"], SCHEMA_VERSION);
+            assert!(value.get("search_index").is_none());
+            assert!(value["library"].as_array().unwrap().iter().all(|song| song["cover"].is_null()));
+        }
+    }
+
+    #[test]
+    fn playlist_keeps_its_key_when_the_name_is_taken() {
+        let text = r#"{"version": 7, "library": [], "volume": 50, "current_playlist": "Rock",
+            "library_roots": [], "scan_cache": {}, "queue": [],
+            "playlists": {
+                "Rock": {"name": "🎸 Rock", "songs": [], "current_index": null, "is_shuffle": false,
+                         "smart_shuffle": false, "repeat": "Off"},
+                "🎸 Rock": {"name": "🎸 Rock", "songs": [], "current_index": null, "is_shuffle": false,
+                           "smart_shuffle": false, "repeat": "One"}
+            }}"#;
+        let state = from_json(text).unwrap();
+        assert_eq!(state.playlists["Rock"].name, "Rock");
+        assert_eq!(state.playlists["Rock"].repeat, RepeatMode::Off);
+        assert_eq!(state.playlists["🎸 Rock"].repeat, RepeatMode::One);
+        assert_eq!(state.current_playlist.as_deref(), Some("Rock"));
+    }
+
+    #[test]
+    fn songs_without_path_are_dropped_from_playlists() {
+        let text = r#"{"version": 8, "library": [], "volume": 50, "current_playlist": null,
+            "library_roots": [], "scan_cache": {}, "queue": [{"title": "?"}],
+            "playlists": {}}"#;
+        let state = from_json(text).unwrap();
+        assert!(state.queue.is_empty());
+        assert!(state.library.is_empty());
+    }
+
+    #[test]
+    fn unsupported_files_are_errors() {
+        let future = format!(r#"{{"version": {}}}"#, SCHEMA_VERSION + 1);
+        for text in [future.as_str(), r#"{"version": 0}"#, "[1, 2]", "\"state\"", "null"] {
+            let error = migrate(serde_json::from_str(text).unwrap()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", text);
+        }
+        // Без поля version файл считается первой версией
+        assert!(from_json(r#"{"library": [], "playlists": {}, "current_playlist": null, "volume": 50}"#).is_ok());
+    }
+
+    #[test]
+    fn load_reports_broken_json_and_missing_file() {
+        let path = temp_path("broken.json");
+        fs::write(&path, "{\"version\": 11, \"library\": [").unwrap();
+        assert_eq!(load(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+        assert!(load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let state = from_json(FIXTURES[6]).unwrap();
+        let path = temp_path("state.json");
+        save(&path, &StateRef {
+            version: SCHEMA_VERSION,
+            library: &state.library,
+            playlists: &state.playlists,
+            current_playlist: &state.current_playlist,
+            volume: state.volume,
+            library_roots: &state.library_roots,
+            scan_cache: &state.scan_cache,
+            queue: &state.queue,
+        }).unwrap();
+        let saved: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert!(saved.get("search_index").is_none());
+        let loaded = load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(serde_json::to_value(&loaded.library).unwrap(), serde_json::to_value(&state.library).unwrap());
+        assert_eq!(serde_json::to_value(&loaded.playlists).unwrap(), serde_json::to_value(&state.playlists).unwrap());
+        assert_eq!((loaded.current_playlist, loaded.volume, loaded.queue), (state.current_playlist, state.volume, state.queue));
+        assert_eq!(loaded.library_roots, state.library_roots);
+        assert_eq!(loaded.scan_cache.len(), state.scan_cache.len());
+        assert!(!Path::new(&format!("{}.tmp", path.display())).exists());
+    }
+}
@@ -0,0 +1,162 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::{samples_to_seconds, vorbis_comment, TagWarning, Tags};
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+const STREAMINFO_LEN: usize = 34;
+
+pub fn read(file: &mut (impl Read + Seek), tags: &mut Tags, warnings: &mut Vec<TagWarning>) -> io::Result<()> {
+    let mut marker = [0u8; 4];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut marker)?;
+    if &marker != b"fLaC" {
+        warnings.push(TagWarning::BadBlock { block: "fLaC".to_string(), reason: "нет сигнатуры FLAC" });
+        return Ok(());
+    }
+
+    loop {
+        let mut header = [0u8; 4];
+        if file.read_exact(&mut header).is_err() {
+            warnings.push(TagWarning::Truncated("метаданные FLAC"));
+            return Ok(());
+        }
+
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        match block_type {
+            STREAMINFO | VORBIS_COMMENT => {
+                let mut data = Vec::with_capacity(len);
+                file.by_ref().take(len as u64).read_to_end(&mut data)?;
+                if data.len() < len {
+                    warnings.push(TagWarning::Truncated("метаданные FLAC"));
+                    return Ok(());
+                }
+
+                if block_type == STREAMINFO {
+                    parse_streaminfo(&data, tags, warnings);
+                } else {
+                    vorbis_comment::parse(&data, "VORBIS_COMMENT", tags, warnings);
+                }
+            }
+            // Обложки и прочие блоки бывают большими - не читаем их
+            _ => {
+                file.seek(SeekFrom::Current(len as i64))?;
+            }
+        }
+
+        if is_last {
+            return Ok(());
+        }
+    }
+}
+
+fn parse_streaminfo(data: &[u8], tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    if data.len() < STREAMINFO_LEN {
+        warnings.push(TagWarning::Truncated("STREAMINFO"));
+        return;
+    }
+
+    // Байты 10..18: частота (20 бит), каналы (3), разрядность (5), число сэмплов (36)
+    let packed = u64::from_be_bytes([data[10], data[11], data[12], data[13], data[14], data[15], data[16], data[17]]);
+    let sample_rate = packed >> 44;
+    let total_samples = packed & 0xF_FFFF_FFFF;
+
+    if sample_rate == 0 || total_samples == 0 {
+        warnings.push(TagWarning::BadBlock { block: "STREAMINFO".to_string(), reason: "длительность не указана" });
+        return;
+    }
+
+    tags.duration = Some(samples_to_seconds(total_samples, sample_rate));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::read_from;
+
+    fn metadata_block(block_type: u8, is_last: bool, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![block_type | if is_last { 0x80 } else { 0 }];
+        block.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        block.extend_from_slice(data);
+        block
+    }
+
+    fn streaminfo(sample_rate: u64, total_samples: u64) -> Vec<u8> {
+        let mut data = vec![0u8; STREAMINFO_LEN];
+        // 2 канала, 16 бит
+        let packed = sample_rate << 44 | 1 << 41 | 15 << 36 | total_samples;
+        data[10..18].copy_from_slice(&packed.to_be_bytes());
+        data
+    }
+
+    fn comments(comments: &[&str]) -> Vec<u8> {
+        let mut data = 0u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+
+    fn sample_file() -> Vec<u8> {
+        let mut file = b"fLaC".to_vec();
+        file.extend(metadata_block(STREAMINFO, false, &streaminfo(44_100, 44_100 * 185 + 30_000)));
+        file.extend(metadata_block(1, false, &[0; 1000])); // PADDING пропускается
+        file.extend(metadata_block(VORBIS_COMMENT, true, &comments(&["TITLE=Песня", "TRACKNUMBER=2"])));
+        file.extend([0xFF; 64]); // аудиокадры
+        file
+    }
+
+    #[test]
+    fn reads_duration_and_comments() {
+        let (tags, warnings) = read_from(read, &sample_file()).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.duration, Some(186));
+        assert_eq!(tags.title.as_deref(), Some("Песня"));
+        assert_eq!(tags.track, Some(2));
+    }
+
+    #[test]
+    fn missing_signature_is_reported() {
+        let (tags, warnings) = read_from(read, b"ID3\x03 not a flac").unwrap();
+        assert!(tags.title.is_none());
+        assert_eq!(warnings, [TagWarning::BadBlock { block: "fLaC".to_string(), reason: "нет сигнатуры FLAC" }]);
+        assert!(read_from(read, b"fL").is_err());
+    }
+
+    #[test]
+    fn truncated_blocks_are_reported() {
+        let file = sample_file();
+        // Обрыв посреди комментариев
+        let cut = file.len() - 64 - 5;
+        let (tags, warnings) = read_from(read, &file[..cut]).unwrap();
+        assert_eq!(tags.duration, Some(186));
+        assert_eq!(tags.title, None);
+        assert_eq!(warnings, [TagWarning::Truncated("метаданные FLAC")]);
+
+        // Нет блока с флагом "последний"
+        let mut file = b"fLaC".to_vec();
+        file.extend(metadata_block(STREAMINFO, false, &streaminfo(48_000, 48_000)));
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert_eq!(tags.duration, Some(1));
+        assert_eq!(warnings, [TagWarning::Truncated("метаданные FLAC")]);
+    }
+
+    #[test]
+    fn bad_streaminfo_is_reported() {
+        let mut file = b"fLaC".to_vec();
+        file.extend(metadata_block(STREAMINFO, true, &streaminfo(44_100, 0)));
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert_eq!(tags.duration, None);
+        assert_eq!(warnings, [TagWarning::BadBlock { block: "STREAMINFO".to_string(), reason: "длительность не указана" }]);
+
+        let mut file = b"fLaC".to_vec();
+        file.extend(metadata_block(STREAMINFO, true, &[0; 10]));
+        assert_eq!(read_from(read, &file).unwrap().1, [TagWarning::Truncated("STREAMINFO")]);
+    }
+}
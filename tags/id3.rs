@@ -0,0 +1,544 @@
+use std::borrow::Cow;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::{parse_position, TagWarning, Tags};
+
+const V2_HEADER_LEN: usize = 10;
+const V1_LEN: u64 = 128;
+
+// Жанры ID3v1 вместе с расширениями Winamp
+pub const GENRES: [&str; 192] = [
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop",
+    "Jazz", "Metal", "New Age", "Oldies", "Other", "Pop", "R&B", "Rap",
+    "Reggae", "Rock", "Techno", "Industrial", "Alternative", "Ska", "Death Metal", "Pranks",
+    "Soundtrack", "Euro-Techno", "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk", "Fusion", "Trance",
+    "Classical", "Instrumental", "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise",
+    "AlternRock", "Bass", "Soul", "Punk", "Space", "Meditative", "Instrumental Pop", "Instrumental Rock",
+    "Ethnic", "Gothic", "Darkwave", "Techno-Industrial", "Electronic", "Pop-Folk", "Eurodance", "Dream",
+    "Southern Rock", "Comedy", "Cult", "Gangsta", "Top 40", "Christian Rap", "Pop/Funk", "Jungle",
+    "Native American", "Cabaret", "New Wave", "Psychadelic", "Rave", "Showtunes", "Trailer", "Lo-Fi",
+    "Tribal", "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+    "Folk", "Folk-Rock", "National Folk", "Swing", "Fast Fusion", "Bebob", "Latin", "Revival",
+    "Celtic", "Bluegrass", "Avantgarde", "Gothic Rock", "Progressive Rock", "Psychedelic Rock", "Symphonic Rock", "Slow Rock",
+    "Big Band", "Chorus", "Easy Listening", "Acoustic", "Humour", "Speech", "Chanson", "Opera",
+    "Chamber Music", "Sonata", "Symphony", "Booty Bass", "Primus", "Porn Groove", "Satire", "Slow Jam",
+    "Club", "Tango", "Samba", "Folklore", "Ballad", "Power Ballad", "Rhythmic Soul", "Freestyle",
+    "Duet", "Punk Rock", "Drum Solo", "A capella", "Euro-House", "Dance Hall", "Goa", "Drum & Bass",
+    "Club-House", "Hardcore", "Terror", "Indie", "BritPop", "Afro-Punk", "Polsk Punk", "Beat",
+    "Christian Gangsta Rap", "Heavy Metal", "Black Metal", "Crossover", "Contemporary Christian", "Christian Rock", "Merengue", "Salsa",
+    "Thrash Metal", "Anime", "JPop", "Synthpop", "Abstract", "Art Rock", "Baroque", "Bhangra",
+    "Big Beat", "Breakbeat", "Chillout", "Downtempo", "Dub", "EBM", "Eclectic", "Electro",
+    "Electroclash", "Emo", "Experimental", "Garage", "Global", "IDM", "Illbient", "Industro-Goth",
+    "Jam Band", "Krautrock", "Leftfield", "Lounge", "Math Rock", "New Romantic", "Nu-Breakz", "Post-Punk",
+    "Post-Rock", "Psytrance", "Shoegaze", "Space Rock", "Trop Rock", "World Music", "Neoclassical", "Audiobook",
+    "Audio Theatre", "Neue Deutsche Welle", "Podcast", "Indie Rock", "G-Funk", "Dubstep", "Garage Rock", "Psybient",
+];
+
+pub fn read(file: &mut (impl Read + Seek), tags: &mut Tags, warnings: &mut Vec<TagWarning>) -> io::Result<()> {
+    let mut header = Vec::with_capacity(V2_HEADER_LEN);
+    file.seek(SeekFrom::Start(0))?;
+    file.by_ref().take(V2_HEADER_LEN as u64).read_to_end(&mut header)?;
+
+    if header.len() == V2_HEADER_LEN && header.starts_with(b"ID3") {
+        if header[6..10].iter().any(|&b| b & 0x80 != 0) {
+            warnings.push(TagWarning::BadFrame { id: "ID3v2".to_string(), reason: "размер тега не в формате syncsafe" });
+        } else {
+            let size = syncsafe(&header[6..10]) as u64;
+            let mut body = Vec::new();
+            file.by_ref().take(size).read_to_end(&mut body)?;
+            if (body.len() as u64) < size {
+                warnings.push(TagWarning::Truncated("ID3v2"));
+            }
+            parse_v2(header[3], header[5], &body, tags, warnings);
+        }
+    }
+
+    // ID3v1 лежит в последних 128 байтах и дополняет то, чего нет в ID3v2
+    if file.seek(SeekFrom::End(0))? >= V1_LEN {
+        let mut trailer = [0u8; V1_LEN as usize];
+        file.seek(SeekFrom::End(-(V1_LEN as i64)))?;
+        file.read_exact(&mut trailer)?;
+        if trailer.starts_with(b"TAG") {
+            tags.fill_missing(parse_v1(&trailer));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_v1(trailer: &[u8]) -> Tags {
+    let text = |range: std::ops::Range<usize>| {
+        let value = latin1(&trailer[range]);
+        let value = value.trim_end_matches('\0').trim();
+        if value.is_empty() { None } else { Some(value.to_string()) }
+    };
+
+    // ID3v1.1: нулевой байт перед последним байтом комментария означает номер трека
+    let track = if trailer[125] == 0 && trailer[126] != 0 { Some(trailer[126] as u32) } else { None };
+
+    Tags {
+        title: text(3..33),
+        artist: text(33..63),
+        album: text(63..93),
+        year: text(93..97).and_then(|year| year.parse().ok()),
+        track,
+        genre: GENRES.get(trailer[127] as usize).map(|genre| genre.to_string()),
+        ..Tags::default()
+    }
+}
+
+fn parse_v2(version: u8, flags: u8, body: &[u8], tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    if version != 3 && version != 4 {
+        warnings.push(TagWarning::UnsupportedVersion(format!("ID3v2.{}", version)));
+        return;
+    }
+
+    let unsynchronised = flags & 0x80 != 0;
+    // В v2.3 рассинхронизация применяется ко всему тегу, в v2.4 - к каждому кадру отдельно
+    let body: Cow<[u8]> = if version == 3 && unsynchronised {
+        Cow::Owned(remove_unsync(body))
+    } else {
+        Cow::Borrowed(body)
+    };
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 {
+        if body.len() < 4 {
+            warnings.push(TagWarning::Truncated("расширенный заголовок ID3v2"));
+            return;
+        }
+        // В v2.3 размер расширенного заголовка не включает сами 4 байта размера
+        pos = if version == 3 { be_u32(&body[0..4]) as usize + 4 } else { syncsafe(&body[0..4]) as usize };
+        if pos > body.len() {
+            warnings.push(TagWarning::Truncated("расширенный заголовок ID3v2"));
+            return;
+        }
+    }
+
+    while pos + V2_HEADER_LEN <= body.len() {
+        let frame_header = &body[pos..pos + V2_HEADER_LEN];
+        if frame_header[0] == 0 {
+            break; // дальше только выравнивание нулями
+        }
+
+        let id = String::from_utf8_lossy(&frame_header[0..4]).into_owned();
+        if !frame_header[0..4].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+            warnings.push(TagWarning::BadFrame { id, reason: "недопустимый идентификатор кадра" });
+            break;
+        }
+
+        let size = if version == 4 { syncsafe(&frame_header[4..8]) } else { be_u32(&frame_header[4..8]) } as usize;
+        let format_flags = frame_header[9];
+        pos += V2_HEADER_LEN;
+
+        if size > body.len() - pos {
+            warnings.push(TagWarning::BadFrame { id, reason: "кадр выходит за границы тега" });
+            break;
+        }
+
+        let frame = &body[pos..pos + size];
+        pos += size;
+
+        match frame_payload(version, format_flags, unsynchronised, frame) {
+            Ok(payload) => apply_frame(&id, &payload, tags, warnings),
+            Err(reason) => warnings.push(TagWarning::BadFrame { id, reason }),
+        }
+    }
+}
+
+// Снимает с данных кадра служебные байты, описанные флагами формата
+fn frame_payload(version: u8, flags: u8, tag_unsync: bool, frame: &[u8]) -> Result<Cow<'_, [u8]>, &'static str> {
+    let (compressed, encrypted, skip) = if version == 3 {
+        let grouping = if flags & 0x20 != 0 { 1 } else { 0 };
+        (flags & 0x80 != 0, flags & 0x40 != 0, grouping)
+    } else {
+        let grouping = if flags & 0x40 != 0 { 1 } else { 0 };
+        let length = if flags & 0x01 != 0 { 4 } else { 0 };
+        (flags & 0x08 != 0, flags & 0x04 != 0, grouping + length)
+    };
+
+    if compressed {
+        return Err("сжатые кадры не поддерживаются");
+    }
+    if encrypted {
+        return Err("зашифрованные кадры не поддерживаются");
+    }
+    if skip > frame.len() {
+        return Err("кадр короче своих служебных полей");
+    }
+
+    let data = &frame[skip..];
+    if version == 4 && (tag_unsync || flags & 0x02 != 0) {
+        Ok(Cow::Owned(remove_unsync(data)))
+    } else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+fn apply_frame(id: &str, data: &[u8], tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    match id {
+        "COMM" => return apply_comment(data, tags, warnings),
+        "UFID" => return apply_unique_id(data, tags),
+        "TIT2" | "TPE1" | "TPE2" | "TALB" | "TCOM" | "TCON" | "TDRC" | "TYER" | "TRCK" | "TPOS" | "TLEN" => {}
+        _ => return,
+    }
+
+    let Some(text) = decode_text(data) else {
+        warnings.push(TagWarning::BadText(id.to_string()));
+        return;
+    };
+
+    // В v2.4 кадр может содержать несколько значений, разделённых нулём
+    let Some(value) = text.split('\0').map(str::trim).find(|value| !value.is_empty()) else {
+        return;
+    };
+
+    match id {
+        "TIT2" => tags.title = Some(value.to_string()),
+        "TPE1" => tags.artist = Some(value.to_string()),
+        "TPE2" => tags.album_artist = Some(value.to_string()),
+        "TALB" => tags.album = Some(value.to_string()),
+        "TCOM" => tags.composer = Some(value.to_string()),
+        "TCON" => tags.genre = resolve_genre(value, warnings),
+        "TDRC" | "TYER" => match value.get(0..4).and_then(|year| year.parse().ok()) {
+            Some(year) => tags.year = Some(year),
+            None => warnings.push(TagWarning::BadFrame { id: id.to_string(), reason: "некорректный год" }),
+        },
+        "TRCK" => match parse_position(value) {
+            Some(track) => tags.track = Some(track),
+            None => warnings.push(TagWarning::BadFrame { id: id.to_string(), reason: "некорректный номер трека" }),
+        },
+        "TPOS" => match parse_position(value) {
+            Some(disc) => tags.disc = Some(disc),
+            None => warnings.push(TagWarning::BadFrame { id: id.to_string(), reason: "некорректный номер диска" }),
+        },
+        "TLEN" => match value.parse::<u64>() {
+            Ok(millis) => tags.duration = Some(((millis + 500) / 1000) as u32),
+            Err(_) => warnings.push(TagWarning::BadFrame { id: id.to_string(), reason: "некорректная длительность" }),
+        },
+        _ => {}
+    }
+}
+
+// COMM: кодировка, язык (3 байта), описание и текст, разделённые нулём.
+// Комментарии с описанием обычно служебные (iTunNORM и т.п.) - их пропускаем.
+fn apply_comment(data: &[u8], tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    if data.len() < 4 {
+        warnings.push(TagWarning::BadFrame { id: "COMM".to_string(), reason: "кадр слишком короткий" });
+        return;
+    }
+
+    let mut text_data = vec![data[0]];
+    text_data.extend_from_slice(&data[4..]);
+    let Some(text) = decode_text(&text_data) else {
+        warnings.push(TagWarning::BadText("COMM".to_string()));
+        return;
+    };
+
+    if let Some((description, comment)) = text.split_once('\0') {
+        let comment = comment.trim_matches('\0').trim();
+        if description.is_empty() && !comment.is_empty() && tags.comment.is_none() {
+            tags.comment = Some(comment.to_string());
+        }
+    }
+}
+
+// UFID: владелец (строка с нулём в конце) и двоичный идентификатор
+fn apply_unique_id(data: &[u8], tags: &mut Tags) {
+    if let Some(end) = data.iter().position(|&b| b == 0) {
+        if &data[..end] == b"http://musicbrainz.org" {
+            tags.mbid = String::from_utf8(data[end + 1..].to_vec()).ok();
+        }
+    }
+}
+
+// TCON бывает "Rock", "17", "(17)", "(17)Rock", "(RX)" или "((скобка в начале"
+fn resolve_genre(value: &str, warnings: &mut Vec<TagWarning>) -> Option<String> {
+    let mut rest = value;
+    let mut referenced = None;
+
+    while let Some(inner) = rest.strip_prefix('(') {
+        if inner.starts_with('(') {
+            break;
+        }
+        let Some(end) = inner.find(')') else {
+            break;
+        };
+        if referenced.is_none() {
+            referenced = genre_by_ref(&inner[..end], warnings);
+        }
+        rest = &inner[end + 1..];
+    }
+
+    let rest = rest.strip_prefix('(').filter(|r| r.starts_with('(')).unwrap_or(rest).trim();
+    if rest.is_empty() {
+        referenced
+    } else if rest.bytes().all(|b| b.is_ascii_digit()) {
+        genre_by_ref(rest, warnings)
+    } else {
+        // Текстовое уточнение точнее номера из таблицы
+        Some(rest.to_string())
+    }
+}
+
+fn genre_by_ref(reference: &str, warnings: &mut Vec<TagWarning>) -> Option<String> {
+    match reference {
+        "RX" => Some("Remix".to_string()),
+        "CR" => Some("Cover".to_string()),
+        _ => {
+            let genre = reference.parse::<usize>().ok().and_then(|index| GENRES.get(index));
+            if genre.is_none() {
+                warnings.push(TagWarning::UnknownGenre(reference.to_string()));
+            }
+            genre.map(|genre| genre.to_string())
+        }
+    }
+}
+
+fn decode_text(data: &[u8]) -> Option<String> {
+    let (&encoding, text) = data.split_first()?;
+    match encoding {
+        0 => Some(latin1(text)),
+        1 => {
+            let big_endian = text.starts_with(&[0xFE, 0xFF]);
+            if !big_endian && !text.starts_with(&[0xFF, 0xFE]) && !text.is_empty() {
+                return None;
+            }
+            decode_utf16(text, big_endian)
+        }
+        2 => decode_utf16(text, true),
+        3 => String::from_utf8(text.to_vec()).ok(),
+        _ => None,
+    }
+}
+
+fn decode_utf16(text: &[u8], big_endian: bool) -> Option<String> {
+    let units: Vec<u16> = text
+        .chunks_exact(2)
+        .map(|pair| if big_endian { u16::from_be_bytes([pair[0], pair[1]]) } else { u16::from_le_bytes([pair[0], pair[1]]) })
+        .collect();
+
+    // BOM стоит перед каждым значением, а не только в начале кадра
+    let decoded = String::from_utf16(&units).ok()?;
+    Some(decoded.replace('\u{FEFF}', ""))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn remove_unsync(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut previous = 0;
+    for &byte in data {
+        if !(previous == 0xFF && byte == 0x00) {
+            result.push(byte);
+        }
+        previous = byte;
+    }
+    result
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, &b| (acc << 7) | (b & 0x7F) as u32)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::read_from;
+
+    fn syncsafe_bytes(size: usize) -> [u8; 4] {
+        [(size >> 21) as u8 & 0x7F, (size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, size as u8 & 0x7F]
+    }
+
+    fn frame(version: u8, id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        if version == 4 {
+            frame.extend_from_slice(&syncsafe_bytes(data.len()));
+        } else {
+            frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        }
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn text(version: u8, id: &[u8; 4], value: &str) -> Vec<u8> {
+        let mut data = vec![3];
+        data.extend_from_slice(value.as_bytes());
+        frame(version, id, &data)
+    }
+
+    fn tag(version: u8, flags: u8, body: &[u8]) -> Vec<u8> {
+        let mut tag = vec![b'I', b'D', b'3', version, 0, flags];
+        tag.extend_from_slice(&syncsafe_bytes(body.len()));
+        tag.extend_from_slice(body);
+        tag
+    }
+
+    fn v1(title: &str, track: u8, genre: u8) -> [u8; 128] {
+        let mut trailer = [0u8; 128];
+        trailer[..3].copy_from_slice(b"TAG");
+        trailer[3..3 + title.len()].copy_from_slice(title.as_bytes());
+        trailer[33..39].copy_from_slice(b"Artist");
+        trailer[93..97].copy_from_slice(b"1984");
+        trailer[126] = track;
+        trailer[127] = genre;
+        trailer
+    }
+
+
+    fn parse(version: u8, flags: u8, body: &[u8]) -> (Tags, Vec<TagWarning>) {
+        let mut tags = Tags::default();
+        let mut warnings = Vec::new();
+        parse_v2(version, flags, body, &mut tags, &mut warnings);
+        (tags, warnings)
+    }
+
+    #[test]
+    fn reads_v23_frames_in_every_encoding() {
+        let mut utf16 = vec![1, 0xFF, 0xFE];
+        utf16.extend("Кино".encode_utf16().flat_map(u16::to_le_bytes));
+        let mut body = frame(3, b"TIT2", b"\0Gruppa krovi");
+        body.extend(frame(3, b"TPE1", &utf16));
+        body.extend(text(3, b"TRCK", "3/12"));
+        body.extend(text(3, b"TYER", "1988"));
+        body.extend(text(3, b"TCON", "(17)"));
+        body.extend(text(3, b"TLEN", "285500"));
+        body.extend(frame(3, b"COMM", b"\0eng\0Hello"));
+        body.extend(frame(3, b"UFID", b"http://musicbrainz.org\0abc-123"));
+        body.extend([0; 16]); // выравнивание
+
+        let (tags, warnings) = parse(3, 0, &body);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.title.as_deref(), Some("Gruppa krovi"));
+        assert_eq!(tags.artist.as_deref(), Some("Кино"));
+        assert_eq!((tags.track, tags.year, tags.duration), (Some(3), Some(1988), Some(286)));
+        assert_eq!(tags.genre.as_deref(), Some("Rock"));
+        assert_eq!(tags.comment.as_deref(), Some("Hello"));
+        assert_eq!(tags.mbid.as_deref(), Some("abc-123"));
+    }
+
+    #[test]
+    fn reads_v24_syncsafe_sizes_and_takes_the_first_value() {
+        // 200 байт: в syncsafe и обычной записи размер различается
+        let long = "x".repeat(200);
+        let mut body = text(4, b"TALB", &long);
+        body.extend(text(4, b"TPE1", "First\0Second"));
+        body.extend(text(4, b"TDRC", "2003-04-05"));
+
+        let (tags, warnings) = parse(4, 0, &body);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.album.as_deref(), Some(long.as_str()));
+        assert_eq!(tags.artist.as_deref(), Some("First"));
+        assert_eq!(tags.year, Some(2003));
+    }
+
+    #[test]
+    fn v23_unsynchronisation_is_removed() {
+        let body = text(3, b"TIT2", "a\u{ff}b");
+        let mut unsync = Vec::new();
+        for &byte in &body {
+            unsync.push(byte);
+            if byte == 0xFF {
+                unsync.push(0);
+            }
+        }
+        // Размер кадра в v2.3 считается до рассинхронизации
+        let (tags, warnings) = parse(3, 0x80, &unsync);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.title.as_deref(), Some("a\u{ff}b"));
+    }
+
+    #[test]
+    fn v1_fills_what_v2_does_not_have() {
+        let mut file = tag(3, 0, &text(3, b"TIT2", "From v2"));
+        file.extend([0xFF; 300]); // "аудио"
+        file.extend(v1("From v1", 5, 13));
+
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.title.as_deref(), Some("From v2"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!((tags.year, tags.track), (Some(1984), Some(5)));
+        assert_eq!(tags.genre.as_deref(), Some("Pop"));
+    }
+
+    #[test]
+    fn genre_references() {
+        let mut warnings = Vec::new();
+        assert_eq!(resolve_genre("Rock", &mut warnings).as_deref(), Some("Rock"));
+        assert_eq!(resolve_genre("17", &mut warnings).as_deref(), Some("Rock"));
+        assert_eq!(resolve_genre("(17)Hard Rock", &mut warnings).as_deref(), Some("Hard Rock"));
+        assert_eq!(resolve_genre("(RX)", &mut warnings).as_deref(), Some("Remix"));
+        assert_eq!(resolve_genre("((Brackets)", &mut warnings).as_deref(), Some("(Brackets)"));
+        assert!(warnings.is_empty());
+        assert_eq!(resolve_genre("(250)", &mut warnings), None);
+        assert_eq!(warnings, [TagWarning::UnknownGenre("250".to_string())]);
+    }
+
+    #[test]
+    fn frame_past_the_tag_keeps_earlier_frames() {
+        let mut body = text(3, b"TIT2", "Title");
+        let mut broken = text(3, b"TPE1", "Artist");
+        broken[4..8].copy_from_slice(&1000u32.to_be_bytes());
+        body.extend(broken);
+
+        let (tags, warnings) = parse(3, 0, &body);
+        assert_eq!(tags.title.as_deref(), Some("Title"));
+        assert_eq!(tags.artist, None);
+        assert_eq!(warnings, [TagWarning::BadFrame { id: "TPE1".to_string(), reason: "кадр выходит за границы тега" }]);
+    }
+
+    #[test]
+    fn malformed_frames_are_reported() {
+        let (_, warnings) = parse(3, 0, &text(3, b"ti!2", "x"));
+        assert!(matches!(&warnings[..], [TagWarning::BadFrame { reason: "недопустимый идентификатор кадра", .. }]));
+
+        let mut compressed = text(3, b"TIT2", "x");
+        compressed[9] = 0x80;
+        let (tags, warnings) = parse(3, 0, &compressed);
+        assert_eq!(tags.title, None);
+        assert!(matches!(&warnings[..], [TagWarning::BadFrame { reason: "сжатые кадры не поддерживаются", .. }]));
+
+        // UTF-16 без BOM
+        let (_, warnings) = parse(3, 0, &frame(3, b"TIT2", &[1, b'a', 0]));
+        assert_eq!(warnings, [TagWarning::BadText("TIT2".to_string())]);
+
+        let (_, warnings) = parse(3, 0, &text(3, b"TRCK", "track"));
+        assert!(matches!(&warnings[..], [TagWarning::BadFrame { reason: "некорректный номер трека", .. }]));
+
+        let (_, warnings) = parse(3, 0, &frame(3, b"COMM", b"\0e"));
+        assert!(matches!(&warnings[..], [TagWarning::BadFrame { reason: "кадр слишком короткий", .. }]));
+
+        // Расширенный заголовок длиннее тега
+        let (_, warnings) = parse(3, 0x40, &[0, 0, 0, 50, 0, 0]);
+        assert_eq!(warnings, [TagWarning::Truncated("расширенный заголовок ID3v2")]);
+    }
+
+    #[test]
+    fn broken_headers_are_reported() {
+        let (_, warnings) = parse(2, 0, &[]);
+        assert_eq!(warnings, [TagWarning::UnsupportedVersion("ID3v2.2".to_string())]);
+
+        let mut not_syncsafe = tag(3, 0, &text(3, b"TIT2", "x"));
+        not_syncsafe[9] = 0x80;
+        let (tags, warnings) = read_from(read, &not_syncsafe).unwrap();
+        assert_eq!(tags.title, None);
+        assert!(matches!(&warnings[..], [TagWarning::BadFrame { reason: "размер тега не в формате syncsafe", .. }]));
+
+        let mut truncated = tag(3, 0, &text(3, b"TIT2", "Title"));
+        truncated[9] += 20;
+        let (tags, warnings) = read_from(read, &truncated).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Title"));
+        assert_eq!(warnings, [TagWarning::Truncated("ID3v2")]);
+
+        let (tags, warnings) = read_from(read, b"").unwrap();
+        assert!(tags.title.is_none() && warnings.is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+mod flac;
+mod id3;
+mod mp4;
+mod ogg;
+mod vorbis_comment;
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::decode::{Decoder, WavDecoder};
+use crate::scanner::AudioFormat;
+use crate::Song;
+
+// Метаданные, найденные в тегах файла. None - поля в тегах нет.
+#[derive(Debug, Default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    pub mbid: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u16>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub duration: Option<u32>, // в секундах
+    pub cover: Option<Cover>,
+}
+
+// Обложка внутри аудиофайла: сами байты не копируем, а запоминаем, где они лежат
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cover {
+    pub format: CoverFormat,
+    pub offset: u64, // от начала файла
+    pub len: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoverFormat {
+    Jpeg,
+    Png,
+    Bmp,
+    Unknown,
+}
+
+impl CoverFormat {
+    // По сигнатуре в начале изображения, когда контейнер формат не указал
+    pub fn sniff(image: &[u8]) -> CoverFormat {
+        if image.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            CoverFormat::Jpeg
+        } else if image.starts_with(b"\x89PNG") {
+            CoverFormat::Png
+        } else if image.starts_with(b"BM") {
+            CoverFormat::Bmp
+        } else {
+            CoverFormat::Unknown
+        }
+    }
+}
+
+impl Tags {
+    // Заполняет пустые поля значениями из `other` (например, ID3v2 поверх ID3v1)
+    fn fill_missing(&mut self, other: Tags) {
+        self.title = self.title.take().or(other.title);
+        self.artist = self.artist.take().or(other.artist);
+        self.album = self.album.take().or(other.album);
+        self.album_artist = self.album_artist.take().or(other.album_artist);
+        self.composer = self.composer.take().or(other.composer);
+        self.comment = self.comment.take().or(other.comment);
+        self.mbid = self.mbid.take().or(other.mbid);
+        self.genre = self.genre.take().or(other.genre);
+        self.year = self.year.or(other.year);
+        self.track = self.track.or(other.track);
+        self.disc = self.disc.or(other.disc);
+        self.duration = self.duration.or(other.duration);
+        self.cover = self.cover.or(other.cover);
+    }
+
+    pub fn apply(self, song: &mut Song) {
+        if let Some(title) = self.title {
+            song.title = title;
+        }
+        if let Some(artist) = self.artist {
+            song.artist = artist;
+        }
+        if let Some(album) = self.album {
+            song.album = album;
+        }
+        if let Some(album_artist) = self.album_artist {
+            song.album_artist = album_artist;
+        }
+        if let Some(composer) = self.composer {
+            song.composer = composer;
+        }
+        if let Some(comment) = self.comment {
+            song.comment = comment;
+        }
+        if self.mbid.is_some() {
+            song.mbid = self.mbid;
+        }
+        if let Some(genre) = self.genre {
+            song.genre = genre;
+        }
+        if let Some(year) = self.year {
+            song.year = year;
+        }
+        if let Some(track) = self.track {
+            song.track = track;
+        }
+        if let Some(disc) = self.disc {
+            song.disc = disc;
+        }
+        if let Some(duration) = self.duration {
+            song.duration = duration;
+        }
+        if self.cover.is_some() {
+            song.cover = self.cover;
+        }
+    }
+}
+
+// Проблемы в тегах не мешают добавить файл в библиотеку, но попадают в отчёт
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagWarning {
+    Truncated(&'static str),
+    UnsupportedVersion(String),
+    BadFrame { id: String, reason: &'static str },
+    BadBlock { block: String, reason: &'static str },
+    BadText(String),
+    UnknownGenre(String),
+}
+
+impl fmt::Display for TagWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagWarning::Truncated(what) => write!(f, "{}: данные обрываются раньше времени", what),
+            TagWarning::UnsupportedVersion(version) => write!(f, "неподдерживаемая версия {}", version),
+            TagWarning::BadFrame { id, reason } => write!(f, "кадр {}: {}", id, reason),
+            TagWarning::BadBlock { block, reason } => write!(f, "блок {}: {}", block, reason),
+            TagWarning::BadText(id) => write!(f, "кадр {}: не удалось декодировать текст", id),
+            TagWarning::UnknownGenre(genre) => write!(f, "неизвестный номер жанра {}", genre),
+        }
+    }
+}
+
+pub fn read_tags(path: &Path, format: AudioFormat) -> io::Result<(Tags, Vec<TagWarning>)> {
+    let mut tags = Tags::default();
+    let mut warnings = Vec::new();
+    let mut file = File::open(path)?;
+
+    match format {
+        AudioFormat::Mp3 => id3::read(&mut file, &mut tags, &mut warnings)?,
+        AudioFormat::Flac => flac::read(&mut file, &mut tags, &mut warnings)?,
+        AudioFormat::Ogg => ogg::read(&mut file, &mut tags, &mut warnings)?,
+        AudioFormat::Mp4 => mp4::read(&mut file, &mut tags, &mut warnings)?,
+        // Тегов в WAV не читаем, но длительность берём из заголовка
+        AudioFormat::Wav => match WavDecoder::new(file) {
+            Ok(decoder) => {
+                let info = decoder.info();
+                tags.duration = info.total_frames.map(|frames| samples_to_seconds(frames, info.sample_rate as u64));
+            }
+            Err(_) => warnings.push(TagWarning::BadBlock { block: "fmt".to_string(), reason: "не удалось разобрать заголовок WAV" }),
+        },
+    }
+
+    Ok((tags, warnings))
+}
+
+// Разбор тегов из байтов в памяти: парсеры принимают любой Read + Seek
+#[cfg(test)]
+fn read_from<'a>(
+    read: impl FnOnce(&mut io::Cursor<&'a [u8]>, &mut Tags, &mut Vec<TagWarning>) -> io::Result<()>,
+    bytes: &'a [u8],
+) -> io::Result<(Tags, Vec<TagWarning>)> {
+    let mut tags = Tags::default();
+    let mut warnings = Vec::new();
+    read(&mut io::Cursor::new(bytes), &mut tags, &mut warnings)?;
+    Ok((tags, warnings))
+}
+
+// Номер трека или диска: "3" или "3/12"
+fn parse_position(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+// Длительность по числу сэмплов, с округлением до ближайшей секунды
+fn samples_to_seconds(samples: u64, sample_rate: u64) -> u32 {
+    ((samples + sample_rate / 2) / sample_rate) as u32
+}
@@ -0,0 +1,466 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::id3::GENRES;
+use super::{samples_to_seconds, Cover, CoverFormat, TagWarning, Tags};
+
+// moov с метаданными обычно занимает килобайты; больше - почти наверняка мусор
+const MAX_MOOV_LEN: u64 = 64 * 1024 * 1024;
+
+const NAME: [u8; 4] = [0xA9, b'n', b'a', b'm'];
+const ARTIST: [u8; 4] = [0xA9, b'A', b'R', b'T'];
+const ALBUM: [u8; 4] = [0xA9, b'a', b'l', b'b'];
+const GENRE: [u8; 4] = [0xA9, b'g', b'e', b'n'];
+const DAY: [u8; 4] = [0xA9, b'd', b'a', b'y'];
+const COMPOSER: [u8; 4] = [0xA9, b'w', b'r', b't'];
+const COMMENT: [u8; 4] = [0xA9, b'c', b'm', b't'];
+const ALBUM_ARTIST: [u8; 4] = *b"aART";
+
+pub fn read(file: &mut (impl Read + Seek), tags: &mut Tags, warnings: &mut Vec<TagWarning>) -> io::Result<()> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    let mut pos = 0;
+
+    // Верхний уровень обходим через seek: mdat может идти перед moov и весить гигабайты
+    while pos + 8 <= file_len {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+
+        let kind = [header[4], header[5], header[6], header[7]];
+        let (header_len, box_len) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (8, file_len - pos),
+            1 => {
+                file.read_exact(&mut header[8..16])?;
+                (16, u64::from_be_bytes(header[8..16].try_into().unwrap()))
+            }
+            len => (8, len as u64),
+        };
+
+        // Без сложения: pos + box_len переполняется на 64-битном размере у u64::MAX
+        if box_len < header_len || box_len > file_len - pos {
+            warnings.push(TagWarning::BadBlock { block: box_name(&kind), reason: "размер атома выходит за границы файла" });
+            return Ok(());
+        }
+
+        if &kind == b"moov" {
+            let body_len = box_len - header_len;
+            if body_len > MAX_MOOV_LEN {
+                warnings.push(TagWarning::BadBlock { block: "moov".to_string(), reason: "атом слишком большой" });
+                return Ok(());
+            }
+            let mut moov = vec![0u8; body_len as usize];
+            file.read_exact(&mut moov)?;
+            parse_moov(&moov, pos + header_len, tags, warnings);
+            return Ok(());
+        }
+
+        pos += box_len;
+    }
+
+    warnings.push(TagWarning::BadBlock { block: "moov".to_string(), reason: "атом не найден" });
+    Ok(())
+}
+
+// moov_offset - смещение тела moov в файле, от него считается положение обложки
+fn parse_moov(moov: &[u8], moov_offset: u64, tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    for (kind, body) in children(moov, warnings) {
+        match &kind {
+            b"mvhd" => parse_mvhd(body, tags, warnings),
+            b"udta" => {
+                // meta - "полный" атом: перед дочерними атомами 4 байта версии и флагов
+                let meta = find_child(body, b"meta", warnings).filter(|meta| meta.len() >= 4);
+                if let Some(ilst) = meta.and_then(|meta| find_child(&meta[4..], b"ilst", warnings)) {
+                    parse_ilst(ilst, moov_offset + offset_in(moov, ilst), tags, warnings);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_mvhd(body: &[u8], tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    // Версия 1 использует 64-битные поля времени
+    let (timescale, duration) = match body.first() {
+        Some(0) if body.len() >= 20 => (be_u32(&body[12..16]) as u64, be_u32(&body[16..20]) as u64),
+        Some(1) if body.len() >= 32 => (be_u32(&body[20..24]) as u64, u64::from_be_bytes(body[24..32].try_into().unwrap())),
+        _ => {
+            warnings.push(TagWarning::Truncated("mvhd"));
+            return;
+        }
+    };
+
+    if timescale == 0 {
+        warnings.push(TagWarning::BadBlock { block: "mvhd".to_string(), reason: "нулевой timescale" });
+        return;
+    }
+    tags.duration = Some(samples_to_seconds(duration, timescale));
+}
+
+fn parse_ilst(ilst: &[u8], ilst_offset: u64, tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    for (kind, item) in children(ilst, warnings) {
+        // Значение лежит в дочернем атоме data: тип (4 байта), локаль (4 байта), данные
+        let data = find_child(item, b"data", warnings);
+        let Some(data) = data.filter(|data| data.len() >= 8) else {
+            warnings.push(TagWarning::BadBlock { block: box_name(&kind), reason: "нет атома data" });
+            continue;
+        };
+        let value_type = be_u32(&data[0..4]) & 0x00FF_FFFF;
+        let value = &data[8..];
+
+        let text = || match value_type {
+            1 => String::from_utf8(value.to_vec()).ok(),
+            2 => {
+                let units: Vec<u16> = value.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                String::from_utf16(&units).ok()
+            }
+            _ => None,
+        };
+
+        match &kind {
+            &NAME | &ARTIST | &ALBUM | &GENRE | &DAY | &COMPOSER | &COMMENT | &ALBUM_ARTIST => {
+                let Some(text) = text() else {
+                    warnings.push(TagWarning::BadText(box_name(&kind)));
+                    continue;
+                };
+                let text = text.trim().to_string();
+                match kind {
+                    NAME => tags.title = Some(text),
+                    ARTIST => tags.artist = Some(text),
+                    ALBUM => tags.album = Some(text),
+                    GENRE => tags.genre = Some(text),
+                    COMPOSER => tags.composer = Some(text),
+                    COMMENT => tags.comment = Some(text),
+                    ALBUM_ARTIST => tags.album_artist = Some(text),
+                    _ => match text.get(0..4).and_then(|year| year.parse().ok()) {
+                        Some(year) => tags.year = Some(year),
+                        None => warnings.push(TagWarning::BadBlock { block: box_name(&kind), reason: "некорректный год" }),
+                    },
+                }
+            }
+            // gnre хранит номер жанра ID3v1, увеличенный на единицу
+            b"gnre" if value.len() >= 2 => {
+                let index = u16::from_be_bytes([value[0], value[1]]) as usize;
+                match index.checked_sub(1).and_then(|index| GENRES.get(index)) {
+                    Some(genre) => {
+                        tags.genre.get_or_insert_with(|| genre.to_string());
+                    }
+                    None => warnings.push(TagWarning::UnknownGenre(index.to_string())),
+                }
+            }
+            // trkn и disk: 2 байта выравнивания, номер трека, общее число треков
+            b"trkn" if value.len() >= 4 => {
+                tags.track = Some(u16::from_be_bytes([value[2], value[3]]) as u32);
+            }
+            b"disk" if value.len() >= 4 => {
+                tags.disc = Some(u16::from_be_bytes([value[2], value[3]]) as u32);
+            }
+            // covr: тип data говорит формат изображения (13 - JPEG, 14 - PNG, 27 - BMP)
+            b"covr" if value.is_empty() => {
+                warnings.push(TagWarning::BadBlock { block: "covr".to_string(), reason: "пустая обложка" });
+            }
+            b"covr" => {
+                let format = match value_type {
+                    13 => CoverFormat::Jpeg,
+                    14 => CoverFormat::Png,
+                    27 => CoverFormat::Bmp,
+                    _ => CoverFormat::sniff(value),
+                };
+                tags.cover = Some(Cover { format, offset: ilst_offset + offset_in(ilst, value), len: value.len() as u64 });
+            }
+            _ => {}
+        }
+    }
+}
+
+// Разбирает дочерние атомы; на повреждённом атоме пишет предупреждение и останавливается
+fn children<'a>(mut data: &'a [u8], warnings: &mut Vec<TagWarning>) -> Vec<([u8; 4], &'a [u8])> {
+    let mut result = Vec::new();
+
+    while data.len() >= 8 {
+        let kind = [data[4], data[5], data[6], data[7]];
+        let (header_len, box_len) = match be_u32(&data[0..4]) {
+            0 => (8, data.len()),
+            1 if data.len() >= 16 => (16, u64::from_be_bytes(data[8..16].try_into().unwrap()) as usize),
+            len => (8, len as usize),
+        };
+
+        if box_len < header_len || box_len > data.len() {
+            warnings.push(TagWarning::BadBlock { block: box_name(&kind), reason: "размер атома выходит за границы родителя" });
+            break;
+        }
+
+        result.push((kind, &data[header_len..box_len]));
+        data = &data[box_len..];
+    }
+
+    result
+}
+
+fn find_child<'a>(data: &'a [u8], kind: &[u8; 4], warnings: &mut Vec<TagWarning>) -> Option<&'a [u8]> {
+    children(data, warnings).into_iter().find(|(child, _)| child == kind).map(|(_, body)| body)
+}
+
+// Смещение подсреза inner от начала outer; inner всегда берётся из outer
+fn offset_in(outer: &[u8], inner: &[u8]) -> u64 {
+    (inner.as_ptr() as usize - outer.as_ptr() as usize) as u64
+}
+
+fn box_name(kind: &[u8; 4]) -> String {
+    kind.iter().map(|&b| if b == 0xA9 { '©' } else { b as char }).collect()
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::read_from;
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn text_item(kind: &[u8; 4], text: &str) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend_from_slice(text.as_bytes());
+        atom(kind, &atom(b"data", &data))
+    }
+
+    fn sample_file() -> Vec<u8> {
+        let mut mvhd = vec![0u8; 20];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&185_000u32.to_be_bytes());
+
+        let mut ilst = text_item(&NAME, "Песня");
+        ilst.extend(text_item(&ARTIST, "Artist"));
+        ilst.extend(text_item(&DAY, "1999-05-01"));
+        ilst.extend(atom(b"trkn", &atom(b"data", &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 0, 12])));
+        ilst.extend(atom(b"gnre", &atom(b"data", &[0, 0, 0, 0, 0, 0, 0, 0, 0, 18])));
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(atom(b"ilst", &ilst));
+
+        let mut moov = atom(b"mvhd", &mvhd);
+        moov.extend(atom(b"udta", &atom(b"meta", &meta)));
+
+        let mut file = atom(b"ftyp", b"M4A \0\0\0\0");
+        file.extend(atom(b"mdat", &[0; 32]));
+        file.extend(atom(b"moov", &moov));
+        file
+    }
+
+    #[test]
+    fn reads_ilst_after_mdat() {
+        let (tags, warnings) = read_from(read, &sample_file()).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.title.as_deref(), Some("Песня"));
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.year, Some(1999));
+        assert_eq!(tags.track, Some(7));
+        assert_eq!(tags.genre.as_deref(), Some("Rock"));
+        assert_eq!(tags.duration, Some(185));
+    }
+
+    #[test]
+    fn huge_64bit_size_is_rejected_without_overflow() {
+        let mut file = atom(b"ftyp", b"M4A \0\0\0\0");
+        file.extend_from_slice(&1u32.to_be_bytes());
+        file.extend_from_slice(b"free");
+        file.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        file.extend_from_slice(&[0; 8]);
+        assert_eq!(file.len(), 40);
+
+        let (_, warnings) = read_from(read, &file).unwrap();
+        assert!(matches!(&warnings[..], [TagWarning::BadBlock { block, .. }] if block == "free"));
+    }
+
+    #[test]
+    fn size_smaller_than_header_is_rejected() {
+        let mut file = 1u32.to_be_bytes().to_vec();
+        file.extend_from_slice(b"ftyp");
+        file.extend_from_slice(&8u64.to_be_bytes());
+        let (_, warnings) = read_from(read, &file).unwrap();
+        assert!(matches!(&warnings[..], [TagWarning::BadBlock { block, .. }] if block == "ftyp"));
+    }
+
+    #[test]
+    fn child_past_parent_stops_parsing() {
+        let mut moov = atom(b"udta", &[]);
+        moov.extend_from_slice(&100u32.to_be_bytes());
+        moov.extend_from_slice(b"mvhd");
+        let (tags, warnings) = read_from(read, &atom(b"moov", &moov)).unwrap();
+        assert_eq!(tags.duration, None);
+        assert!(matches!(&warnings[..], [TagWarning::BadBlock { block, .. }] if block == "mvhd"));
+    }
+
+    #[test]
+    fn missing_moov_is_reported() {
+        let (_, warnings) = read_from(read, &atom(b"ftyp", b"M4A \0\0\0\0")).unwrap();
+        assert!(matches!(&warnings[..], [TagWarning::BadBlock { block, .. }] if block == "moov"));
+    }
+
+    fn moov_with_ilst(ilst: &[u8]) -> Vec<u8> {
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(atom(b"ilst", ilst));
+        atom(b"moov", &atom(b"udta", &atom(b"meta", &meta)))
+    }
+
+    #[test]
+    fn mvhd_versions_and_bad_timescale() {
+        let mut mvhd = vec![1u8; 32];
+        mvhd[..4].copy_from_slice(&[1, 0, 0, 0]);
+        mvhd[20..24].copy_from_slice(&48_000u32.to_be_bytes());
+        mvhd[24..32].copy_from_slice(&(48_000u64 * 7_200).to_be_bytes());
+        let (tags, warnings) = read_from(read, &atom(b"moov", &atom(b"mvhd", &mvhd))).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.duration, Some(7_200));
+
+        let (tags, warnings) = read_from(read, &atom(b"moov", &atom(b"mvhd", &[0; 20]))).unwrap();
+        assert_eq!(tags.duration, None);
+        assert_eq!(warnings, [TagWarning::BadBlock { block: "mvhd".to_string(), reason: "нулевой timescale" }]);
+
+        let (_, warnings) = read_from(read, &atom(b"moov", &atom(b"mvhd", &[1; 20]))).unwrap();
+        assert_eq!(warnings, [TagWarning::Truncated("mvhd")]);
+    }
+
+    #[test]
+    fn utf16_text_and_last_atom_without_size() {
+        let mut utf16 = vec![0, 0, 0, 2, 0, 0, 0, 0];
+        utf16.extend("Кино".encode_utf16().flat_map(u16::to_be_bytes));
+        let mut ilst = atom(&ARTIST, &atom(b"data", &utf16));
+        ilst.extend(atom(&ALBUM_ARTIST, &atom(b"data", &[0, 0, 0, 1, 0, 0, 0, 0, b'V', b'A'])));
+        ilst.extend(atom(b"disk", &atom(b"data", &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2])));
+
+        // Размер 0: атом тянется до конца файла
+        let mut file = atom(b"ftyp", b"M4A \0\0\0\0");
+        let mut moov = moov_with_ilst(&ilst);
+        moov[..4].copy_from_slice(&[0; 4]);
+        file.extend(moov);
+
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.artist.as_deref(), Some("Кино"));
+        assert_eq!(tags.album_artist.as_deref(), Some("VA"));
+        assert_eq!(tags.disc, Some(2));
+    }
+
+    #[test]
+    fn bad_items_are_reported_and_skipped() {
+        let mut ilst = atom(&NAME, &atom(b"data", &[0, 0, 0, 1, 0, 0, 0, 0, 0xC3, 0x28]));
+        ilst.extend(atom(&ALBUM, &atom(b"name", b"no data")));
+        ilst.extend(atom(&DAY, &atom(b"data", &[0, 0, 0, 1, 0, 0, 0, 0, b'n', b'/', b'a'])));
+        ilst.extend(atom(b"gnre", &atom(b"data", &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0])));
+        ilst.extend(text_item(&ARTIST, "Still read"));
+
+        let (tags, warnings) = read_from(read, &moov_with_ilst(&ilst)).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Still read"));
+        assert_eq!((tags.title, tags.album, tags.year, tags.genre), (None, None, None, None));
+        assert_eq!(warnings, [
+            TagWarning::BadText("©nam".to_string()),
+            TagWarning::BadBlock { block: "©alb".to_string(), reason: "нет атома data" },
+            TagWarning::BadBlock { block: "©day".to_string(), reason: "некорректный год" },
+            TagWarning::UnknownGenre("0".to_string()),
+        ]);
+    }
+
+    // Поток заданной длины из head и нулей после него, без выделения памяти под нули
+    struct Sparse {
+        head: Vec<u8>,
+        len: u64,
+        pos: u64,
+    }
+
+    impl Read for Sparse {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.len.saturating_sub(self.pos) as usize);
+            for (i, byte) in buf[..n].iter_mut().enumerate() {
+                *byte = self.head.get(self.pos as usize + i).copied().unwrap_or(0);
+            }
+            self.pos += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for Sparse {
+        fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+            self.pos = match from {
+                SeekFrom::Start(pos) => pos,
+                SeekFrom::End(offset) => self.len.saturating_add_signed(offset),
+                SeekFrom::Current(offset) => self.pos.saturating_add_signed(offset),
+            };
+            Ok(self.pos)
+        }
+    }
+
+    #[test]
+    fn oversized_moov_is_not_read() {
+        // moov на 64 МБ с лишним, из которых прочитать можно только заголовок
+        let moov_len = MAX_MOOV_LEN + 16;
+        let mut head = (moov_len as u32).to_be_bytes().to_vec();
+        head.extend_from_slice(b"moov");
+        let mut file = Sparse { head, len: moov_len, pos: 0 };
+
+        let mut tags = Tags::default();
+        let mut warnings = Vec::new();
+        read(&mut file, &mut tags, &mut warnings).unwrap();
+        assert_eq!(warnings, [TagWarning::BadBlock { block: "moov".to_string(), reason: "атом слишком большой" }]);
+        assert!(file.pos <= 16, "{}", file.pos);
+    }
+
+    fn cover_item(value_type: u8, image: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, value_type, 0, 0, 0, 0];
+        data.extend_from_slice(image);
+        atom(b"covr", &atom(b"data", &data))
+    }
+
+    #[test]
+    fn cover_format_and_byte_range() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3];
+        let png = *b"\x89PNG\r\n\x1a\n....";
+        let cases: [(u8, &[u8], CoverFormat); 5] = [
+            (13, &jpeg, CoverFormat::Jpeg),
+            (14, &png, CoverFormat::Png),
+            (27, b"BM......", CoverFormat::Bmp),
+            (0, &png, CoverFormat::Png),
+            (0, b"GIF89a", CoverFormat::Unknown),
+        ];
+
+        for (value_type, image, format) in cases {
+            let mut ilst = text_item(&NAME, "С обложкой");
+            ilst.extend(cover_item(value_type, image));
+            let mut file = atom(b"ftyp", b"M4A \0\0\0\0");
+            file.extend(atom(b"mdat", &[0; 32]));
+            file.extend(moov_with_ilst(&ilst));
+
+            let (tags, warnings) = read_from(read, &file).unwrap();
+            assert!(warnings.is_empty(), "{:?}", warnings);
+            let cover = tags.cover.unwrap();
+            assert_eq!(cover.format, format, "тип {}", value_type);
+            let range = cover.offset as usize..(cover.offset + cover.len) as usize;
+            assert_eq!(&file[range], image, "тип {}", value_type);
+        }
+    }
+
+    #[test]
+    fn broken_cover_does_not_panic() {
+        // Пустое изображение и data короче заголовка
+        let mut ilst = cover_item(13, &[]);
+        ilst.extend(atom(b"covr", &atom(b"data", &[0, 0, 0, 13])));
+        let (tags, warnings) = read_from(read, &moov_with_ilst(&ilst)).unwrap();
+        assert_eq!(tags.cover, None);
+        assert_eq!(warnings, [
+            TagWarning::BadBlock { block: "covr".to_string(), reason: "пустая обложка" },
+            TagWarning::BadBlock { block: "covr".to_string(), reason: "нет атома data" },
+        ]);
+
+        // data заявляет размер больше, чем осталось в covr
+        let mut covr = atom(b"data", &[0, 0, 0, 13, 0, 0, 0, 0, 0xFF, 0xD8, 0xFF]);
+        covr[..4].copy_from_slice(&1000u32.to_be_bytes());
+        let (tags, warnings) = read_from(read, &moov_with_ilst(&atom(b"covr", &covr))).unwrap();
+        assert_eq!(tags.cover, None);
+        assert!(matches!(&warnings[..], [TagWarning::BadBlock { block, .. }, _] if block == "data"), "{:?}", warnings);
+    }
+}
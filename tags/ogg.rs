@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use super::{samples_to_seconds, vorbis_comment, TagWarning, Tags};
+
+const PAGE_HEADER_LEN: usize = 27;
+// Последняя страница потока почти всегда умещается в хвосте такого размера
+const TAIL_SEARCH: u64 = 64 * 1024;
+const OPUS_SAMPLE_RATE: u64 = 48_000;
+
+enum Codec {
+    Vorbis { sample_rate: u64 },
+    Opus { pre_skip: u64 },
+}
+
+pub fn read(file: &mut (impl Read + Seek), tags: &mut Tags, warnings: &mut Vec<TagWarning>) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut packets = PacketReader::new(BufReader::new(&mut *file));
+
+    let Some(ident) = packets.next_packet()? else {
+        warnings.push(TagWarning::Truncated("Ogg"));
+        return Ok(());
+    };
+
+    let codec = if ident.starts_with(b"\x01vorbis") && ident.len() >= 16 {
+        Codec::Vorbis { sample_rate: u32::from_le_bytes([ident[12], ident[13], ident[14], ident[15]]) as u64 }
+    } else if ident.starts_with(b"OpusHead") && ident.len() >= 12 {
+        Codec::Opus { pre_skip: u16::from_le_bytes([ident[10], ident[11]]) as u64 }
+    } else {
+        warnings.push(TagWarning::BadBlock { block: "Ogg".to_string(), reason: "неизвестный кодек" });
+        return Ok(());
+    };
+
+    // Второй пакет потока - заголовок с комментариями
+    let Some(comments) = packets.next_packet()? else {
+        warnings.push(TagWarning::Truncated("Ogg"));
+        return Ok(());
+    };
+    let comment_prefix: &[u8] = match codec {
+        Codec::Vorbis { .. } => b"\x03vorbis",
+        Codec::Opus { .. } => b"OpusTags",
+    };
+    match comments.strip_prefix(comment_prefix) {
+        Some(data) => vorbis_comment::parse(data, "Ogg", tags, warnings),
+        None => warnings.push(TagWarning::BadBlock { block: "Ogg".to_string(), reason: "нет заголовка с комментариями" }),
+    }
+
+    let serial = packets.serial.unwrap_or_default();
+    drop(packets);
+
+    // Позиция гранулы последней страницы - это число сэмплов в потоке
+    let Some(granule) = last_granule(file, serial)? else {
+        warnings.push(TagWarning::BadBlock { block: "Ogg".to_string(), reason: "не найдена последняя страница" });
+        return Ok(());
+    };
+
+    tags.duration = match codec {
+        Codec::Vorbis { sample_rate } if sample_rate > 0 => Some(samples_to_seconds(granule, sample_rate)),
+        Codec::Opus { pre_skip } => Some(samples_to_seconds(granule.saturating_sub(pre_skip), OPUS_SAMPLE_RATE)),
+        _ => None,
+    };
+
+    Ok(())
+}
+
+fn last_granule(file: &mut (impl Read + Seek), serial: u32) -> io::Result<Option<u64>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_SEARCH)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    let mut end = tail.len();
+    while let Some(pos) = tail[..end].windows(4).rposition(|window| window == b"OggS") {
+        if let Some(header) = tail.get(pos..pos + PAGE_HEADER_LEN) {
+            let granule = i64::from_le_bytes(header[6..14].try_into().unwrap());
+            let page_serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+            // -1 означает, что на странице не заканчивается ни один пакет
+            if page_serial == serial && granule >= 0 {
+                return Ok(Some(granule as u64));
+            }
+        }
+        end = pos;
+    }
+
+    Ok(None)
+}
+
+// Собирает пакеты первого логического потока из страниц Ogg
+struct PacketReader<R> {
+    reader: R,
+    serial: Option<u32>,
+    ready: VecDeque<Vec<u8>>,
+    partial: Vec<u8>,
+}
+
+impl<R: Read> PacketReader<R> {
+    fn new(reader: R) -> Self {
+        PacketReader { reader, serial: None, ready: VecDeque::new(), partial: Vec::new() }
+    }
+
+    // None - поток закончился или повреждён
+    fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(packet) = self.ready.pop_front() {
+                return Ok(Some(packet));
+            }
+
+            let mut header = [0u8; PAGE_HEADER_LEN];
+            match self.reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            if &header[0..4] != b"OggS" {
+                return Ok(None);
+            }
+
+            let mut lacing = vec![0u8; header[26] as usize];
+            let mut payload = Vec::new();
+            if self.reader.read_exact(&mut lacing).is_err() {
+                return Ok(None);
+            }
+            let payload_len: u64 = lacing.iter().map(|&len| len as u64).sum();
+            self.reader.by_ref().take(payload_len).read_to_end(&mut payload)?;
+            if (payload.len() as u64) < payload_len {
+                return Ok(None);
+            }
+
+            let serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+            if *self.serial.get_or_insert(serial) != serial {
+                continue; // страница другого логического потока
+            }
+
+            // Сегмент короче 255 байт завершает пакет
+            let mut pos = 0;
+            for &len in &lacing {
+                self.partial.extend_from_slice(&payload[pos..pos + len as usize]);
+                pos += len as usize;
+                if len < 255 {
+                    self.ready.push_back(std::mem::take(&mut self.partial));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::read_from;
+
+    // Страница с целыми пакетами; пакет длиной в 255 * n байт продолжается на следующей
+    fn page(serial: u32, granule: i64, packets: &[&[u8]]) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        let mut payload = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+            payload.extend_from_slice(packet);
+        }
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0; 8]); // номер страницы и CRC не проверяются
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        page.extend(payload);
+        page
+    }
+
+    fn comments(prefix: &[u8], comments: &[&str]) -> Vec<u8> {
+        let mut data = prefix.to_vec();
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    fn vorbis_ident(sample_rate: u32) -> Vec<u8> {
+        let mut ident = b"\x01vorbis\0\0\0\0\x02".to_vec();
+        ident.extend_from_slice(&sample_rate.to_le_bytes());
+        ident.extend_from_slice(&[0; 14]);
+        ident
+    }
+
+
+    #[test]
+    fn reads_vorbis_comments_and_duration() {
+        let mut file = page(7, 0, &[&vorbis_ident(44_100)]);
+        file.extend(page(7, 0, &[&comments(b"\x03vorbis", &["TITLE=Песня", "ARTIST=Artist"])]));
+        file.extend(page(7, 44_100 * 100, &[&[0; 300]]));
+        file.extend(page(7, -1, &[&[0; 10]])); // на странице не кончается ни один пакет
+
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.title.as_deref(), Some("Песня"));
+        assert_eq!(tags.duration, Some(100));
+    }
+
+    #[test]
+    fn reads_opus_with_pre_skip_and_long_comment_packet() {
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&[0; 7]);
+        let long_comment = format!("COMMENT={}", "x".repeat(600));
+        let tags_packet = comments(b"OpusTags", &["TITLE=Opus", &long_comment]);
+
+        let mut file = page(1, 0, &[&head]);
+        // Пакет с комментариями разбит на две страницы
+        let (first, second) = tags_packet.split_at(255 * 2);
+        let mut continued = page(1, 0, &[first]);
+        continued.truncate(continued.len() - first.len() - 1);
+        continued[26] -= 1; // без завершающего нулевого сегмента
+        continued.extend_from_slice(first);
+        file.extend(continued);
+        file.extend(page(1, 0, &[second]));
+        // Страница другого логического потока не мешает
+        file.extend(page(2, 999_999_999, &[b"other"]));
+        file.extend(page(1, 48_000 * 3 + 312, &[&[0; 50]]));
+        file.extend(page(2, 999_999_999, &[b"other"]));
+
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.title.as_deref(), Some("Opus"));
+        assert_eq!(tags.comment.map(|comment| comment.len()), Some(600));
+        assert_eq!(tags.duration, Some(3));
+    }
+
+    #[test]
+    fn malformed_streams_are_reported() {
+        let (_, warnings) = read_from(read, b"").unwrap();
+        assert_eq!(warnings, [TagWarning::Truncated("Ogg")]);
+
+        let (_, warnings) = read_from(read, &[0x55; 100]).unwrap();
+        assert_eq!(warnings, [TagWarning::Truncated("Ogg")]);
+
+        let (_, warnings) = read_from(read, &page(1, 0, &[b"\x80theora"])).unwrap();
+        assert_eq!(warnings, [TagWarning::BadBlock { block: "Ogg".to_string(), reason: "неизвестный кодек" }]);
+
+        let (_, warnings) = read_from(read, &page(1, 0, &[&vorbis_ident(44_100)])).unwrap();
+        assert_eq!(warnings, [TagWarning::Truncated("Ogg")]);
+
+        // Заявленный размер страницы больше файла
+        let mut file = page(1, 0, &[&vorbis_ident(44_100)]);
+        file.extend(page(1, 0, &[&[0; 200]]));
+        file.truncate(file.len() - 100);
+        assert_eq!(read_from(read, &file).unwrap().1, [TagWarning::Truncated("Ogg")]);
+
+        let mut file = page(1, 0, &[&vorbis_ident(44_100)]);
+        file.extend(page(1, 0, &[b"\x05setup"]));
+        file.extend(page(1, -1, &[&[0; 10]]));
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert_eq!(tags.duration, Some(0)); // аудио нет, только заголовки
+        assert_eq!(warnings, [TagWarning::BadBlock { block: "Ogg".to_string(), reason: "нет заголовка с комментариями" }]);
+
+        // В хвосте файла нет ни одной страницы
+        let mut file = page(1, 0, &[&vorbis_ident(44_100)]);
+        file.extend(page(1, 0, &[&comments(b"\x03vorbis", &["TITLE=Title"])]));
+        file.extend(vec![0x55; TAIL_SEARCH as usize + 100]);
+        let (tags, warnings) = read_from(read, &file).unwrap();
+        assert_eq!((tags.title.as_deref(), tags.duration), (Some("Title"), None));
+        assert_eq!(warnings, [TagWarning::BadBlock { block: "Ogg".to_string(), reason: "не найдена последняя страница" }]);
+    }
+}
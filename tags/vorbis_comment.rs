@@ -0,0 +1,170 @@
+use super::{parse_position, TagWarning, Tags};
+
+// Блок комментариев Vorbis: используется и во FLAC, и в Ogg Vorbis/Opus.
+// Все числа little-endian, строки - UTF-8 вида "КЛЮЧ=значение".
+pub fn parse(data: &[u8], block: &str, tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    let mut reader = Reader { data, pos: 0 };
+
+    let parsed = (|| {
+        let vendor_len = reader.u32()? as usize;
+        reader.bytes(vendor_len)?;
+
+        let count = reader.u32()?;
+        for _ in 0..count {
+            let len = reader.u32()? as usize;
+            let comment = reader.bytes(len)?;
+            match std::str::from_utf8(comment) {
+                Ok(comment) => apply_comment(comment, tags, warnings),
+                Err(_) => warnings.push(TagWarning::BadText(block.to_string())),
+            }
+        }
+        Some(())
+    })();
+
+    if parsed.is_none() {
+        warnings.push(TagWarning::Truncated("комментарии Vorbis"));
+    }
+}
+
+fn apply_comment(comment: &str, tags: &mut Tags, warnings: &mut Vec<TagWarning>) {
+    let Some((key, value)) = comment.split_once('=') else {
+        warnings.push(TagWarning::BadBlock { block: comment.to_string(), reason: "комментарий без '='" });
+        return;
+    };
+
+    let value = value.trim();
+    if value.is_empty() {
+        return;
+    }
+
+    // Поле может повторяться - берём первое значение
+    match key.to_ascii_uppercase().as_str() {
+        "TITLE" if tags.title.is_none() => tags.title = Some(value.to_string()),
+        "ARTIST" if tags.artist.is_none() => tags.artist = Some(value.to_string()),
+        "ALBUM" if tags.album.is_none() => tags.album = Some(value.to_string()),
+        "ALBUMARTIST" | "ALBUM ARTIST" if tags.album_artist.is_none() => tags.album_artist = Some(value.to_string()),
+        "COMPOSER" if tags.composer.is_none() => tags.composer = Some(value.to_string()),
+        "COMMENT" | "DESCRIPTION" if tags.comment.is_none() => tags.comment = Some(value.to_string()),
+        "MUSICBRAINZ_TRACKID" if tags.mbid.is_none() => tags.mbid = Some(value.to_string()),
+        "GENRE" if tags.genre.is_none() => tags.genre = Some(value.to_string()),
+        "DATE" if tags.year.is_none() => match value.get(0..4).and_then(|year| year.parse().ok()) {
+            Some(year) => tags.year = Some(year),
+            None => warnings.push(TagWarning::BadBlock { block: "DATE".to_string(), reason: "некорректный год" }),
+        },
+        "TRACKNUMBER" if tags.track.is_none() => match parse_position(value) {
+            Some(track) => tags.track = Some(track),
+            None => warnings.push(TagWarning::BadBlock { block: "TRACKNUMBER".to_string(), reason: "некорректный номер трека" }),
+        },
+        "DISCNUMBER" if tags.disc.is_none() => match parse_position(value) {
+            Some(disc) => tags.disc = Some(disc),
+            None => warnings.push(TagWarning::BadBlock { block: "DISCNUMBER".to_string(), reason: "некорректный номер диска" }),
+        },
+        _ => {}
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(comments: &[&str]) -> Vec<u8> {
+        let mut data = 6u32.to_le_bytes().to_vec();
+        data.extend_from_slice(b"vendor");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    fn parse_block(data: &[u8]) -> (Tags, Vec<TagWarning>) {
+        let mut tags = Tags::default();
+        let mut warnings = Vec::new();
+        parse(data, "test", &mut tags, &mut warnings);
+        (tags, warnings)
+    }
+
+    #[test]
+    fn reads_fields_case_insensitively_and_keeps_the_first() {
+        let (tags, warnings) = parse_block(&block(&[
+            "title=Группа крови",
+            "ARTIST=Кино",
+            "Artist=Другой",
+            "ALBUM ARTIST=Various",
+            "DATE=1988-01-05",
+            "TRACKNUMBER=3/12",
+            "DISCNUMBER=1",
+            "GENRE=Rock",
+            "COMMENT=",
+            "DESCRIPTION=Remaster",
+            "REPLAYGAIN_TRACK_GAIN=-6.5 dB",
+        ]));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(tags.title.as_deref(), Some("Группа крови"));
+        assert_eq!(tags.artist.as_deref(), Some("Кино"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Various"));
+        assert_eq!((tags.year, tags.track, tags.disc), (Some(1988), Some(3), Some(1)));
+        assert_eq!(tags.genre.as_deref(), Some("Rock"));
+        assert_eq!(tags.comment.as_deref(), Some("Remaster"));
+    }
+
+    #[test]
+    fn bad_comments_are_reported_and_skipped() {
+        let mut data = block(&["TITLE=ok", "no separator", "DATE=soon", "TRACKNUMBER=x"]);
+        // Ещё один комментарий - не UTF-8
+        let count = u32::from_le_bytes(data[10..14].try_into().unwrap());
+        data[10..14].copy_from_slice(&(count + 1).to_le_bytes());
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0xC3, 0x28]);
+
+        let (tags, warnings) = parse_block(&data);
+        assert_eq!(tags.title.as_deref(), Some("ok"));
+        assert_eq!(warnings, [
+            TagWarning::BadBlock { block: "no separator".to_string(), reason: "комментарий без '='" },
+            TagWarning::BadBlock { block: "DATE".to_string(), reason: "некорректный год" },
+            TagWarning::BadBlock { block: "TRACKNUMBER".to_string(), reason: "некорректный номер трека" },
+            TagWarning::BadText("test".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_block_keeps_what_was_read() {
+        let data = block(&["TITLE=Title", "ARTIST=Artist"]);
+        let (tags, warnings) = parse_block(&data[..data.len() - 3]);
+        assert_eq!(tags.title.as_deref(), Some("Title"));
+        assert_eq!(tags.artist, None);
+        assert_eq!(warnings, [TagWarning::Truncated("комментарии Vorbis")]);
+    }
+
+    #[test]
+    fn huge_lengths_do_not_overflow() {
+        let mut data = u32::MAX.to_le_bytes().to_vec();
+        data.extend_from_slice(b"vendor");
+        assert_eq!(parse_block(&data).1, [TagWarning::Truncated("комментарии Vorbis")]);
+
+        let mut data = block(&[]);
+        data[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse_block(&data).1, [TagWarning::Truncated("комментарии Vorbis")]);
+        assert_eq!(parse_block(&[]).1, [TagWarning::Truncated("комментарии Vorbis")]);
+    }
+}